@@ -3,7 +3,7 @@ The build script has two primary jobs:
 
 1. Do code generation.  Currently, this consists of turning `data/winver.json` into an appropriate `enum`.
 
-2. Tell Cargo to link against Clang.
+2. Tell Cargo to link against Clang.  This uses `LIBCLANG_PATH` if it's set, and `bin/<target>` otherwise.  With the `runtime` feature, libclang is loaded when the tool runs instead, so nothing is linked.
 */
 extern crate itertools;
 extern crate serde;
@@ -111,6 +111,10 @@ impl WinVersion {{
 
     pub const AFTER_LAST: u32 = 0x{guard_const:08x};
 
+    pub const ALL: &'static [WinVersion] = &[
+{all_versions}
+    ];
+
     pub fn from_name(name: &str) -> Option<WinVersion> {{
         match name {{
 {from_names}
@@ -141,6 +145,10 @@ impl WinVersion {{
 
         guard_const = next_ver[vers.last().unwrap()],
 
+        all_versions = vers.iter().cloned()
+            .map(|v| format!("        WinVersion::{},", reverse[&v]))
+            .join("\n"),
+
         from_names = primary.iter().map(|(&k, &v)| (k, v)).chain(aliases.iter().map(|&(k, v)| (k, v)))
             .map(|(k, v)| format!("            \"{}\" => Some(WinVersion::{}),",
                 k, reverse[&v]))
@@ -161,8 +169,14 @@ impl WinVersion {{
 }
 
 fn link_clang() {
-    let manifest_path = get_manifest_dir();
-    let bin_dir = pj!(manifest_path, "bin", get_target());
+    if env::var_os("CARGO_FEATURE_RUNTIME").is_some() {
+        return;
+    }
+
+    let bin_dir = match env::var_os("LIBCLANG_PATH") {
+        Some(path) => PathBuf::from(path),
+        None => pj!(get_manifest_dir(), "bin", get_target())
+    };
 
     println!("cargo:rustc-link-lib=clang");
     println!("cargo:rustc-link-search={}", bin_dir.to_str().unwrap());
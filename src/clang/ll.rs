@@ -329,6 +329,24 @@ pub const CXLinkage_NoLinkage: ::libc::c_uint = 1;
 pub const CXLinkage_Internal: ::libc::c_uint = 2;
 pub const CXLinkage_UniqueExternal: ::libc::c_uint = 3;
 pub const CXLinkage_External: ::libc::c_uint = 4;
+pub type Enum_CX_StorageClass = ::libc::c_uint;
+pub const CX_SC_Invalid: ::libc::c_uint = 0;
+pub const CX_SC_None: ::libc::c_uint = 1;
+pub const CX_SC_Extern: ::libc::c_uint = 2;
+pub const CX_SC_Static: ::libc::c_uint = 3;
+pub const CX_SC_PrivateExtern: ::libc::c_uint = 4;
+pub const CX_SC_OpenCLWorkGroupLocal: ::libc::c_uint = 5;
+pub const CX_SC_Auto: ::libc::c_uint = 6;
+pub const CX_SC_Register: ::libc::c_uint = 7;
+pub type CXEvalResult = *mut ::libc::c_void;
+pub type Enum_CXEvalResultKind = ::libc::c_uint;
+pub const CXEval_Int: ::libc::c_uint = 1;
+pub const CXEval_Float: ::libc::c_uint = 2;
+pub const CXEval_ObjCStrLiteral: ::libc::c_uint = 3;
+pub const CXEval_StrLiteral: ::libc::c_uint = 4;
+pub const CXEval_CFStr: ::libc::c_uint = 5;
+pub const CXEval_Other: ::libc::c_uint = 6;
+pub const CXEval_UnExposed: ::libc::c_uint = 0;
 #[derive(Copy, Clone)] #[repr(C)]
 pub struct Struct_CXPlatformAvailability {
     pub Platform: CXString,
@@ -397,6 +415,7 @@ pub const CXType_IncompleteArray: ::libc::c_uint = 114;
 pub const CXType_VariableArray: ::libc::c_uint = 115;
 pub const CXType_DependentSizedArray: ::libc::c_uint = 116;
 pub const CXType_MemberPointer: ::libc::c_uint = 117;
+pub const CXType_Elaborated: ::libc::c_uint = 119;
 pub type Enum_CXCallingConv = ::libc::c_uint;
 pub const CXCallingConv_Default: ::libc::c_uint = 0;
 pub const CXCallingConv_C: ::libc::c_uint = 1;
@@ -811,6 +830,11 @@ pub const CXIndexOpt_IndexFunctionLocalSymbols: ::libc::c_uint = 2;
 pub const CXIndexOpt_IndexImplicitTemplateInstantiations: ::libc::c_uint = 4;
 pub const CXIndexOpt_SuppressWarnings: ::libc::c_uint = 8;
 pub const CXIndexOpt_SkipParsedBodiesInSession: ::libc::c_uint = 16;
+// With the `runtime` feature, these are replaced by wrappers around functions resolved when libclang is loaded.
+#[cfg(feature = "runtime")]
+pub use super::runtime::functions::*;
+
+#[cfg(not(feature = "runtime"))]
 #[link(name = "clang")]
 extern "C" {
     pub fn clang_getCString(string: CXString) -> *const ::libc::c_char;
@@ -992,6 +1016,17 @@ extern "C" {
     pub fn clang_isPreprocessing(arg1: Enum_CXCursorKind) -> ::libc::c_uint;
     pub fn clang_isUnexposed(arg1: Enum_CXCursorKind) -> ::libc::c_uint;
     pub fn clang_getCursorLinkage(cursor: CXCursor) -> Enum_CXLinkageKind;
+    pub fn clang_Cursor_getStorageClass(arg1: CXCursor) -> Enum_CX_StorageClass;
+    pub fn clang_Cursor_isAnonymous(C: CXCursor) -> ::libc::c_uint;
+    pub fn clang_Cursor_isMacroFunctionLike(C: CXCursor) -> ::libc::c_uint;
+    pub fn clang_Type_getNamedType(T: CXType) -> CXType;
+    pub fn clang_Cursor_Evaluate(C: CXCursor) -> CXEvalResult;
+    pub fn clang_EvalResult_getKind(E: CXEvalResult) -> Enum_CXEvalResultKind;
+    pub fn clang_EvalResult_isUnsignedInt(E: CXEvalResult) -> ::libc::c_uint;
+    pub fn clang_EvalResult_getAsLongLong(E: CXEvalResult) -> ::libc::c_longlong;
+    pub fn clang_EvalResult_getAsUnsigned(E: CXEvalResult) -> ::libc::c_ulonglong;
+    pub fn clang_EvalResult_getAsDouble(E: CXEvalResult) -> ::libc::c_double;
+    pub fn clang_EvalResult_dispose(E: CXEvalResult);
     pub fn clang_getCursorAvailability(cursor: CXCursor) ->
      Enum_CXAvailabilityKind;
     pub fn clang_getCursorPlatformAvailability(cursor: CXCursor,
@@ -8,6 +8,26 @@ use libc;
 use util::{BoolUtil, CheckedInto, ToCStr, TryFrom, TryInto};
 
 pub mod ll;
+pub mod visit;
+#[cfg(feature = "runtime")]
+mod runtime;
+
+pub use self::visit::{Position, Visitor, Walk};
+
+/**
+Makes sure libclang is ready to use.
+
+With the `runtime` feature, this finds and loads libclang (see `runtime::load`).  Otherwise, it was linked in at build time and there is nothing to do.
+*/
+#[cfg(feature = "runtime")]
+pub fn load() -> Result<(), String> {
+    runtime::load()
+}
+
+#[cfg(not(feature = "runtime"))]
+pub fn load() -> Result<(), String> {
+    Ok(())
+}
 
 pub fn version() -> String {
     unsafe {
@@ -15,6 +35,116 @@ pub fn version() -> String {
     }
 }
 
+/**
+Parses the major and minor version out of `version()`, which looks something like `clang version 14.0.0-1ubuntu1`.
+*/
+pub fn version_number() -> Option<(u32, u32)> {
+    parse_version(&version())
+}
+
+fn parse_version(s: &str) -> Option<(u32, u32)> {
+    const MARKER: &'static str = "version ";
+    let rest = match s.find(MARKER) {
+        Some(i) => &s[i + MARKER.len()..],
+        None => return None
+    };
+    let mut parts = rest.split(|c: char| !c.is_digit(10)).map(|p| p.parse::<u32>().ok());
+    match (parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor))) => Some((major, minor)),
+        (Some(Some(major)), _) => Some((major, 0)),
+        _ => None
+    }
+}
+
+/**
+Which of the newer libclang APIs can be used.
+
+Each of these replaces a heuristic, which is still used when the API isn't available.  An API is only used if the library is new enough for it to behave as expected *and* (when libclang is loaded at runtime) actually exports it.
+*/
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Capabilities {
+    /// `clang_Cursor_isAnonymous`.  Before 9.0, this only recognised anonymous members, not unnamed types in general.
+    pub is_anonymous: bool,
+    /// `clang_Type_getNamedType` (3.9).
+    pub named_type: bool,
+    /// `clang_Cursor_isMacroFunctionLike` (3.9).
+    pub macro_function_like: bool,
+    /// `clang_Cursor_Evaluate`, along with everything needed to read integers out of the result (5.0).
+    pub evaluate: bool,
+    /// `clang_Cursor_getVarDeclInitializer` (12.0).  This is only ever used when libclang is loaded at runtime.
+    pub var_decl_initializer: bool,
+}
+
+impl Capabilities {
+    fn detect() -> Capabilities {
+        let version = version_number().unwrap_or((0, 0));
+        let has = |min: (u32, u32), fns: &[&str]| version >= min && fns.iter().all(|f| has_function(f));
+
+        Capabilities {
+            is_anonymous: has((9, 0), &["clang_Cursor_isAnonymous"]),
+            named_type: has((3, 9), &["clang_Type_getNamedType"]),
+            macro_function_like: has((3, 9), &["clang_Cursor_isMacroFunctionLike"]),
+            evaluate: has((5, 0), &[
+                "clang_Cursor_Evaluate",
+                "clang_EvalResult_getKind",
+                "clang_EvalResult_isUnsignedInt",
+                "clang_EvalResult_getAsLongLong",
+                "clang_EvalResult_getAsUnsigned",
+                "clang_EvalResult_getAsDouble",
+                "clang_EvalResult_dispose",
+            ]),
+            var_decl_initializer: cfg!(feature = "runtime")
+                && has((12, 0), &["clang_Cursor_getVarDeclInitializer"]),
+        }
+    }
+}
+
+lazy_static! {
+    static ref CAPABILITIES: Capabilities = {
+        let caps = Capabilities::detect();
+        info!("libclang capabilities: {:?}", caps);
+        caps
+    };
+}
+
+/**
+Returns which of the newer libclang APIs can be used.
+
+This must not be called before `load`.
+*/
+pub fn capabilities() -> Capabilities {
+    *CAPABILITIES
+}
+
+/**
+Gets the initialiser of a variable declaration straight from libclang, if it can.
+
+`clang_Cursor_getVarDeclInitializer` only appeared in libclang 12, so it's only used when libclang is loaded at runtime; linking against it would raise the minimum version for everyone.
+*/
+#[cfg(feature = "runtime")]
+unsafe fn var_decl_initializer_ll(cur: ll::CXCursor) -> Option<ll::CXCursor> {
+    if capabilities().var_decl_initializer {
+        Some(ll::clang_Cursor_getVarDeclInitializer(cur))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(feature = "runtime"))]
+unsafe fn var_decl_initializer_ll(_: ll::CXCursor) -> Option<ll::CXCursor> {
+    None
+}
+
+#[cfg(feature = "runtime")]
+fn has_function(name: &str) -> bool {
+    runtime::is_available(name)
+}
+
+#[cfg(not(feature = "runtime"))]
+fn has_function(_: &str) -> bool {
+    true
+}
+
 // This is so the rc type can be switched out later.
 fn rc<T>(v: T) -> Rc<T> {
     Rc::new(v)
@@ -51,7 +181,7 @@ ext_impl! { Rc<Index> as RcIndexExt {
         let clang_command_line_args = clang_command_line_args
             .iter().map(|s| s.to_c_str()).collect::<Vec<_>>()
             .iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
-        let mut unsaved_files = unsaved_files.iter_mut().map(|e| e.0).collect::<Vec<_>>();
+        let mut unsaved_files = unsaved_files.iter_mut().map(|e| e.raw).collect::<Vec<_>>();
         let ptr: ll::CXTranslationUnit = unsafe { ::std::mem::uninitialized() };
         unsafe {
             ll::clang_createTranslationUnitFromSourceFile(
@@ -86,7 +216,7 @@ ext_impl! { Rc<Index> as RcIndexExt {
             let command_line_args = command_line_args
                 .iter().map(|s| s.to_c_str()).collect::<Vec<_>>()
                 .iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
-            let mut unsaved_files = unsaved_files.iter().map(|e| e.0).collect::<Vec<_>>();
+            let mut unsaved_files = unsaved_files.iter().map(|e| e.raw).collect::<Vec<_>>();
             let mut tu_ptr = ::std::ptr::null_mut();
             let err = ll::clang_parseTranslationUnit2(
                 self.ptr,
@@ -159,6 +289,16 @@ impl TranslationUnit {
     pub fn tokenize(&self) -> Tokens {
         self.cursor().tokenize()
     }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        unsafe {
+            let tu = TranslationUnit::from_ll_cached(self.1);
+            let n = ll::clang_getNumDiagnostics(self.1);
+            (0..n)
+                .map(|i| Diagnostic::from_ll(tu.clone(), ll::clang_getDiagnostic(self.1, i)))
+                .collect()
+        }
+    }
 }
 
 impl Drop for TranslationUnit {
@@ -181,7 +321,38 @@ bitflags! {
     }
 }
 
-pub struct UnsavedFile(pub ll::Struct_CXUnsavedFile);
+/**
+A file whose contents are given directly, rather than read from disk.
+
+This can either replace a file that exists, or provide one that doesn't.
+*/
+pub struct UnsavedFile {
+    raw: ll::Struct_CXUnsavedFile,
+
+    // These own what `raw` points to.  Their heap buffers don't move when the `UnsavedFile` does.
+    filename: ::std::ffi::CString,
+    contents: Vec<u8>,
+}
+
+impl UnsavedFile {
+    pub fn new(filename: &str, contents: &str) -> UnsavedFile {
+        let filename = ::std::ffi::CString::new(filename).expect("unsaved file name without NULs");
+        let contents = contents.as_bytes().to_vec();
+        UnsavedFile {
+            raw: ll::Struct_CXUnsavedFile {
+                Filename: filename.as_ptr(),
+                Contents: contents.as_ptr() as *const libc::c_char,
+                Length: contents.len() as libc::c_ulong,
+            },
+            filename: filename,
+            contents: contents,
+        }
+    }
+
+    pub fn filename(&self) -> &str {
+        self.filename.to_str().expect("unsaved file name is UTF-8")
+    }
+}
 
 pub struct IndexAction(pub ll::CXIndexAction);
 
@@ -353,6 +524,32 @@ impl Cursor {
         }
     }
 
+    /// The attributes attached to this declaration.
+    pub fn attributes(&self) -> Vec<Cursor> {
+        let mut v = vec![];
+        self.visit_children(|cur, _parent| {
+            if cur.is_attribute() {
+                v.push(cur);
+            }
+            VisitAction::Continue
+        });
+        v
+    }
+
+    /// Whether this declaration is available, deprecated, *etc.* in the current translation unit.
+    pub fn availability(&self) -> Availability {
+        unsafe {
+            ll::clang_getCursorAvailability(self.1).into()
+        }
+    }
+
+    /// The first paragraph of the documentation comment attached to this cursor, if any.
+    pub fn brief_comment(&self) -> Option<String> {
+        unsafe {
+            cxstring_to_string_opt(ll::clang_Cursor_getBriefCommentText(self.1))
+        }
+    }
+
     pub fn children(&self) -> Vec<Cursor> {
         let mut v = vec![];
         self.visit_children(|decl, _parent| {
@@ -380,12 +577,113 @@ impl Cursor {
         }
     }
 
+    /**
+    Evaluates this expression (or the initialiser of this variable) as a numeric constant.
+
+    Returns `None` if it isn't one, or if libclang can't evaluate expressions.
+    */
+    pub fn evaluate(&self) -> Option<EvalResult> {
+        if !capabilities().evaluate {
+            return None;
+        }
+
+        unsafe {
+            let res = ll::clang_Cursor_Evaluate(self.1);
+            if res.is_null() {
+                return None;
+            }
+            let value = match ll::clang_EvalResult_getKind(res) {
+                ll::CXEval_Int => if ll::clang_EvalResult_isUnsignedInt(res) != 0 {
+                    Some(EvalResult::UInt(ll::clang_EvalResult_getAsUnsigned(res) as u64))
+                } else {
+                    Some(EvalResult::Int(ll::clang_EvalResult_getAsLongLong(res) as i64))
+                },
+                ll::CXEval_Float => Some(EvalResult::Float(ll::clang_EvalResult_getAsDouble(res) as f64)),
+                _ => None
+            };
+            ll::clang_EvalResult_dispose(res);
+            value
+        }
+    }
+
+    pub fn extent(&self) -> Option<SourceRange> {
+        unsafe {
+            SourceRange::from_ll(self.0.clone(), ll::clang_getCursorExtent(self.1))
+        }
+    }
+
+    /**
+    Returns `true` if this declaration has no name of its own.
+
+    Newer versions of libclang give unnamed declarations a descriptive spelling (*e.g.* `struct (unnamed at foo.h:3:5)`), so the spelling is only used when `clang_Cursor_isAnonymous` can't be.
+    */
+    pub fn is_anonymous(&self) -> bool {
+        if capabilities().is_anonymous {
+            unsafe {
+                ll::clang_Cursor_isAnonymous(self.1) != 0
+            }
+        } else {
+            self.spelling() == ""
+        }
+    }
+
+    /**
+    Returns `true` if this cursor is an attribute.
+
+    This doesn't go through `kind`, since libclang knows of many more attribute kinds than `CursorKind` does.
+    */
+    pub fn is_attribute(&self) -> bool {
+        unsafe {
+            ll::clang_isAttribute(ll::clang_getCursorKind(self.1)) != 0
+        }
+    }
+
     pub fn is_definition(&self) -> bool {
         unsafe {
             ll::clang_isCursorDefinition(self.1) != 0
         }
     }
 
+    /**
+    Returns `true` if this declaration is marked `__declspec(dllimport)`.
+
+    Older versions of libclang don't expose the attribute, so this falls back on its tokens.
+    */
+    pub fn is_dllimport(&self) -> bool {
+        const CXCURSOR_DLLIMPORT: ll::Enum_CXCursorKind = 419;
+        self.attributes().into_iter().any(|attr| unsafe {
+            ll::clang_getCursorKind(attr.1) == CXCURSOR_DLLIMPORT
+                || attr.tokenize().into_iter().any(|tok| tok.spelling() == "dllimport")
+        })
+    }
+
+    /**
+    Returns `true` if this is the definition of a function-like macro.
+
+    Without `clang_Cursor_isMacroFunctionLike`, this checks whether the macro's name is immediately followed by a `(`.
+    */
+    pub fn is_macro_function_like(&self) -> bool {
+        if capabilities().macro_function_like {
+            return unsafe {
+                ll::clang_Cursor_isMacroFunctionLike(self.1) != 0
+            };
+        }
+
+        let toks = self.tokenize();
+        match (toks.get(0), toks.get(1)) {
+            (Some(name_tok), Some(next_tok)) => {
+                match (name_tok.extent(), next_tok.extent()) {
+                    (Some(name_ext), Some(next_ext)) => {
+                        next_tok.spelling() == "("
+                            && name_ext.end().column() + 1 == next_ext.end().column()
+                    },
+                    _ => false
+                }
+            },
+            _ => false
+        }
+    }
+
     pub fn is_null(&self) -> bool {
         unsafe {
             ll::clang_Cursor_isNull(self.1) != 0
@@ -404,18 +702,125 @@ impl Cursor {
         }
     }
 
+    pub fn linkage(&self) -> Linkage {
+        unsafe {
+            ll::clang_getCursorLinkage(self.1).into()
+        }
+    }
+
     pub fn location(&self) -> SourceLocation {
         unsafe {
             SourceLocation::from_ll(self.0.clone(), ll::clang_getCursorLocation(self.1))
         }
     }
 
+    /**
+    Returns the spelling of the operator for a `UnaryOperator`, `BinaryOperator` or `CompoundAssignOperator`.
+
+    libclang doesn't expose operators, so they have to be recovered from the tokens.  A binary operator is the first token after the left operand.  A unary operator is the first token, unless that's part of the operand, in which case it's postfix and comes after the operand.
+    */
+    pub fn operator_spelling(&self) -> Option<String> {
+        let operand = match self.children().into_iter().next().and_then(|cur| cur.extent()) {
+            Some(extent) => extent,
+            None => return None
+        };
+        let (_, _, _, operand_start) = operand.start().file_location();
+        let (_, _, _, operand_end) = operand.end().file_location();
+
+        let toks = self.tokenize();
+        let first = match toks.get(0) {
+            Some(tok) => tok,
+            None => return None
+        };
+
+        if self.kind() == CursorKind::UnaryOperator && first.location().file_location().3 < operand_start {
+            return Some(first.spelling());
+        }
+
+        toks.into_iter()
+            .find(|tok| tok.location().file_location().3 >= operand_end)
+            .map(|tok| tok.spelling())
+    }
+
+    /**
+    The availability of this declaration across every platform, including any deprecation or unavailability messages.
+    */
+    pub fn platform_availability(&self) -> PlatformAvailability {
+        const MAX_PLATFORMS: usize = 16;
+        unsafe {
+            let mut always_deprecated = 0;
+            let mut deprecated_message = ::std::mem::zeroed();
+            let mut always_unavailable = 0;
+            let mut unavailable_message = ::std::mem::zeroed();
+            let mut avail: [ll::CXPlatformAvailability; MAX_PLATFORMS] = ::std::mem::zeroed();
+
+            let n = ll::clang_getCursorPlatformAvailability(
+                self.1,
+                &mut always_deprecated,
+                &mut deprecated_message,
+                &mut always_unavailable,
+                &mut unavailable_message,
+                avail.as_mut_ptr(),
+                MAX_PLATFORMS as libc::c_int);
+            let n = ::std::cmp::min(n.max(0) as usize, MAX_PLATFORMS);
+
+            let platforms = avail[..n].iter_mut()
+                .map(|pa| {
+                    let platform = PlatformVersions {
+                        platform: cxstring_peek(&pa.Platform).unwrap_or(String::new()),
+                        introduced: Version::from_ll(pa.Introduced),
+                        deprecated: Version::from_ll(pa.Deprecated),
+                        obsoleted: Version::from_ll(pa.Obsoleted),
+                        unavailable: pa.Unavailable != 0,
+                        message: cxstring_peek(&pa.Message),
+                    };
+                    ll::clang_disposeCXPlatformAvailability(pa);
+                    platform
+                })
+                .collect();
+
+            PlatformAvailability {
+                always_deprecated: always_deprecated != 0,
+                deprecated_message: cxstring_to_string_opt(deprecated_message).and_then(non_empty),
+                always_unavailable: always_unavailable != 0,
+                unavailable_message: cxstring_to_string_opt(unavailable_message).and_then(non_empty),
+                platforms: platforms,
+            }
+        }
+    }
+
+    /// The parsed form of the documentation comment attached to this cursor, if any.
+    pub fn parsed_comment(&self) -> Option<Comment> {
+        unsafe {
+            Comment::from_ll(self.0.clone(), ll::clang_Cursor_getParsedComment(self.1))
+        }
+    }
+
+    /// The documentation comment attached to this cursor, exactly as written, if any.
+    pub fn raw_comment(&self) -> Option<String> {
+        unsafe {
+            cxstring_to_string_opt(ll::clang_Cursor_getRawCommentText(self.1))
+        }
+    }
+
+    pub fn referenced(&self) -> Option<Cursor> {
+        unsafe {
+            Cursor::from_ll(ll::clang_getCursorReferenced(self.1))
+        }
+    }
+
     pub fn spelling(&self) -> String {
         unsafe {
             cxstring_to_string(ll::clang_getCursorSpelling(self.1))
         }
     }
 
+    pub fn storage_class(&self) -> StorageClass {
+        unsafe {
+            ll::clang_Cursor_getStorageClass(self.1).into()
+        }
+    }
+
     pub fn tokenize(&self) -> Tokens {
         unsafe {
             let range = ll::clang_getCursorExtent(self.1);
@@ -438,6 +843,52 @@ impl Cursor {
         }
     }
 
+    /**
+    The initialiser of a variable declaration, if it has one.
+
+    Without `clang_Cursor_getVarDeclInitializer`, this is the first child expression after the declaration's `=`.  Expressions before it (such as the size of an array) are part of the type.
+    */
+    pub fn var_decl_initializer(&self) -> Option<Cursor> {
+        if let Some(init) = unsafe { var_decl_initializer_ll(self.1) } {
+            return Cursor::from_ll(init);
+        }
+
+        let eq_offset = {
+            let mut depth = 0;
+            let mut eq_offset = None;
+            let toks = self.tokenize();
+            for tok in &toks {
+                match &*tok.spelling() {
+                    "(" | "[" | "{" => depth += 1,
+                    ")" | "]" | "}" => depth -= 1,
+                    "=" if depth == 0 => {
+                        eq_offset = Some(tok.location().file_location().3);
+                        break;
+                    },
+                    _ => ()
+                }
+            }
+            match eq_offset {
+                Some(off) => off,
+                None => return None
+            }
+        };
+
+        let mut init = None;
+        self.visit_children(|cur, _parent| {
+            let is_expr = unsafe { ll::clang_isExpression(ll::clang_getCursorKind(cur.1)) != 0 };
+            let after_eq = cur.extent()
+                .map(|ext| ext.start().file_location().3 > eq_offset)
+                .unwrap_or(false);
+            if is_expr && after_eq {
+                init = Some(cur);
+                return VisitAction::Break;
+            }
+            VisitAction::Continue
+        });
+        init
+    }
+
     pub fn visit_children<F>(&self, mut f: F) -> VisitTermination
     where F: FnMut(Cursor, Option<Cursor>) -> VisitAction {
         extern "C" fn thunk<F>(
@@ -686,6 +1137,12 @@ impl CursorKind {
     #[allow(non_upper_case_globals)] pub const LastPreprocessing: CursorKind = /* 503 */ CursorKind::InclusionDirective;
     #[allow(non_upper_case_globals)] pub const FirstExtraDecl: CursorKind = /* 600 */ CursorKind::ModuleImportDecl;
     #[allow(non_upper_case_globals)] pub const LastExtraDecl: CursorKind = /* 600 */ CursorKind::ModuleImportDecl;
+
+    pub fn is_expression(self) -> bool {
+        unsafe {
+            ll::clang_isExpression(self.into()) != 0
+        }
+    }
 }
 
 pub struct SourceLocation(Rc<TranslationUnit>, ll::CXSourceLocation);
@@ -831,6 +1288,35 @@ pub unsafe fn cxstring_to_string(cxs: ll::CXString) -> String {
     str
 }
 
+/**
+Like `cxstring_to_string`, except that a null string (which libclang uses to mean "nothing") becomes `None`.
+*/
+pub unsafe fn cxstring_to_string_opt(cxs: ll::CXString) -> Option<String> {
+    if ll::clang_getCString(cxs).is_null() {
+        ll::clang_disposeString(cxs);
+        None
+    } else {
+        Some(cxstring_to_string(cxs))
+    }
+}
+
+/**
+Copies a string owned by some other libclang structure, without disposing of it.
+*/
+unsafe fn cxstring_peek(cxs: &ll::CXString) -> Option<String> {
+    use std::ffi::CStr;
+    let ptr = ll::clang_getCString(*cxs);
+    if ptr.is_null() {
+        None
+    } else {
+        non_empty(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
+}
+
+fn non_empty(s: String) -> Option<String> {
+    if s == "" { None } else { Some(s) }
+}
+
 pub struct Token(Rc<TranslationUnit>, ll::CXToken);
 
 impl Token {
@@ -857,6 +1343,7 @@ impl Token {
     }
 }
 
+#[derive(Clone)]
 pub struct Type(Rc<TranslationUnit>, ll::CXType);
 
 impl Type {
@@ -927,6 +1414,33 @@ impl Type {
         self.1.kind.try_into().expect("valid type kind for type")
     }
 
+    /**
+    Returns the type an elaborated type (*e.g.* `struct foo`, or from libclang 16 on, almost any type name) refers to.
+
+    Without `clang_Type_getNamedType`, this falls back on the canonical type, which loses any typedefs along the way.
+    */
+    pub fn named(&self) -> Type {
+        if capabilities().named_type {
+            unsafe {
+                Type::from_ll(self.0.clone(), ll::clang_Type_getNamedType(self.1))
+            }
+        } else {
+            self.canonical()
+        }
+    }
+
+    /**
+    Returns the offset of the named field, *in bits*.
+
+    `field` must name a direct member of the record (or a member of an anonymous record nested inside it).
+    */
+    pub fn offset_of(&self, field: &str) -> Result<usize, LayoutError> {
+        unsafe {
+            let field = field.to_c_str();
+            layout_result(ll::clang_Type_getOffsetOf(self.1, field.as_ptr()))
+        }
+    }
+
     pub fn pointee(&self) -> Type {
         unsafe {
             Type::from_ll(self.0.clone(), ll::clang_getPointeeType(self.1))
@@ -950,6 +1464,34 @@ impl Type {
             cxstring_to_string(ll::clang_getTypeSpelling(self.1))
         }
     }
+
+    /**
+    Like `size_of`, except that it reports *why* clang couldn't work out the size, rather than panicking.
+    */
+    pub fn try_size_of(&self) -> Result<usize, LayoutError> {
+        unsafe {
+            layout_result(ll::clang_Type_getSizeOf(self.1))
+        }
+    }
+}
+
+fn layout_result(r: libc::c_longlong) -> Result<usize, LayoutError> {
+    if r < 0 {
+        Err((r as ll::Enum_CXTypeLayoutError).try_into().unwrap_or(LayoutError::Invalid))
+    } else {
+        Ok((r as i64).checked_into())
+    }
+}
+
+c_enum! {
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+    pub enum LayoutError: ll::Enum_CXTypeLayoutError {
+        Invalid = -1,
+        Incomplete = -2,
+        Dependent = -3,
+        NotConstantSize = -4,
+        InvalidFieldName = -5,
+    }
 }
 
 c_enum! {
@@ -1025,6 +1567,7 @@ c_enum! {
         VariableArray = 115,
         DependentSizedArray = 116,
         MemberPointer = 117,
+        Elaborated = 119,
     }
 }
 
@@ -1058,3 +1601,490 @@ impl SourceRange {
         }
     }
 }
+
+/**
+The value of a constant expression, as worked out by libclang.
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EvalResult {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Linkage {
+    Invalid,
+    NoLinkage,
+    Internal,
+    UniqueExternal,
+    External,
+}
+
+impl From<libc::c_uint> for Linkage {
+    fn from(v: libc::c_uint) -> Linkage {
+        use self::Linkage::*;
+        match v {
+            ll::CXLinkage_NoLinkage => NoLinkage,
+            ll::CXLinkage_Internal => Internal,
+            ll::CXLinkage_UniqueExternal => UniqueExternal,
+            ll::CXLinkage_External => External,
+            _ => Invalid
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum StorageClass {
+    Invalid,
+    None,
+    Extern,
+    Static,
+    PrivateExtern,
+    OpenCLWorkGroupLocal,
+    Auto,
+    Register,
+}
+
+impl From<libc::c_uint> for StorageClass {
+    fn from(v: libc::c_uint) -> StorageClass {
+        // Not a glob import, since `None` would shadow `Option::None`.
+        use self::StorageClass as SC;
+        match v {
+            ll::CX_SC_None => SC::None,
+            ll::CX_SC_Extern => SC::Extern,
+            ll::CX_SC_Static => SC::Static,
+            ll::CX_SC_PrivateExtern => SC::PrivateExtern,
+            ll::CX_SC_OpenCLWorkGroupLocal => SC::OpenCLWorkGroupLocal,
+            ll::CX_SC_Auto => SC::Auto,
+            ll::CX_SC_Register => SC::Register,
+            _ => SC::Invalid
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Availability {
+    Available,
+    Deprecated,
+    NotAvailable,
+    NotAccessible,
+}
+
+impl From<libc::c_uint> for Availability {
+    fn from(v: libc::c_uint) -> Availability {
+        use self::Availability::*;
+        match v {
+            ll::CXAvailability_Deprecated => Deprecated,
+            ll::CXAvailability_NotAvailable => NotAvailable,
+            ll::CXAvailability_NotAccessible => NotAccessible,
+            _ => Available
+        }
+    }
+}
+
+/**
+The result of `Cursor::platform_availability`.
+*/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PlatformAvailability {
+    /// Is the declaration deprecated on every platform?
+    pub always_deprecated: bool,
+    pub deprecated_message: Option<String>,
+
+    /// Is the declaration unavailable on every platform?
+    pub always_unavailable: bool,
+    pub unavailable_message: Option<String>,
+
+    /// Per-platform availability, from `__attribute__((availability(...)))`.
+    pub platforms: Vec<PlatformVersions>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PlatformVersions {
+    pub platform: String,
+    pub introduced: Option<Version>,
+    pub deprecated: Option<Version>,
+    pub obsoleted: Option<Version>,
+    pub unavailable: bool,
+    pub message: Option<String>,
+}
+
+/**
+A version number, as used by availability attributes.  Missing components are `None`.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: Option<u32>,
+    pub subminor: Option<u32>,
+}
+
+impl Version {
+    fn from_ll(v: ll::CXVersion) -> Option<Version> {
+        fn component(c: libc::c_int) -> Option<u32> {
+            if c < 0 { None } else { Some(c as u32) }
+        }
+        component(v.Major).map(|major| Version {
+            major: major,
+            minor: component(v.Minor),
+            subminor: component(v.Subminor),
+        })
+    }
+}
+
+impl_Display! {
+    for Version, (s, f) {
+        try!(write!(f, "{}", s.major));
+        if let Some(minor) = s.minor { try!(write!(f, ".{}", minor)); }
+        if let Some(subminor) = s.subminor { try!(write!(f, ".{}", subminor)); }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum DiagnosticSeverity {
+    Ignored,
+    Note,
+    Warning,
+    Error,
+    Fatal,
+}
+
+impl From<libc::c_uint> for DiagnosticSeverity {
+    fn from(v: libc::c_uint) -> DiagnosticSeverity {
+        use self::DiagnosticSeverity::*;
+        match v {
+            ll::CXDiagnostic_Ignored => Ignored,
+            ll::CXDiagnostic_Note => Note,
+            ll::CXDiagnostic_Warning => Warning,
+            ll::CXDiagnostic_Error => Error,
+            ll::CXDiagnostic_Fatal => Fatal,
+            // Err on the side of caution.
+            _ => Fatal
+        }
+    }
+}
+
+impl_Display! {
+    for DiagnosticSeverity, (s, f) {
+        use self::DiagnosticSeverity::*;
+        write!(f, "{}", match *s {
+            Ignored => "ignored",
+            Note => "note",
+            Warning => "warning",
+            Error => "error",
+            Fatal => "fatal error",
+        })
+    }
+}
+
+pub struct Diagnostic(Rc<TranslationUnit>, ll::CXDiagnostic);
+
+impl Diagnostic {
+    fn from_ll(tu: Rc<TranslationUnit>, diag: ll::CXDiagnostic) -> Diagnostic {
+        assert!(!diag.is_null());
+        Diagnostic(tu, diag)
+    }
+
+    /// The name of the category this diagnostic belongs to (*e.g.* "Semantic Issue").
+    pub fn category(&self) -> String {
+        unsafe {
+            cxstring_to_string(ll::clang_getDiagnosticCategoryText(self.1))
+        }
+    }
+
+    /// The fix-its suggested by Clang for this diagnostic.
+    pub fn fix_its(&self) -> Vec<FixIt> {
+        unsafe {
+            let n = ll::clang_getDiagnosticNumFixIts(self.1);
+            (0..n)
+                .map(|i| {
+                    let mut range = ::std::mem::zeroed();
+                    let replacement = cxstring_to_string(ll::clang_getDiagnosticFixIt(self.1, i, &mut range));
+                    FixIt {
+                        range: SourceRange::from_ll(self.0.clone(), range),
+                        replacement: replacement,
+                    }
+                })
+                .collect()
+        }
+    }
+
+    /// Formats this diagnostic the same way Clang itself would.
+    pub fn format(&self) -> String {
+        unsafe {
+            cxstring_to_string(ll::clang_formatDiagnostic(self.1, ll::clang_defaultDiagnosticDisplayOptions()))
+        }
+    }
+
+    pub fn location(&self) -> SourceLocation {
+        unsafe {
+            SourceLocation::from_ll(self.0.clone(), ll::clang_getDiagnosticLocation(self.1))
+        }
+    }
+
+    /// The command-line option which controls this diagnostic (*e.g.* `-Wunused-variable`), if any.
+    pub fn option(&self) -> Option<String> {
+        unsafe {
+            let s = cxstring_to_string(ll::clang_getDiagnosticOption(self.1, ::std::ptr::null_mut()));
+            if s.len() == 0 { None } else { Some(s) }
+        }
+    }
+
+    pub fn ranges(&self) -> Vec<SourceRange> {
+        unsafe {
+            let n = ll::clang_getDiagnosticNumRanges(self.1);
+            (0..n)
+                .filter_map(|i| SourceRange::from_ll(self.0.clone(), ll::clang_getDiagnosticRange(self.1, i)))
+                .collect()
+        }
+    }
+
+    pub fn severity(&self) -> DiagnosticSeverity {
+        unsafe {
+            ll::clang_getDiagnosticSeverity(self.1).into()
+        }
+    }
+
+    /// The text of this diagnostic.
+    pub fn spelling(&self) -> String {
+        unsafe {
+            cxstring_to_string(ll::clang_getDiagnosticSpelling(self.1))
+        }
+    }
+}
+
+impl Drop for Diagnostic {
+    fn drop(&mut self) {
+        unsafe { ll::clang_disposeDiagnostic(self.1) }
+    }
+}
+
+impl_Display! {
+    for Diagnostic, (s, f) {
+        try!(write!(f, "{}: {}: {}", s.location().display_short(), s.severity(), s.spelling()));
+        if let Some(option) = s.option() {
+            try!(write!(f, " [{}]", option));
+        }
+        Ok(())
+    }
+}
+
+/**
+A suggested fix for a diagnostic: replace the source in `range` with `replacement`.
+*/
+pub struct FixIt {
+    pub range: Option<SourceRange>,
+    pub replacement: String,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CommentKind {
+    Null,
+    Text,
+    InlineCommand,
+    HtmlStartTag,
+    HtmlEndTag,
+    Paragraph,
+    BlockCommand,
+    ParamCommand,
+    TParamCommand,
+    VerbatimBlockCommand,
+    VerbatimBlockLine,
+    VerbatimLine,
+    FullComment,
+}
+
+impl From<libc::c_uint> for CommentKind {
+    fn from(v: libc::c_uint) -> CommentKind {
+        use self::CommentKind::*;
+        match v {
+            ll::CXComment_Text => Text,
+            ll::CXComment_InlineCommand => InlineCommand,
+            ll::CXComment_HTMLStartTag => HtmlStartTag,
+            ll::CXComment_HTMLEndTag => HtmlEndTag,
+            ll::CXComment_Paragraph => Paragraph,
+            ll::CXComment_BlockCommand => BlockCommand,
+            ll::CXComment_ParamCommand => ParamCommand,
+            ll::CXComment_TParamCommand => TParamCommand,
+            ll::CXComment_VerbatimBlockCommand => VerbatimBlockCommand,
+            ll::CXComment_VerbatimBlockLine => VerbatimBlockLine,
+            ll::CXComment_VerbatimLine => VerbatimLine,
+            ll::CXComment_FullComment => FullComment,
+            _ => Null
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum InlineCommandRenderKind {
+    Normal,
+    Bold,
+    Monospaced,
+    Emphasized,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ParamPassDirection {
+    In,
+    Out,
+    InOut,
+}
+
+/**
+A node in a parsed documentation comment.
+
+Which methods make sense depends on the `kind` of the node; the others return `None` or nothing.
+*/
+#[derive(Clone)]
+pub struct Comment(Rc<TranslationUnit>, ll::CXComment);
+
+impl Comment {
+    fn from_ll(tu: Rc<TranslationUnit>, comment: ll::CXComment) -> Option<Comment> {
+        let comment = Comment(tu, comment);
+        match comment.kind() {
+            CommentKind::Null => None,
+            _ => Some(comment)
+        }
+    }
+
+    /// The arguments of an inline or block command.
+    pub fn args(&self) -> Vec<String> {
+        use self::CommentKind::*;
+        unsafe {
+            match self.kind() {
+                InlineCommand => (0..ll::clang_InlineCommandComment_getNumArgs(self.1))
+                    .map(|i| cxstring_to_string(ll::clang_InlineCommandComment_getArgText(self.1, i)))
+                    .collect(),
+                BlockCommand | ParamCommand | VerbatimBlockCommand => (0..ll::clang_BlockCommandComment_getNumArgs(self.1))
+                    .map(|i| cxstring_to_string(ll::clang_BlockCommandComment_getArgText(self.1, i)))
+                    .collect(),
+                _ => vec![]
+            }
+        }
+    }
+
+    pub fn children(&self) -> Vec<Comment> {
+        unsafe {
+            (0..ll::clang_Comment_getNumChildren(self.1))
+                .filter_map(|i| Comment::from_ll(self.0.clone(), ll::clang_Comment_getChild(self.1, i)))
+                .collect()
+        }
+    }
+
+    /// The name of an inline or block command (*e.g.* `brief` or `return`).
+    pub fn command_name(&self) -> Option<String> {
+        use self::CommentKind::*;
+        unsafe {
+            match self.kind() {
+                InlineCommand => Some(cxstring_to_string(ll::clang_InlineCommandComment_getCommandName(self.1))),
+                BlockCommand | ParamCommand | TParamCommand | VerbatimBlockCommand
+                => Some(cxstring_to_string(ll::clang_BlockCommandComment_getCommandName(self.1))),
+                _ => None
+            }
+        }
+    }
+
+    pub fn has_trailing_newline(&self) -> bool {
+        unsafe {
+            ll::clang_InlineContentComment_hasTrailingNewline(self.1) != 0
+        }
+    }
+
+    /// Returns `true` if this is a text or paragraph node containing only whitespace.
+    pub fn is_whitespace(&self) -> bool {
+        unsafe {
+            ll::clang_Comment_isWhitespace(self.1) != 0
+        }
+    }
+
+    pub fn kind(&self) -> CommentKind {
+        unsafe {
+            ll::clang_Comment_getKind(self.1).into()
+        }
+    }
+
+    /// The paragraph of a block or parameter command.
+    pub fn paragraph(&self) -> Option<Comment> {
+        use self::CommentKind::*;
+        match self.kind() {
+            BlockCommand | ParamCommand | TParamCommand => unsafe {
+                Comment::from_ll(self.0.clone(), ll::clang_BlockCommandComment_getParagraph(self.1))
+            },
+            _ => None
+        }
+    }
+
+    /// The direction of a parameter command, if it was given explicitly.
+    pub fn param_direction(&self) -> Option<ParamPassDirection> {
+        if self.kind() != CommentKind::ParamCommand {
+            return None;
+        }
+        unsafe {
+            if ll::clang_ParamCommandComment_isDirectionExplicit(self.1) == 0 {
+                return None;
+            }
+            match ll::clang_ParamCommandComment_getDirection(self.1) {
+                ll::CXCommentParamPassDirection_In => Some(ParamPassDirection::In),
+                ll::CXCommentParamPassDirection_Out => Some(ParamPassDirection::Out),
+                ll::CXCommentParamPassDirection_InOut => Some(ParamPassDirection::InOut),
+                _ => None
+            }
+        }
+    }
+
+    /// The index of the parameter a parameter command documents, if it actually matches one.
+    pub fn param_index(&self) -> Option<u32> {
+        if self.kind() != CommentKind::ParamCommand {
+            return None;
+        }
+        unsafe {
+            if ll::clang_ParamCommandComment_isParamIndexValid(self.1) == 0 {
+                None
+            } else {
+                Some(ll::clang_ParamCommandComment_getParamIndex(self.1))
+            }
+        }
+    }
+
+    /// The name of the parameter a parameter command documents.
+    pub fn param_name(&self) -> Option<String> {
+        unsafe {
+            match self.kind() {
+                CommentKind::ParamCommand => Some(cxstring_to_string(ll::clang_ParamCommandComment_getParamName(self.1))),
+                CommentKind::TParamCommand => Some(cxstring_to_string(ll::clang_TParamCommandComment_getParamName(self.1))),
+                _ => None
+            }
+        }
+    }
+
+    /// How an inline command's arguments should be rendered.
+    pub fn render_kind(&self) -> Option<InlineCommandRenderKind> {
+        use self::InlineCommandRenderKind as RK;
+        if self.kind() != CommentKind::InlineCommand {
+            return None;
+        }
+        unsafe {
+            match ll::clang_InlineCommandComment_getRenderKind(self.1) {
+                ll::CXCommentInlineCommandRenderKind_Bold => Some(RK::Bold),
+                ll::CXCommentInlineCommandRenderKind_Monospaced => Some(RK::Monospaced),
+                ll::CXCommentInlineCommandRenderKind_Emphasized => Some(RK::Emphasized),
+                _ => Some(RK::Normal)
+            }
+        }
+    }
+
+    /// The text of a text or verbatim node.
+    pub fn text(&self) -> Option<String> {
+        use self::CommentKind::*;
+        unsafe {
+            match self.kind() {
+                Text => Some(cxstring_to_string(ll::clang_TextComment_getText(self.1))),
+                VerbatimBlockLine => Some(cxstring_to_string(ll::clang_VerbatimBlockLineComment_getText(self.1))),
+                VerbatimLine => Some(cxstring_to_string(ll::clang_VerbatimLineComment_getText(self.1))),
+                _ => None
+            }
+        }
+    }
+}
@@ -0,0 +1,493 @@
+/*!
+Loads libclang at runtime, rather than linking against it.
+
+This is used when the `runtime` feature is enabled.  It means the tool isn't tied to the one libclang it was built against: any version with the functions we need will do.
+
+Only the functions this crate actually uses are resolved.  They're listed in `runtime_fns!` below, which also generates the wrappers that `ll` exposes in place of its usual `extern` block.  Adding a new use of a libclang function means adding it here, too.
+*/
+use std::env;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use libc::c_void;
+use clang::ll;
+
+/**
+Generates the function table, and a wrapper for each function which calls through it.
+
+`required` functions must be present for a library to be used at all.  `optional` ones are left as `None` if they're missing, and calling one of those panics.
+*/
+macro_rules! runtime_fns {
+    ($($req:ident fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)*;)*) => {
+        #[allow(non_snake_case)]
+        struct Functions {
+            $($name: Option<unsafe extern "C" fn($($ty),*) $(-> $ret)*>,)*
+        }
+
+        impl Functions {
+            /**
+            Resolves every function from the given library, along with the names of any required functions which are missing.
+            */
+            unsafe fn resolve(lib: &Library) -> (Functions, Vec<&'static str>) {
+                let mut missing = vec![];
+                let fns = Functions {
+                    $($name: {
+                        let ptr = lib.symbol(stringify!($name));
+                        if ptr.is_null() {
+                            if stringify!($req) == "required" {
+                                missing.push(stringify!($name));
+                            }
+                            None
+                        } else {
+                            Some(::std::mem::transmute(ptr))
+                        }
+                    },)*
+                };
+                (fns, missing)
+            }
+
+            fn has(&self, name: &str) -> bool {
+                $(if name == stringify!($name) {
+                    return self.$name.is_some();
+                })*
+                false
+            }
+        }
+
+        pub mod functions {
+            #![allow(non_snake_case)]
+            use clang::ll;
+
+            $(
+                pub unsafe fn $name($($arg: $ty),*) $(-> $ret)* {
+                    match super::loaded().$name {
+                        Some(f) => f($($arg),*),
+                        None => panic!(concat!("libclang function `", stringify!($name), "` is not available"))
+                    }
+                }
+            )*
+        }
+    };
+}
+
+runtime_fns! {
+    required fn clang_getCString(string: ll::CXString) -> *const ::libc::c_char;
+    required fn clang_disposeString(string: ll::CXString);
+    required fn clang_createIndex(excludeDeclarationsFromPCH: ::libc::c_int, displayDiagnostics: ::libc::c_int) -> ll::CXIndex;
+    required fn clang_disposeIndex(index: ll::CXIndex);
+    required fn clang_CXIndex_setGlobalOptions(arg1: ll::CXIndex, options: ::libc::c_uint);
+    required fn clang_CXIndex_getGlobalOptions(arg1: ll::CXIndex) -> ::libc::c_uint;
+    required fn clang_getFileName(SFile: ll::CXFile) -> ll::CXString;
+    required fn clang_equalLocations(loc1: ll::CXSourceLocation, loc2: ll::CXSourceLocation) -> ::libc::c_uint;
+    required fn clang_Location_isInSystemHeader(location: ll::CXSourceLocation) -> ::libc::c_int;
+    required fn clang_Location_isFromMainFile(location: ll::CXSourceLocation) -> ::libc::c_int;
+    required fn clang_Range_isNull(range: ll::CXSourceRange) -> ::libc::c_int;
+    required fn clang_getInstantiationLocation(location: ll::CXSourceLocation, file: *mut ll::CXFile, line: *mut ::libc::c_uint, column: *mut ::libc::c_uint, offset: *mut ::libc::c_uint);
+    required fn clang_getFileLocation(location: ll::CXSourceLocation, file: *mut ll::CXFile, line: *mut ::libc::c_uint, column: *mut ::libc::c_uint, offset: *mut ::libc::c_uint);
+    required fn clang_getRangeStart(range: ll::CXSourceRange) -> ll::CXSourceLocation;
+    required fn clang_getRangeEnd(range: ll::CXSourceRange) -> ll::CXSourceLocation;
+    required fn clang_getNumDiagnostics(Unit: ll::CXTranslationUnit) -> ::libc::c_uint;
+    required fn clang_getDiagnostic(Unit: ll::CXTranslationUnit, Index: ::libc::c_uint) -> ll::CXDiagnostic;
+    required fn clang_disposeDiagnostic(Diagnostic: ll::CXDiagnostic);
+    required fn clang_formatDiagnostic(Diagnostic: ll::CXDiagnostic, Options: ::libc::c_uint) -> ll::CXString;
+    required fn clang_defaultDiagnosticDisplayOptions() -> ::libc::c_uint;
+    required fn clang_getDiagnosticSeverity(arg1: ll::CXDiagnostic) -> ll::Enum_CXDiagnosticSeverity;
+    required fn clang_getDiagnosticLocation(arg1: ll::CXDiagnostic) -> ll::CXSourceLocation;
+    required fn clang_getDiagnosticSpelling(arg1: ll::CXDiagnostic) -> ll::CXString;
+    required fn clang_getDiagnosticOption(Diag: ll::CXDiagnostic, Disable: *mut ll::CXString) -> ll::CXString;
+    required fn clang_getDiagnosticCategoryText(arg1: ll::CXDiagnostic) -> ll::CXString;
+    required fn clang_getDiagnosticNumRanges(arg1: ll::CXDiagnostic) -> ::libc::c_uint;
+    required fn clang_getDiagnosticRange(Diagnostic: ll::CXDiagnostic, Range: ::libc::c_uint) -> ll::CXSourceRange;
+    required fn clang_getDiagnosticNumFixIts(Diagnostic: ll::CXDiagnostic) -> ::libc::c_uint;
+    required fn clang_getDiagnosticFixIt(Diagnostic: ll::CXDiagnostic, FixIt: ::libc::c_uint, ReplacementRange: *mut ll::CXSourceRange) -> ll::CXString;
+    required fn clang_createTranslationUnitFromSourceFile(CIdx: ll::CXIndex, source_filename: *const ::libc::c_char, num_clang_command_line_args: ::libc::c_int, clang_command_line_args: *const *const ::libc::c_char, num_unsaved_files: ::libc::c_uint, unsaved_files: *mut ll::Struct_CXUnsavedFile) -> ll::CXTranslationUnit;
+    required fn clang_createTranslationUnit(arg1: ll::CXIndex, ast_filename: *const ::libc::c_char) -> ll::CXTranslationUnit;
+    required fn clang_parseTranslationUnit2(CIdx: ll::CXIndex, source_filename: *const ::libc::c_char, command_line_args: *const *const ::libc::c_char, num_command_line_args: ::libc::c_int, unsaved_files: *mut ll::Struct_CXUnsavedFile, num_unsaved_files: ::libc::c_uint, options: ::libc::c_uint, out_TU: *mut ll::CXTranslationUnit) -> ll::CXErrorCode;
+    required fn clang_disposeTranslationUnit(arg1: ll::CXTranslationUnit);
+    required fn clang_getTranslationUnitCursor(arg1: ll::CXTranslationUnit) -> ll::CXCursor;
+    required fn clang_equalCursors(arg1: ll::CXCursor, arg2: ll::CXCursor) -> ::libc::c_uint;
+    required fn clang_Cursor_isNull(cursor: ll::CXCursor) -> ::libc::c_int;
+    required fn clang_hashCursor(arg1: ll::CXCursor) -> ::libc::c_uint;
+    required fn clang_getCursorKind(arg1: ll::CXCursor) -> ll::Enum_CXCursorKind;
+    required fn clang_isExpression(arg1: ll::Enum_CXCursorKind) -> ::libc::c_uint;
+    required fn clang_isAttribute(arg1: ll::Enum_CXCursorKind) -> ::libc::c_uint;
+    required fn clang_getCursorLinkage(cursor: ll::CXCursor) -> ll::Enum_CXLinkageKind;
+    required fn clang_Cursor_getStorageClass(arg1: ll::CXCursor) -> ll::Enum_CX_StorageClass;
+    required fn clang_getCursorAvailability(cursor: ll::CXCursor) -> ll::Enum_CXAvailabilityKind;
+    required fn clang_getCursorPlatformAvailability(cursor: ll::CXCursor, always_deprecated: *mut ::libc::c_int, deprecated_message: *mut ll::CXString, always_unavailable: *mut ::libc::c_int, unavailable_message: *mut ll::CXString, availability: *mut ll::CXPlatformAvailability, availability_size: ::libc::c_int) -> ::libc::c_int;
+    required fn clang_disposeCXPlatformAvailability(availability: *mut ll::CXPlatformAvailability);
+    required fn clang_Cursor_getTranslationUnit(arg1: ll::CXCursor) -> ll::CXTranslationUnit;
+    required fn clang_getCursorLexicalParent(cursor: ll::CXCursor) -> ll::CXCursor;
+    required fn clang_getCursorLocation(arg1: ll::CXCursor) -> ll::CXSourceLocation;
+    required fn clang_getCursorExtent(arg1: ll::CXCursor) -> ll::CXSourceRange;
+    required fn clang_getCursorType(C: ll::CXCursor) -> ll::CXType;
+    required fn clang_getTypeSpelling(CT: ll::CXType) -> ll::CXString;
+    required fn clang_getTypedefDeclUnderlyingType(C: ll::CXCursor) -> ll::CXType;
+    required fn clang_getEnumDeclIntegerType(C: ll::CXCursor) -> ll::CXType;
+    required fn clang_getEnumConstantDeclValue(C: ll::CXCursor) -> ::libc::c_longlong;
+    required fn clang_getCanonicalType(T: ll::CXType) -> ll::CXType;
+    required fn clang_isConstQualifiedType(T: ll::CXType) -> ::libc::c_uint;
+    required fn clang_getPointeeType(T: ll::CXType) -> ll::CXType;
+    required fn clang_getTypeDeclaration(T: ll::CXType) -> ll::CXCursor;
+    required fn clang_getFunctionTypeCallingConv(T: ll::CXType) -> ll::Enum_CXCallingConv;
+    required fn clang_getResultType(T: ll::CXType) -> ll::CXType;
+    required fn clang_getNumArgTypes(T: ll::CXType) -> ::libc::c_int;
+    required fn clang_getArgType(T: ll::CXType, i: ::libc::c_uint) -> ll::CXType;
+    required fn clang_isFunctionTypeVariadic(T: ll::CXType) -> ::libc::c_uint;
+    required fn clang_getArrayElementType(T: ll::CXType) -> ll::CXType;
+    required fn clang_getArraySize(T: ll::CXType) -> ::libc::c_longlong;
+    required fn clang_Type_getAlignOf(T: ll::CXType) -> ::libc::c_longlong;
+    required fn clang_Type_getSizeOf(T: ll::CXType) -> ::libc::c_longlong;
+    required fn clang_Type_getOffsetOf(T: ll::CXType, S: *const ::libc::c_char) -> ::libc::c_longlong;
+    required fn clang_visitChildren(parent: ll::CXCursor, visitor: ll::CXCursorVisitor, client_data: ll::CXClientData) -> ::libc::c_uint;
+    required fn clang_getCursorSpelling(arg1: ll::CXCursor) -> ll::CXString;
+    required fn clang_getCursorReferenced(arg1: ll::CXCursor) -> ll::CXCursor;
+    required fn clang_getCursorDefinition(arg1: ll::CXCursor) -> ll::CXCursor;
+    required fn clang_isCursorDefinition(arg1: ll::CXCursor) -> ::libc::c_uint;
+    required fn clang_Cursor_getRawCommentText(C: ll::CXCursor) -> ll::CXString;
+    required fn clang_Cursor_getBriefCommentText(C: ll::CXCursor) -> ll::CXString;
+    required fn clang_Cursor_getParsedComment(C: ll::CXCursor) -> ll::CXComment;
+    required fn clang_Comment_getKind(Comment: ll::CXComment) -> ll::Enum_CXCommentKind;
+    required fn clang_Comment_getNumChildren(Comment: ll::CXComment) -> ::libc::c_uint;
+    required fn clang_Comment_getChild(Comment: ll::CXComment, ChildIdx: ::libc::c_uint) -> ll::CXComment;
+    required fn clang_Comment_isWhitespace(Comment: ll::CXComment) -> ::libc::c_uint;
+    required fn clang_InlineContentComment_hasTrailingNewline(Comment: ll::CXComment) -> ::libc::c_uint;
+    required fn clang_TextComment_getText(Comment: ll::CXComment) -> ll::CXString;
+    required fn clang_InlineCommandComment_getCommandName(Comment: ll::CXComment) -> ll::CXString;
+    required fn clang_InlineCommandComment_getRenderKind(Comment: ll::CXComment) -> ll::Enum_CXCommentInlineCommandRenderKind;
+    required fn clang_InlineCommandComment_getNumArgs(Comment: ll::CXComment) -> ::libc::c_uint;
+    required fn clang_InlineCommandComment_getArgText(Comment: ll::CXComment, ArgIdx: ::libc::c_uint) -> ll::CXString;
+    required fn clang_BlockCommandComment_getCommandName(Comment: ll::CXComment) -> ll::CXString;
+    required fn clang_BlockCommandComment_getNumArgs(Comment: ll::CXComment) -> ::libc::c_uint;
+    required fn clang_BlockCommandComment_getArgText(Comment: ll::CXComment, ArgIdx: ::libc::c_uint) -> ll::CXString;
+    required fn clang_BlockCommandComment_getParagraph(Comment: ll::CXComment) -> ll::CXComment;
+    required fn clang_ParamCommandComment_getParamName(Comment: ll::CXComment) -> ll::CXString;
+    required fn clang_ParamCommandComment_isParamIndexValid(Comment: ll::CXComment) -> ::libc::c_uint;
+    required fn clang_ParamCommandComment_getParamIndex(Comment: ll::CXComment) -> ::libc::c_uint;
+    required fn clang_ParamCommandComment_isDirectionExplicit(Comment: ll::CXComment) -> ::libc::c_uint;
+    required fn clang_ParamCommandComment_getDirection(Comment: ll::CXComment) -> ll::Enum_CXCommentParamPassDirection;
+    required fn clang_TParamCommandComment_getParamName(Comment: ll::CXComment) -> ll::CXString;
+    required fn clang_VerbatimBlockLineComment_getText(Comment: ll::CXComment) -> ll::CXString;
+    required fn clang_VerbatimLineComment_getText(Comment: ll::CXComment) -> ll::CXString;
+    required fn clang_getTokenSpelling(arg1: ll::CXTranslationUnit, arg2: ll::CXToken) -> ll::CXString;
+    required fn clang_getTokenLocation(arg1: ll::CXTranslationUnit, arg2: ll::CXToken) -> ll::CXSourceLocation;
+    required fn clang_getTokenExtent(arg1: ll::CXTranslationUnit, arg2: ll::CXToken) -> ll::CXSourceRange;
+    required fn clang_tokenize(TU: ll::CXTranslationUnit, Range: ll::CXSourceRange, Tokens: *mut *mut ll::CXToken, NumTokens: *mut ::libc::c_uint);
+    required fn clang_disposeTokens(TU: ll::CXTranslationUnit, Tokens: *mut ll::CXToken, NumTokens: ::libc::c_uint);
+    required fn clang_getClangVersion() -> ll::CXString;
+    required fn clang_IndexAction_dispose(arg1: ll::CXIndexAction);
+
+    // Newer functions, which are only used when `Capabilities` says so.
+    optional fn clang_Cursor_isAnonymous(C: ll::CXCursor) -> ::libc::c_uint;
+    optional fn clang_Cursor_isMacroFunctionLike(C: ll::CXCursor) -> ::libc::c_uint;
+    optional fn clang_Type_getNamedType(T: ll::CXType) -> ll::CXType;
+    optional fn clang_Cursor_Evaluate(C: ll::CXCursor) -> ll::CXEvalResult;
+    optional fn clang_EvalResult_getKind(E: ll::CXEvalResult) -> ll::Enum_CXEvalResultKind;
+    optional fn clang_EvalResult_isUnsignedInt(E: ll::CXEvalResult) -> ::libc::c_uint;
+    optional fn clang_EvalResult_getAsLongLong(E: ll::CXEvalResult) -> ::libc::c_longlong;
+    optional fn clang_EvalResult_getAsUnsigned(E: ll::CXEvalResult) -> ::libc::c_ulonglong;
+    optional fn clang_EvalResult_getAsDouble(E: ll::CXEvalResult) -> ::libc::c_double;
+    optional fn clang_EvalResult_dispose(E: ll::CXEvalResult);
+    optional fn clang_Cursor_getVarDeclInitializer(cursor: ll::CXCursor) -> ll::CXCursor;
+}
+
+static mut FUNCTIONS: *const Functions = 0 as *const Functions;
+
+fn loaded() -> &'static Functions {
+    unsafe {
+        if FUNCTIONS.is_null() {
+            panic!("libclang has not been loaded; call `clang::load` first");
+        }
+        &*FUNCTIONS
+    }
+}
+
+/**
+Returns `true` if the loaded libclang has the named function.
+*/
+pub fn is_available(name: &str) -> bool {
+    loaded().has(name)
+}
+
+/**
+Finds and loads libclang, if it hasn't been already.
+
+Candidates are tried in order; the first one which loads and has every required function wins.  The error lists every candidate that was tried and what was wrong with it.
+*/
+pub fn load() -> Result<(), String> {
+    unsafe {
+        if !FUNCTIONS.is_null() {
+            return Ok(());
+        }
+    }
+
+    let candidates: Vec<PathBuf> = candidates().into_iter().filter(|p| p.is_file()).collect();
+    if candidates.len() == 0 {
+        return Err(format!("could not find libclang; set LIBCLANG_PATH to the directory containing {}",
+            LIB_NAMES[0]));
+    }
+
+    let mut errors = vec![];
+    for path in candidates {
+        match try_load(&path) {
+            Ok(fns) => {
+                info!("loaded libclang from {}", path.display());
+                unsafe { FUNCTIONS = Box::into_raw(Box::new(fns)); }
+                return Ok(());
+            },
+            Err(err) => {
+                debug!("could not use {}: {}", path.display(), err);
+                errors.push(format!("  {}: {}", path.display(), err));
+            }
+        }
+    }
+
+    Err(format!("could not load a usable libclang:\n{}", errors.join("\n")))
+}
+
+fn try_load(path: &Path) -> Result<Functions, String> {
+    let lib = try!(Library::open(path));
+    let (fns, missing) = unsafe { Functions::resolve(&lib) };
+    if missing.len() > 0 {
+        let err = format!("{} is missing required functions: {}",
+            version_of(&fns).unwrap_or("unknown version".into()),
+            missing.join(", "));
+        // Nothing from `fns` can be used once the library is gone.
+        drop(fns);
+        unsafe { lib.close(); }
+        return Err(err);
+    }
+    Ok(fns)
+}
+
+/**
+Gets the version of a library which hasn't been (and may never be) installed as *the* libclang.
+*/
+fn version_of(fns: &Functions) -> Option<String> {
+    use std::ffi::CStr;
+    match (fns.clang_getClangVersion, fns.clang_getCString, fns.clang_disposeString) {
+        (Some(get_version), Some(get_cstring), Some(dispose)) => unsafe {
+            let cxs = get_version();
+            let s = CStr::from_ptr(get_cstring(cxs)).to_string_lossy().into_owned();
+            dispose(cxs);
+            Some(s)
+        },
+        _ => None
+    }
+}
+
+/**
+Every path libclang might be at, in the order they should be tried.
+
+This is `LIBCLANG_PATH` (which may name either the library or its directory), then wherever `llvm-config` says, then the usual install locations.
+*/
+fn candidates() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+
+    if let Some(path) = env::var_os("LIBCLANG_PATH") {
+        let path = PathBuf::from(path);
+        if path.is_file() {
+            return vec![path];
+        }
+        dirs.push(path);
+    }
+
+    dirs.extend(llvm_config_dirs());
+    dirs.extend(COMMON_DIRS.iter().map(PathBuf::from));
+    for v in (MIN_VERSION..MAX_VERSION + 1).rev() {
+        dirs.extend(versioned_dirs(v));
+    }
+
+    let mut paths = vec![];
+    for dir in dirs {
+        for name in LIB_NAMES {
+            paths.push(dir.join(name));
+        }
+        for v in (MIN_VERSION..MAX_VERSION + 1).rev() {
+            paths.extend(versioned_names(v).into_iter().map(|name| dir.join(name)));
+        }
+    }
+    paths
+}
+
+/// The range of major versions to look for in versioned names and directories.
+const MIN_VERSION: u32 = 10;
+const MAX_VERSION: u32 = 17;
+
+fn llvm_config_dirs() -> Vec<PathBuf> {
+    use std::process::Command;
+
+    // On Windows, the DLL lives with the executables.
+    let flags: &[&str] = if cfg!(windows) { &["--bindir", "--libdir"] } else { &["--libdir"] };
+
+    flags.iter()
+        .filter_map(|flag| Command::new("llvm-config").arg(flag).output().ok())
+        .filter(|out| out.status.success())
+        .filter_map(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| PathBuf::from(s.trim()))
+        .collect()
+}
+
+#[cfg(windows)]
+const LIB_NAMES: &'static [&'static str] = &["libclang.dll", "clang.dll"];
+
+#[cfg(windows)]
+const COMMON_DIRS: &'static [&'static str] = &[
+    "C:\\Program Files\\LLVM\\bin",
+    "C:\\Program Files (x86)\\LLVM\\bin",
+];
+
+#[cfg(windows)]
+fn versioned_dirs(_version: u32) -> Vec<PathBuf> { vec![] }
+
+#[cfg(windows)]
+fn versioned_names(_version: u32) -> Vec<String> { vec![] }
+
+#[cfg(target_os = "macos")]
+const LIB_NAMES: &'static [&'static str] = &["libclang.dylib"];
+
+#[cfg(target_os = "macos")]
+const COMMON_DIRS: &'static [&'static str] = &[
+    "/usr/local/opt/llvm/lib",
+    "/opt/homebrew/opt/llvm/lib",
+    "/Library/Developer/CommandLineTools/usr/lib",
+    "/Applications/Xcode.app/Contents/Developer/Toolchains/XcodeDefault.xctoolchain/usr/lib",
+];
+
+#[cfg(target_os = "macos")]
+fn versioned_dirs(version: u32) -> Vec<PathBuf> {
+    vec![PathBuf::from(format!("/usr/local/opt/llvm@{}/lib", version))]
+}
+
+#[cfg(target_os = "macos")]
+fn versioned_names(_version: u32) -> Vec<String> { vec![] }
+
+#[cfg(all(unix, not(target_os = "macos")))]
+const LIB_NAMES: &'static [&'static str] = &["libclang.so", "libclang.so.1"];
+
+#[cfg(all(unix, not(target_os = "macos")))]
+const COMMON_DIRS: &'static [&'static str] = &[
+    "/usr/local/lib",
+    "/usr/lib",
+    "/usr/lib64",
+    "/usr/lib/x86_64-linux-gnu",
+    "/usr/lib/i386-linux-gnu",
+];
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn versioned_dirs(version: u32) -> Vec<PathBuf> {
+    vec![
+        PathBuf::from(format!("/usr/lib/llvm-{}/lib", version)),
+        PathBuf::from(format!("/usr/lib64/llvm{}/lib", version)),
+    ]
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn versioned_names(version: u32) -> Vec<String> {
+    vec![format!("libclang.so.{}", version), format!("libclang-{}.so", version)]
+}
+
+/**
+A loaded shared library.
+
+Only libraries which are rejected get closed.  The one that's used stays loaded, since the functions resolved from it are used for the rest of the run.
+*/
+struct Library(*mut c_void);
+
+#[cfg(unix)]
+impl Library {
+    fn open(path: &Path) -> Result<Library, String> {
+        use std::ffi::CStr;
+        use libc::{c_char, c_int};
+
+        #[link(name = "dl")]
+        extern "C" {
+            fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+            fn dlerror() -> *mut c_char;
+        }
+        const RTLD_NOW: c_int = 2;
+
+        let path = try!(path.to_str()
+            .and_then(|s| CString::new(s).ok())
+            .ok_or_else(|| String::from("unusable path")));
+        unsafe {
+            let handle = dlopen(path.as_ptr(), RTLD_NOW);
+            if handle.is_null() {
+                let err = dlerror();
+                return Err(if err.is_null() {
+                    "could not load library".into()
+                } else {
+                    CStr::from_ptr(err).to_string_lossy().into_owned()
+                });
+            }
+            Ok(Library(handle))
+        }
+    }
+
+    unsafe fn symbol(&self, name: &str) -> *mut c_void {
+        use libc::c_char;
+
+        #[link(name = "dl")]
+        extern "C" {
+            fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        }
+
+        let name = CString::new(name).expect("symbol name without NULs");
+        dlsym(self.0, name.as_ptr())
+    }
+
+    unsafe fn close(self) {
+        use libc::c_int;
+
+        #[link(name = "dl")]
+        extern "C" {
+            fn dlclose(handle: *mut c_void) -> c_int;
+        }
+
+        dlclose(self.0);
+    }
+}
+
+#[cfg(windows)]
+impl Library {
+    fn open(path: &Path) -> Result<Library, String> {
+        use std::io;
+        use std::os::windows::ffi::OsStrExt;
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn LoadLibraryW(name: *const u16) -> *mut c_void;
+        }
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        unsafe {
+            let handle = LoadLibraryW(wide.as_ptr());
+            if handle.is_null() {
+                return Err(io::Error::last_os_error().to_string());
+            }
+            Ok(Library(handle))
+        }
+    }
+
+    unsafe fn symbol(&self, name: &str) -> *mut c_void {
+        use libc::c_char;
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+        }
+
+        let name = CString::new(name).expect("symbol name without NULs");
+        GetProcAddress(self.0, name.as_ptr())
+    }
+
+    unsafe fn close(self) {
+        use libc::c_int;
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn FreeLibrary(module: *mut c_void) -> c_int;
+        }
+
+        FreeLibrary(self.0);
+    }
+}
@@ -0,0 +1,115 @@
+/*!
+A visitor over the Clang AST.
+
+Unlike `Cursor::children`, this doesn't allocate a `Vec` for every node it looks at.  Unlike `Cursor::visit_children`, it tells the visitor when it *leaves* a node, as well as when it enters it, and where in the tree each node is.
+*/
+use super::{Cursor, VisitAction, VisitTermination};
+
+/**
+What to do after entering a cursor.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Walk {
+    /// Visit this cursor's children, then leave it.
+    Recurse,
+
+    /// Skip this cursor's children, and leave it immediately.
+    Prune,
+
+    /// Stop the walk entirely.  Nothing further is entered *or* left.
+    Stop,
+}
+
+/**
+Where a cursor is in the tree being walked.
+*/
+pub struct Position<'a> {
+    /// The cursors enclosing this one, outermost first.  The root of the walk is not included.
+    pub ancestors: &'a [Cursor],
+
+    /// Which child of its parent this cursor is, counting from zero.
+    pub index: usize,
+}
+
+impl<'a> Position<'a> {
+    /// How deeply nested this cursor is; the children of the root are at depth zero.
+    pub fn depth(&self) -> usize {
+        self.ancestors.len()
+    }
+
+    /// The cursor directly enclosing this one, unless that's the root of the walk.
+    pub fn parent(&self) -> Option<&'a Cursor> {
+        self.ancestors.last()
+    }
+}
+
+pub trait Visitor {
+    /**
+    Called when a cursor is reached.  The return value determines whether its children are visited.
+    */
+    fn enter(&mut self, cur: &Cursor, pos: &Position) -> Walk;
+
+    /**
+    Called once a cursor and all of its (unpruned) children have been visited.
+    */
+    fn leave(&mut self, _cur: &Cursor, _pos: &Position) {}
+}
+
+/**
+Walks every descendant of `root` in order, calling `visitor` on each.
+
+Returns `VisitTermination::Early` if the visitor stopped the walk.
+*/
+pub fn walk<V: Visitor>(root: &Cursor, visitor: &mut V) -> VisitTermination {
+    let mut ancestors = vec![];
+    match walk_children(root, visitor, &mut ancestors) {
+        true => VisitTermination::Normal,
+        false => VisitTermination::Early
+    }
+}
+
+/**
+Walks the children of `cur`, returning `false` if the visitor stopped the walk.
+*/
+fn walk_children<V: Visitor>(cur: &Cursor, visitor: &mut V, ancestors: &mut Vec<Cursor>) -> bool {
+    let mut index = 0;
+    let mut completed = true;
+
+    cur.visit_children(|child, _parent| {
+        let walk = {
+            let pos = Position { ancestors: &ancestors[..], index: index };
+            visitor.enter(&child, &pos)
+        };
+
+        let action = match walk {
+            Walk::Stop => {
+                completed = false;
+                VisitAction::Break
+            },
+            Walk::Prune => {
+                let pos = Position { ancestors: &ancestors[..], index: index };
+                visitor.leave(&child, &pos);
+                VisitAction::Continue
+            },
+            Walk::Recurse => {
+                ancestors.push(child.clone());
+                let ok = walk_children(&child, &mut *visitor, &mut *ancestors);
+                ancestors.pop();
+
+                if ok {
+                    let pos = Position { ancestors: &ancestors[..], index: index };
+                    visitor.leave(&child, &pos);
+                    VisitAction::Continue
+                } else {
+                    completed = false;
+                    VisitAction::Break
+                }
+            },
+        };
+
+        index += 1;
+        action
+    });
+
+    completed
+}
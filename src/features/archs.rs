@@ -2,6 +2,8 @@
 Defines the Architectures feature set component.
 */
 use std::fmt;
+use Architecture;
+use super::cfg::Cfg;
 
 bitflags! {
     /**
@@ -56,25 +58,44 @@ impl Architectures {
     }
 }
 
+impl Architectures {
+    /// Returns the `target_arch` names of the architectures in this set.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = vec![];
+        if (*self & Architectures::X86_32).is_any() {
+            names.push("x86");
+        }
+        if (*self & Architectures::X86_64).is_any() {
+            names.push("x86_64");
+        }
+        if (*self & Architectures::Arm).is_any() {
+            names.push("arm");
+        }
+        names
+    }
+
+    /// Converts this set into a `#[cfg]` predicate.
+    pub fn to_cfg(&self) -> Cfg {
+        if self.is_all() {
+            return Cfg::All(vec![]);
+        }
+        Cfg::Any(self.names().into_iter().map(Cfg::TargetArch).collect())
+    }
+}
+
+impl From<Architecture> for Architectures {
+    fn from(v: Architecture) -> Architectures {
+        match v {
+            Architecture::X86_32 => Architectures::X86_32,
+            Architecture::X86_64 => Architectures::X86_64,
+            Architecture::Arm => Architectures::Arm,
+        }
+    }
+}
+
 impl fmt::Display for Architectures {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         assert!(self.is_any(), "can't have no architectures enabled");
-        if !self.is_all() {
-            try!(write!(fmt, "#[cfg(any("));
-            let mut sep = "";
-            if (*self & Architectures::X86_32).is_any() {
-                try!(write!(fmt, "{}target_arch=\"x86\"", sep));
-                sep = ", ";
-            }
-            if (*self & Architectures::X86_64).is_any() {
-                try!(write!(fmt, "{}target_arch=\"x86_64\"", sep));
-                sep = ", ";
-            }
-            if (*self & Architectures::Arm).is_any() {
-                try!(write!(fmt, "{}target_arch=\"arm\"", sep));
-            }
-            try!(write!(fmt, "))] "));
-        }
-        Ok(())
+        self.to_cfg().simplify().write_attr(fmt)
     }
 }
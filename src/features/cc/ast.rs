@@ -2,7 +2,7 @@
 Defines the AST for conditional compilation expressions.
 */
 use WinVersion;
-use features::{Architectures, Partitions, is_important_define};
+use features::{Architectures, DefineSet, EvalContext, Family, Partitions, is_important_define};
 use super::eval::Value;
 
 /**
@@ -28,6 +28,18 @@ pub enum Node {
     /// A `_ || _` expression.
     Or(Box<Node>, Box<Node>),
 
+    /// A `_ ? _ : _` expression.
+    Cond(Box<Node>, Box<Node>, Box<Node>),
+
+    /// A `_ | _` expression.
+    BitOr(Box<Node>, Box<Node>),
+
+    /// A `_ ^ _` expression.
+    BitXor(Box<Node>, Box<Node>),
+
+    /// A `_ & _` expression.
+    BitAnd(Box<Node>, Box<Node>),
+
     /// A `_ == _` expression.
     Eq(Box<Node>, Box<Node>),
 
@@ -49,15 +61,30 @@ pub enum Node {
     /// A `_ >> _` expression.
     Rs(Box<Node>, Box<Node>),
 
+    /// A `_ << _` expression.
+    Ls(Box<Node>, Box<Node>),
+
+    /// A `_ + _` expression.
+    Add(Box<Node>, Box<Node>),
+
+    /// A `_ - _` expression.
+    Sub(Box<Node>, Box<Node>),
+
     /// A `OSVER( _ )` expression.
     OsVer(Box<Node>),
 
     /// A `SPVER( _ )` expression.
     SpVer(Box<Node>),
 
+    /// A `SUBVER( _ )` expression.
+    SubVer(Box<Node>),
+
     /// A `WINAPI_FAMILY_PARTITION( _ )` expression.
     Part(Box<Node>),
 
+    /// A `WINAPI_FAMILY_ONE_PARTITION( _ , _ )` expression.
+    OnePart(Box<Node>, Box<Node>),
+
     /// A `_( _ )` expression.
     Invoke(Box<Node>, Box<Node>),
 
@@ -68,29 +95,57 @@ pub enum Node {
 impl Node {
     /**
     Evaluates an AST node.
+
+    If `ctx` is lenient, any subexpression which fails to evaluate is treated as `Ignore`.
     */
-    pub fn eval(&self) -> Result<Value, String> {
+    pub fn eval(&self, ctx: EvalContext) -> Result<Value, String> {
+        match self.eval_node(ctx) {
+            Err(err) => {
+                if ctx.lenient {
+                    debug!(".. ignoring {:?}: {}", self, err);
+                    Ok(Value::Ignore)
+                } else {
+                    Err(err)
+                }
+            },
+            value => value
+        }
+    }
+
+    fn eval_node(&self, ctx: EvalContext) -> Result<Value, String> {
         use self::Node::*;
         match *self {
             IntLit(v) => Ok(Value::Int(v)),
-            Ident(ref s) => Node::eval_ident(s),
-            Defined(ref n) => Node::eval_defined(&*try!(n.simplify_to_ident())),
-            Not(ref n) => try!(n.eval()).complement(),
-            And(ref l, ref r) => Ok(try!(try!(l.eval()).and(try!(r.eval())))),
-            Or(ref l, ref r) => Ok(try!(try!(l.eval()).or(try!(r.eval())))),
-            Eq(ref l, ref r) => Ok(try!(try!(l.eval()).eq(try!(r.eval())))),
-            Ne(ref l, ref r) => Ok(try!(try!(l.eval()).ne(try!(r.eval())))),
-            Lt(ref l, ref r) => Ok(try!(try!(l.eval()).lt(try!(r.eval())))),
-            Le(ref l, ref r) => Ok(try!(try!(l.eval()).le(try!(r.eval())))),
-            Gt(ref l, ref r) => Ok(try!(try!(l.eval()).gt(try!(r.eval())))),
-            Ge(ref l, ref r) => Ok(try!(try!(l.eval()).ge(try!(r.eval())))),
-            Rs(ref l, ref r) => Ok(try!(try!(l.eval()).rs(try!(r.eval())))),
-            OsVer(ref n) => try!(n.eval()).os_ver(),
-            SpVer(ref n) => try!(n.eval()).sp_ver(),
-            Part(ref n) => Node::eval_partition(try!(n.eval())),
+            Ident(ref s) => Node::eval_ident(s, ctx),
+            Defined(ref n) => Node::eval_defined(&*try!(n.simplify_to_ident()), ctx),
+            Not(ref n) => try!(n.eval(ctx)).complement(),
+            And(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).and(try!(r.eval(ctx))))),
+            Or(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).or(try!(r.eval(ctx))))),
+            Cond(ref c, ref t, ref e) => try!(c.eval(ctx)).select(try!(t.eval(ctx)), try!(e.eval(ctx))),
+            BitOr(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).bit_or(try!(r.eval(ctx))))),
+            BitXor(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).bit_xor(try!(r.eval(ctx))))),
+            BitAnd(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).bit_and(try!(r.eval(ctx))))),
+            Eq(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).eq(try!(r.eval(ctx))))),
+            Ne(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).ne(try!(r.eval(ctx))))),
+            Lt(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).lt(try!(r.eval(ctx))))),
+            Le(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).le(try!(r.eval(ctx))))),
+            Gt(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).gt(try!(r.eval(ctx))))),
+            Ge(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).ge(try!(r.eval(ctx))))),
+            Rs(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).rs(try!(r.eval(ctx))))),
+            Ls(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).ls(try!(r.eval(ctx))))),
+            Add(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).add(try!(r.eval(ctx))))),
+            Sub(ref l, ref r) => Ok(try!(try!(l.eval(ctx)).sub(try!(r.eval(ctx))))),
+            OsVer(ref n) => try!(n.eval(ctx)).os_ver(),
+            SpVer(ref n) => try!(n.eval(ctx)).sp_ver(),
+            SubVer(ref n) => try!(n.eval(ctx)).sub_ver(),
+            Part(ref n) => Node::eval_partition(try!(n.eval(ctx))),
+            OnePart(ref set, ref part) => {
+                // This is `((WINAPI_FAMILY & set) == part)`.
+                try!(Value::CurrentFamily.bit_and(try!(set.eval(ctx)))).eq(try!(part.eval(ctx)))
+            },
             Invoke(ref n, ref a) => {
-                try!(try!(n.eval()).ignore());
-                try!(try!(a.eval()).ignore());
+                try!(try!(n.eval(ctx)).ignore());
+                try!(try!(a.eval(ctx)).ignore());
                 Ok(Value::Ignore)
             },
             Ignore => Ok(Value::Ignore),
@@ -100,10 +155,11 @@ impl Node {
     /**
     Evaluates a given identifier.
     */
-    fn eval_ident(ident: &str) -> Result<Value, String> {
+    fn eval_ident(ident: &str, ctx: EvalContext) -> Result<Value, String> {
         match ident {
             "NTDDI_VERSION" => return Ok(Value::FullVersion),
             "WINVER" | "_WIN32_WINNT" => return Ok(Value::ShortVersion),
+            "WINAPI_FAMILY" => return Ok(Value::CurrentFamily),
             _ => ()
         }
 
@@ -121,6 +177,13 @@ impl Node {
             };
         }
 
+        if ident.starts_with("WINAPI_FAMILY_") {
+            return match Family::from_define(ident) {
+                Some(family) => Ok(Value::FamilyValue(family)),
+                None => Err(format!("unknown WINAPI_FAMILY symbol {:?}", ident))
+            };
+        }
+
         if ident.starts_with("WINAPI_PARTITION_") {
             return match Partitions::from_define(ident) {
                 Some(parts) => Ok(Value::Part(parts)),
@@ -128,6 +191,10 @@ impl Node {
             };
         }
 
+        if let Some(define) = ctx.tracked_define(ident) {
+            return Ok(Value::Define(define.clone()));
+        }
+
         if is_important_define(ident) {
             return Err(format!("cannot eval important ident {:?}", ident))
         }
@@ -140,7 +207,7 @@ impl Node {
 
     This should *also* be used to process the argument to `#ifdef` and `#ifndef` directives.
     */
-    pub fn eval_defined(ident: &str) -> Result<Value, String> {
+    pub fn eval_defined(ident: &str, ctx: EvalContext) -> Result<Value, String> {
         match ident {
             "NTDDI_VERSION"
             | "_WIN32_WINNT"
@@ -160,6 +227,10 @@ impl Node {
             return Ok(Value::Feat(arch.into()));
         }
 
+        if let Some(define) = ctx.tracked_define(ident) {
+            return Ok(Value::Feat(DefineSet::defined(define).into()));
+        }
+
         if is_important_define(ident) {
             return Err(format!("cannot eval important defined({})", ident));
         }
@@ -1,8 +1,9 @@
 /*!
 Contains the `Value` type that represents a conditional compilation expression's value.
 */
+use std::rc::Rc;
 use WinVersion;
-use features::{Features, Partitions, WinVersions};
+use features::{DefineSet, Family, Features, Partitions, TrackedDefine, WinVersions};
 
 /**
 Represents a conditional compilation expression's value.
@@ -73,6 +74,26 @@ pub enum Value {
     */
     SpVersion,
     /**
+    The abstract "sub-version"; *i.e.* the lowest byte of the full version.  Windows 10 uses this to distinguish releases.
+    */
+    SubVersion,
+    /**
+    The abstract "current family"; *i.e.* `WINAPI_FAMILY`.  Only useful when compared to a family value, or masked with a set of partitions.
+    */
+    CurrentFamily,
+    /**
+    A specific family value.  Generated by `WINAPI_FAMILY_*` identifiers.
+    */
+    FamilyValue(Family),
+    /**
+    The current family, masked with a set of partitions (*i.e.* `WINAPI_FAMILY & _`).  Only useful when compared to a set of partitions.
+    */
+    MaskedFamily(Partitions),
+    /**
+    The abstract value of a tracked define.  Only useful when compared to an integer, or used as a condition.
+    */
+    Define(Rc<TrackedDefine>),
+    /**
     An ignorable value.
     */
     Ignore,
@@ -96,6 +117,7 @@ impl Value {
                 let wv = WinVersion::from_u32_round_up(v << 16).expect("valid full version");
                 Ok(WinVersions::from(wv).into())
             },
+            Define(d) => Value::define_test(&d, |v| v != 0).to_features(),
             FullVersion
             | ShortVersion
             | Ignore => Ok(Features::default()),
@@ -103,12 +125,43 @@ impl Value {
         }
     }
 
+    /**
+    Constructs the value of a test on the current family, given which families it's true for.
+    */
+    fn from_family_test<F>(pred: F) -> Value
+    where F: Fn(Family) -> bool {
+        match Family::features_where(pred) {
+            Some(parts) => Value::Feat(parts.into()),
+            None => Value::Bool(false)
+        }
+    }
+
+    /**
+    Constructs the value of a test on a tracked define, given which values it's true for.
+    */
+    fn define_test<F>(define: &Rc<TrackedDefine>, pred: F) -> Value
+    where F: Fn(u32) -> bool {
+        Value::Feat(DefineSet::value_where(define, pred).into())
+    }
+
+    /**
+    Converts tracked defines into the feature set for which they're non-zero, leaving other values alone.
+
+    This is used wherever a value is being used as a condition.
+    */
+    fn truthy(self) -> Value {
+        match self {
+            Value::Define(d) => Value::define_test(&d, |v| v != 0),
+            v => v
+        }
+    }
+
     /**
     Computes the complement of this value.
     */
     pub fn complement(self) -> Result<Value, String> {
         use self::Value::*;
-        match self {
+        match self.truthy() {
             Ignore => Ok(Ignore),
             Bool(b) => Ok(Bool(!b)),
             Feat(f) => Ok(Feat(f.complement())),
@@ -122,7 +175,7 @@ impl Value {
     */
     pub fn and(self, rhs: Value) -> Result<Value, String> {
         use self::Value::*;
-        match (self, rhs) {
+        match (self.truthy(), rhs.truthy()) {
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Bool(b)) | (Bool(b), Ignore) => Ok(Bool(b)),
             (Ignore, Feat(f)) | (Feat(f), Ignore) => Ok(Feat(f)),
@@ -139,7 +192,7 @@ impl Value {
     */
     pub fn or(self, rhs: Value) -> Result<Value, String> {
         use self::Value::*;
-        match (self, rhs) {
+        match (self.truthy(), rhs.truthy()) {
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Bool(b)) | (Bool(b), Ignore) => Ok(Bool(b)),
             (Ignore, Feat(f)) | (Feat(f), Ignore) => Ok(Feat(f)),
@@ -149,36 +202,132 @@ impl Value {
         }
     }
 
+    /**
+    Computes the result of a `_ ? _ : _` expression with this value as the condition.
+
+    If the condition is a feature set, the result is the union of "the condition and the first branch" with "not the condition and the second branch".  This means that `(cond) ? 1 : 0` is just `cond`.
+    */
+    pub fn select(self, then_value: Value, else_value: Value) -> Result<Value, String> {
+        use self::Value::*;
+
+        fn to_bool(v: Value) -> Value {
+            match v {
+                Int(i) => Bool(i != 0),
+                v => v
+            }
+        }
+
+        match self.truthy() {
+            Bool(b) => Ok(if b { then_value } else { else_value }),
+            Int(i) => Ok(if i != 0 { then_value } else { else_value }),
+            Ignore if then_value == else_value => Ok(then_value),
+            Ignore => Ok(Ignore),
+            Part(p) => Feat(p.into()).select(then_value, else_value),
+            Feat(f) => {
+                let then_value = try!(Feat(f.clone()).and(to_bool(then_value)));
+                let else_value = try!(Feat(f.complement()).and(to_bool(else_value)));
+                then_value.or(else_value)
+            },
+            n => Err(format!("invalid op: {:?} ? _ : _", n))
+        }
+    }
+
+    /**
+    Computes the bitwise or of two values.
+    */
+    pub fn bit_or(self, rhs: Value) -> Result<Value, String> {
+        use self::Value::*;
+        match (self, rhs) {
+            (Int(l), Int(r)) => Ok(Int(l | r)),
+            (FullVersionValue(v), Int(i)) | (Int(i), FullVersionValue(v)) => Ok(FullVersionValue(v | i)),
+            (Ignore, Ignore) => Ok(Ignore),
+            (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
+            (l, r) => Err(format!("invalid op: {:?} | {:?}", l, r))
+        }
+    }
+
+    /**
+    Computes the bitwise exclusive or of two values.
+    */
+    pub fn bit_xor(self, rhs: Value) -> Result<Value, String> {
+        use self::Value::*;
+        match (self, rhs) {
+            (Int(l), Int(r)) => Ok(Int(l ^ r)),
+            (Ignore, Ignore) => Ok(Ignore),
+            (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
+            (l, r) => Err(format!("invalid op: {:?} ^ {:?}", l, r))
+        }
+    }
+
+    /**
+    Computes the bitwise and of two values.
+
+    Masking the abstract full version with `0xFFFF0000` is the same as taking its OS version.
+    */
+    pub fn bit_and(self, rhs: Value) -> Result<Value, String> {
+        use self::Value::*;
+        match (self, rhs) {
+            (Int(l), Int(r)) => Ok(Int(l & r)),
+            (FullVersion, Int(0xFFFF_0000)) | (Int(0xFFFF_0000), FullVersion) => Ok(OsVersion),
+            (CurrentFamily, Part(p)) | (Part(p), CurrentFamily) => Ok(MaskedFamily(p)),
+            (CurrentFamily, FamilyValue(f)) | (FamilyValue(f), CurrentFamily) => Ok(MaskedFamily(f.partitions())),
+            (FullVersionValue(v), Int(i)) | (Int(i), FullVersionValue(v)) => Ok(FullVersionValue(v & i)),
+            (Ignore, Ignore) => Ok(Ignore),
+            (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
+            (l, r) => Err(format!("invalid op: {:?} & {:?}", l, r))
+        }
+    }
+
+    /**
+    Computes the sum of two values.
+    */
+    pub fn add(self, rhs: Value) -> Result<Value, String> {
+        use self::Value::*;
+        match (self, rhs) {
+            (Int(l), Int(r)) => Ok(Int(l.wrapping_add(r))),
+            (FullVersionValue(v), Int(i)) | (Int(i), FullVersionValue(v)) => Ok(FullVersionValue(v.wrapping_add(i))),
+            (ShortVersionValue(v), Int(i)) | (Int(i), ShortVersionValue(v)) => Ok(ShortVersionValue(v.wrapping_add(i))),
+            (Ignore, Ignore) => Ok(Ignore),
+            (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
+            (l, r) => Err(format!("invalid op: {:?} + {:?}", l, r))
+        }
+    }
+
+    /**
+    Computes the difference of two values.
+    */
+    pub fn sub(self, rhs: Value) -> Result<Value, String> {
+        use self::Value::*;
+        match (self, rhs) {
+            (Int(l), Int(r)) => Ok(Int(l.wrapping_sub(r))),
+            (FullVersionValue(v), Int(i)) => Ok(FullVersionValue(v.wrapping_sub(i))),
+            (ShortVersionValue(v), Int(i)) => Ok(ShortVersionValue(v.wrapping_sub(i))),
+            (Ignore, Ignore) => Ok(Ignore),
+            (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
+            (l, r) => Err(format!("invalid op: {:?} - {:?}", l, r))
+        }
+    }
+
     /**
     Computes the feature set given by an equality comparison of two values.
     */
     pub fn eq(self, rhs: Value) -> Result<Value, String> {
         use self::Value::*;
+        if let Some(v) = self.version_cmp(&rhs, CmpOp::Eq) {
+            return Ok(v);
+        }
         match (self, rhs) {
-            (FullVersion, FullVersionValue(i)) => {
-                let start = WinVersion::from_u32_round_up(i).expect("valid version for fs == fvv");
-                let end = WinVersion::from_u32_round_up((i & 0xFFFF_0000) + 1);
-                let wv = match end {
-                    Some(end) => WinVersions::from(start..end),
-                    None => WinVersions::from(start..)
-                };
-                Ok(Feat(wv.into()))
+            (CurrentFamily, FamilyValue(f)) | (FamilyValue(f), CurrentFamily) => {
+                Ok(Feat(f.feature().into()))
             },
-            (OsVersion, FullVersionValue(i)) => {
-                let start = WinVersion::from_u32_round_up(i).expect("valid version for os == fvv");
-                let end = WinVersion::from_u32_round_up((i & 0xFFFF_0000) + 0x1_0000);
-                let wv = match end {
-                    Some(end) => WinVersions::from(start..end),
-                    None => WinVersions::from(start..)
-                };
-                Ok(Feat(wv.into()))
+            (MaskedFamily(mask), Part(p)) | (Part(p), MaskedFamily(mask)) => {
+                Ok(Value::from_family_test(|f| f.partitions() & mask == p))
             },
-            (ShortVersion, Int(v)) | (Int(v), ShortVersion) => {
-                let start = WinVersion::from_u32_round_up(v << 16).expect("valid version for sv == int");
-                let end = WinVersion::from_u32_round_up(((v << 16) + 0x1_0000));
-                let wv = WinVersions::from(Some(start)..end);
-                Ok(Feat(wv.into()))
-            }
+            (MaskedFamily(mask), FamilyValue(v)) | (FamilyValue(v), MaskedFamily(mask)) => {
+                Ok(Value::from_family_test(|f| f.partitions() & mask == v.partitions()))
+            },
+            (Define(d), Int(i)) => Ok(Value::define_test(&d, |v| v == i)),
+            (Int(i), Define(d)) => Ok(Value::define_test(&d, |v| i == v)),
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
             (l, r) => Err(format!("invalid op: {:?} == {:?}", l, r))
@@ -190,25 +339,16 @@ impl Value {
     */
     pub fn ne(self, rhs: Value) -> Result<Value, String> {
         use self::Value::*;
+        if let Some(v) = self.version_cmp(&rhs, CmpOp::Ne) {
+            return Ok(v);
+        }
         match (self, rhs) {
-            (OsVersion, FullVersionValue(i)) => {
-                let start = WinVersion::from_u32_round_up(i).expect("valid full os version for !=");
-                let end = WinVersion::from_u32_round_up((i & 0xFFFF_0000) + 0x1_0000);
-                let wv = match end {
-                    Some(end) => WinVersions::from((..start, end..)),
-                    None => WinVersions::from(..start)
-                };
-                Ok(Feat(wv.into()))
-            },
-            (ShortVersion, ShortVersionValue(i)) => {
-                let start = WinVersion::from_u32_round_up(i << 16).expect("valid short version for !=");
-                let end = start.next_version();
-                let wv = match end {
-                    Some(end) => WinVersions::from((..start, end..)),
-                    None => WinVersions::from(..start)
-                };
-                Ok(Feat(wv.into()))
+            (l @ CurrentFamily, r) | (l @ MaskedFamily(_), r)
+            | (l, r @ CurrentFamily) | (l, r @ MaskedFamily(_)) => {
+                try!(l.eq(r)).complement()
             },
+            (Define(d), Int(i)) => Ok(Value::define_test(&d, |v| v != i)),
+            (Int(i), Define(d)) => Ok(Value::define_test(&d, |v| i != v)),
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
             (l, r) => Err(format!("invalid op: {:?} != {:?}", l, r))
@@ -220,15 +360,12 @@ impl Value {
     */
     pub fn lt(self, rhs: Value) -> Result<Value, String> {
         use self::Value::*;
+        if let Some(v) = self.version_cmp(&rhs, CmpOp::Lt) {
+            return Ok(v);
+        }
         match (self, rhs) {
-            (FullVersion, FullVersionValue(i)) | (FullVersion, Int(i)) => {
-                let end = WinVersion::from_u32_round_up(i).expect("valid full version for <");
-                Ok(Feat(WinVersions::from(..end).into()))
-            },
-            (ShortVersion, ShortVersionValue(i)) | (ShortVersion, Int(i)) => {
-                let end = WinVersion::from_u32_round_up(i << 16).expect("valid short version for <");
-                Ok(Feat(WinVersions::from(..end).into()))
-            },
+            (Define(d), Int(i)) => Ok(Value::define_test(&d, |v| v < i)),
+            (Int(i), Define(d)) => Ok(Value::define_test(&d, |v| i < v)),
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
             (l, r) => Err(format!("invalid op: {:?} < {:?}", l, r))
@@ -240,15 +377,12 @@ impl Value {
     */
     pub fn le(self, rhs: Value) -> Result<Value, String> {
         use self::Value::*;
+        if let Some(v) = self.version_cmp(&rhs, CmpOp::Le) {
+            return Ok(v);
+        }
         match (self, rhs) {
-            (FullVersion, FullVersionValue(i)) | (FullVersion, Int(i)) => {
-                let end = WinVersion::from_u32_round_up(i + 1).expect("valid full version for <=");
-                Ok(Feat(WinVersions::from(..end).into()))
-            },
-            (ShortVersion, ShortVersionValue(i)) | (ShortVersion, Int(i)) => {
-                let end = WinVersion::from_u32_round_up((i << 16) + 1).expect("valid short version for <=");
-                Ok(Feat(WinVersions::from(..end).into()))
-            },
+            (Define(d), Int(i)) => Ok(Value::define_test(&d, |v| v <= i)),
+            (Int(i), Define(d)) => Ok(Value::define_test(&d, |v| i <= v)),
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
             (l, r) => Err(format!("invalid op: {:?} <= {:?}", l, r))
@@ -260,15 +394,12 @@ impl Value {
     */
     pub fn gt(self, rhs: Value) -> Result<Value, String> {
         use self::Value::*;
+        if let Some(v) = self.version_cmp(&rhs, CmpOp::Gt) {
+            return Ok(v);
+        }
         match (self, rhs) {
-            (FullVersion, FullVersionValue(i)) | (FullVersion, Int(i)) => {
-                let start = WinVersion::from_u32_round_up(i + 1).expect("valid full version for >");
-                Ok(Feat(WinVersions::from(start..).into()))
-            },
-            (ShortVersion, ShortVersionValue(i)) | (ShortVersion, Int(i)) => {
-                let start = WinVersion::from_u32_round_up((i << 16) + 1).expect("valid full version for >");
-                Ok(Feat(WinVersions::from(start..).into()))
-            },
+            (Define(d), Int(i)) => Ok(Value::define_test(&d, |v| v > i)),
+            (Int(i), Define(d)) => Ok(Value::define_test(&d, |v| i > v)),
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
             (l, r) => Err(format!("invalid op: {:?} > {:?}", l, r))
@@ -280,21 +411,74 @@ impl Value {
     */
     pub fn ge(self, rhs: Value) -> Result<Value, String> {
         use self::Value::*;
+        if let Some(v) = self.version_cmp(&rhs, CmpOp::Ge) {
+            return Ok(v);
+        }
         match (self, rhs) {
-            (FullVersion, FullVersionValue(i)) | (FullVersion, Int(i)) => {
-                let start = WinVersion::from_u32_round_up(i).expect("valid full version for >=");
-                Ok(Feat(WinVersions::from(start..).into()))
-            },
-            (ShortVersion, ShortVersionValue(i)) | (ShortVersion, Int(i)) => {
-                let start = WinVersion::from_u32_round_up(i << 16).expect("valid full version for >=");
-                Ok(Feat(WinVersions::from(start..).into()))
-            },
+            (Define(d), Int(i)) => Ok(Value::define_test(&d, |v| v >= i)),
+            (Int(i), Define(d)) => Ok(Value::define_test(&d, |v| i >= v)),
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
             (l, r) => Err(format!("invalid op: {:?} >= {:?}", l, r))
         }
     }
 
+    /**
+    Compares an abstract version against a concrete value, if that's what these are.
+
+    Each abstract version is a projection of the full version (*e.g.* `OSVER(NTDDI_VERSION)` is `NTDDI_VERSION & 0xFFFF0000`), so the result is the set of known versions whose projection satisfies the comparison.  As with the C pre-processor, the concrete value is compared as a plain integer, regardless of what kind of version it is.
+
+    Returns `None` if neither side is an abstract version, or the other side isn't concrete.
+    */
+    fn version_cmp(&self, rhs: &Value, op: CmpOp) -> Option<Value> {
+        let (abs, value, op) = match (self.project(0), rhs.project(0)) {
+            (Some(_), None) => match rhs.concrete_int() {
+                Some(value) => (self, value, op),
+                None => return None
+            },
+            (None, Some(_)) => match self.concrete_int() {
+                Some(value) => (rhs, value, op.flip()),
+                None => return None
+            },
+            _ => return None
+        };
+
+        let wv = WinVersions::from_pred(|v| op.apply(abs.project(v).unwrap(), value));
+        if wv.is_any() {
+            Some(Value::Feat(wv.into()))
+        } else {
+            Some(Value::Bool(false))
+        }
+    }
+
+    /**
+    Projects a full version number through this abstract version.
+
+    Returns `None` if this isn't an abstract version.
+    */
+    fn project(&self, v: u32) -> Option<u32> {
+        use self::Value::*;
+        match *self {
+            FullVersion => Some(v),
+            ShortVersion => Some(v >> 16),
+            OsVersion => Some(v & 0xFFFF_0000),
+            SpVersion => Some((v & 0x0000_FF00) >> 8),
+            SubVersion => Some(v & 0x0000_00FF),
+            _ => None
+        }
+    }
+
+    /**
+    Returns the integer this value would have in the C pre-processor, if it has one.
+    */
+    fn concrete_int(&self) -> Option<u32> {
+        use self::Value::*;
+        match *self {
+            Int(v) | FullVersionValue(v) | ShortVersionValue(v) => Some(v),
+            _ => None
+        }
+    }
+
     /**
     Computes the result of a right-shift of a value.
     */
@@ -302,12 +486,27 @@ impl Value {
         use self::Value::*;
         match (self, rhs) {
             (FullVersionValue(v), Int(16)) => Ok(ShortVersionValue(v >> 16)),
+            (Int(l), Int(r)) if r < 32 => Ok(Int(l >> r)),
             (Ignore, Ignore) => Ok(Ignore),
             (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
             (l, r) => Err(format!("invalid op: {:?} >> {:?}", l, r))
         }
     }
 
+    /**
+    Computes the result of a left-shift of a value.
+    */
+    pub fn ls(self, rhs: Value) -> Result<Value, String> {
+        use self::Value::*;
+        match (self, rhs) {
+            (ShortVersionValue(v), Int(16)) => Ok(FullVersionValue(v << 16)),
+            (Int(l), Int(r)) if r < 32 => Ok(Int(l << r)),
+            (Ignore, Ignore) => Ok(Ignore),
+            (Ignore, Int(_)) | (Int(_), Ignore) => Ok(Ignore),
+            (l, r) => Err(format!("invalid op: {:?} << {:?}", l, r))
+        }
+    }
+
     /**
     Computes the result of taking the "os version" of this value.
     */
@@ -327,10 +526,23 @@ impl Value {
         use self::Value::*;
         match self {
             FullVersion => Ok(SpVersion),
+            FullVersionValue(v) => Ok(Int((v & 0x0000_FF00) >> 8)),
             n => Err(format!("invalid op: SPVER({:?})", n))
         }
     }
 
+    /**
+    Computes the result of taking the "sub-version" of this value.
+    */
+    pub fn sub_ver(self) -> Result<Value, String> {
+        use self::Value::*;
+        match self {
+            FullVersion => Ok(SubVersion),
+            FullVersionValue(v) => Ok(Int(v & 0x0000_00FF)),
+            n => Err(format!("invalid op: SUBVER({:?})", n))
+        }
+    }
+
     /**
     Attempts to ignore this value.
     */
@@ -342,3 +554,146 @@ impl Value {
         }
     }
 }
+
+/**
+A comparison operator.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    /// Gives the operator which results from swapping the operands.
+    fn flip(self) -> CmpOp {
+        use self::CmpOp::*;
+        match self {
+            Eq => Eq,
+            Ne => Ne,
+            Lt => Gt,
+            Le => Ge,
+            Gt => Lt,
+            Ge => Le,
+        }
+    }
+
+    fn apply(self, l: u32, r: u32) -> bool {
+        use self::CmpOp::*;
+        match self {
+            Eq => l == r,
+            Ne => l != r,
+            Lt => l < r,
+            Le => l <= r,
+            Gt => l > r,
+            Ge => l >= r,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use WinVersion::*;
+    use features::{EvalContext, WinVersions};
+    use features::cc::parse_conditional_expr;
+    use super::Value;
+
+    /// Evaluates an expression whose tokens are separated by whitespace.
+    fn eval(expr: &str) -> Value {
+        let toks: Vec<&str> = expr.split_whitespace().collect();
+        let ctx = EvalContext { tracked_defines: &[], lenient: false };
+        let (node, tail) = parse_conditional_expr(&toks).unwrap().expect("parsed expression");
+        assert!(tail.len() == 0, "leftover tokens: {:?}", tail);
+        node.eval(ctx).unwrap()
+    }
+
+    fn winver(expr: &str) -> WinVersions {
+        eval(expr).to_features().unwrap().winver.expect("winver feature")
+    }
+
+    #[test]
+    fn test_full_version() {
+        assert_eq!(winver("NTDDI_VERSION >= NTDDI_WIN7"), WinVersions::from(WIN7..));
+        assert_eq!(winver("( NTDDI_VERSION >= NTDDI_WINXPSP2 )"), WinVersions::from(WINXPSP2..));
+        assert_eq!(winver("NTDDI_VERSION > NTDDI_WINBLUE"), WinVersions::from(WINTHRESHOLD..));
+        assert_eq!(winver("NTDDI_VERSION < NTDDI_WIN8"), WinVersions::from(..WIN8));
+        assert_eq!(winver("NTDDI_VERSION <= NTDDI_WIN7"), WinVersions::from(..WIN7SP1));
+        assert_eq!(winver("NTDDI_VERSION == NTDDI_WIN7"), WinVersions::from(WIN7..WIN7SP1));
+        assert_eq!(winver("NTDDI_VERSION != NTDDI_WIN7"), WinVersions::from((..WIN7, WIN7SP1..)));
+        assert_eq!(winver("NTDDI_VERSION >= NTDDI_WIN10_RS1"), WinVersions::from(WIN10_RS1..));
+        assert_eq!(winver("NTDDI_VERSION >= 0x06010000"), WinVersions::from(WIN7..));
+    }
+
+    #[test]
+    fn test_full_version_reversed() {
+        assert_eq!(winver("NTDDI_WIN7 <= NTDDI_VERSION"), WinVersions::from(WIN7..));
+        assert_eq!(winver("NTDDI_WIN8 > NTDDI_VERSION"), WinVersions::from(..WIN8));
+    }
+
+    #[test]
+    fn test_full_version_range() {
+        assert_eq!(winver("NTDDI_VERSION >= NTDDI_WIN7 && NTDDI_VERSION < NTDDI_WIN8"),
+            WinVersions::from(WIN7..WIN8));
+        assert_eq!(winver("( NTDDI_VERSION >= NTDDI_WIN10_RS2 ) && ( NTDDI_VERSION < NTDDI_WIN10_RS4 )"),
+            WinVersions::from(WIN10_RS2..WIN10_RS4));
+    }
+
+    #[test]
+    fn test_short_version() {
+        assert_eq!(winver("_WIN32_WINNT >= _WIN32_WINNT_WIN7"), WinVersions::from(WIN7..));
+        assert_eq!(winver("WINVER >= 0x0500"), WinVersions::from(WIN2K..));
+        assert_eq!(winver("_WIN32_WINNT < 0x0600"), WinVersions::from(..WIN6));
+        assert_eq!(winver("_WIN32_WINNT == _WIN32_WINNT_WIN6"), WinVersions::from(WIN6..WIN7));
+        assert_eq!(winver("_WIN32_WINNT >= _WIN32_WINNT_WIN10"), WinVersions::from(WINTHRESHOLD..));
+        assert_eq!(winver("NTDDI_VERSION >= ( _WIN32_WINNT_WIN8 << 16 )"), WinVersions::from(WIN8..));
+    }
+
+    #[test]
+    fn test_os_version() {
+        assert_eq!(winver("OSVER ( NTDDI_VERSION ) >= NTDDI_WIN7"), WinVersions::from(WIN7..));
+        assert_eq!(winver("OSVER ( NTDDI_VERSION ) == NTDDI_WIN2K"), WinVersions::from(WIN2K..WINXP));
+        assert_eq!(winver("OSVER ( NTDDI_VERSION ) > NTDDI_WIN2K"), WinVersions::from(WINXP..));
+        assert_eq!(winver("OSVER ( NTDDI_VERSION ) != NTDDI_WIN7"), WinVersions::from((..WIN7, WIN8..)));
+        assert_eq!(winver("( NTDDI_VERSION & 0xFFFF0000 ) == NTDDI_WIN6"), WinVersions::from(WIN6..WIN7));
+    }
+
+    #[test]
+    fn test_sp_version() {
+        let wv = winver("SPVER ( NTDDI_VERSION ) >= 1");
+        for &v in &[WIN2KSP1, WIN2KSP4, WINXPSP2, WS03SP1, WIN6SP1, WIN7SP1] {
+            assert!(wv.contains(v as u32), "{:?} should be included", v);
+        }
+        for &v in &[WIN2K, WINXP, WS03, WIN6, WIN7, WIN8, WINBLUE, WINTHRESHOLD] {
+            assert!(!wv.contains(v as u32), "{:?} should not be included", v);
+        }
+
+        assert_eq!(eval("SPVER ( NTDDI_WIN7SP1 )"), Value::Int(1));
+    }
+
+    #[test]
+    fn test_mixed_versions() {
+        assert_eq!(
+            winver("( NTDDI_VERSION >= NTDDI_WINXP ) || ( ( OSVER ( NTDDI_VERSION ) == NTDDI_WIN2K ) && ( SPVER ( NTDDI_VERSION ) >= SPVER ( NTDDI_WIN2KSP4 ) ) )"),
+            WinVersions::from(WIN2KSP4..));
+        assert_eq!(
+            winver("SPVER ( NTDDI_VERSION ) >= SPVER ( NTDDI_WIN7SP1 ) && OSVER ( NTDDI_VERSION ) == NTDDI_WIN7"),
+            WinVersions::from(WIN7SP1..WIN8));
+    }
+
+    #[test]
+    fn test_sub_version() {
+        let wv = winver("SUBVER ( NTDDI_VERSION ) >= 2");
+        assert!(wv.contains(WIN10_RS1 as u32));
+        assert!(!wv.contains(WIN10_TH2 as u32));
+        assert!(!wv.contains(WIN7 as u32));
+    }
+
+    #[test]
+    fn test_impossible_version() {
+        assert_eq!(eval("NTDDI_VERSION < 0"), Value::Bool(false));
+    }
+}
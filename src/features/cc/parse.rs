@@ -78,7 +78,20 @@ pub type ParseResult<'a, S> = Result<Option<(Node, &'a [S])>, String>;
 pub fn parse_conditional_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
     debug!("parse_conditional_expr({:?})", toks);
     parse_guard!(toks);
-    parse_logical_or_expr(toks)
+
+    parse_logical_or_expr(toks).ro_and_then(|(cond, toks)|
+        (parse_munch(toks, "?")
+            .ro_and_then(|toks| parse_conditional_expr(toks))
+            .ro_and_then(|(then_expr, toks)| parse_munch(toks, ":")
+                .ro_and_then(|toks| parse_conditional_expr(toks))
+                .ro_and_then(|(else_expr, tail)| Ok(Some((
+                    Node::Cond(Box::new(cond.clone()), Box::new(then_expr), Box::new(else_expr)),
+                    tail
+                ))))
+            )
+        )
+        .ro_or_else(|| Ok(Some((cond, toks))))
+    )
 }
 
 fn parse_logical_or_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
@@ -98,7 +111,7 @@ fn parse_logical_and_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
     debug!("parse_logical_and_expr({:?})", toks);
     parse_guard!(toks);
 
-    parse_equality_expr(toks).ro_and_then(|(lhs, toks)|
+    parse_inclusive_or_expr(toks).ro_and_then(|(lhs, toks)|
         (parse_munch(toks, "&&")
             .ro_and_then(|toks| parse_logical_and_expr(toks))
             .ro_and_then(|(rhs, tail)| Ok(Some((Node::And(Box::new(lhs.clone()), Box::new(rhs)), tail))))
@@ -107,6 +120,45 @@ fn parse_logical_and_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
     )
 }
 
+fn parse_inclusive_or_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
+    debug!("parse_inclusive_or_expr({:?})", toks);
+    parse_guard!(toks);
+
+    parse_exclusive_or_expr(toks).ro_and_then(|(lhs, toks)|
+        (parse_munch(toks, "|")
+            .ro_and_then(|toks| parse_inclusive_or_expr(toks))
+            .ro_and_then(|(rhs, tail)| Ok(Some((Node::BitOr(Box::new(lhs.clone()), Box::new(rhs)), tail))))
+        )
+        .ro_or_else(|| Ok(Some((lhs, toks))))
+    )
+}
+
+fn parse_exclusive_or_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
+    debug!("parse_exclusive_or_expr({:?})", toks);
+    parse_guard!(toks);
+
+    parse_and_expr(toks).ro_and_then(|(lhs, toks)|
+        (parse_munch(toks, "^")
+            .ro_and_then(|toks| parse_exclusive_or_expr(toks))
+            .ro_and_then(|(rhs, tail)| Ok(Some((Node::BitXor(Box::new(lhs.clone()), Box::new(rhs)), tail))))
+        )
+        .ro_or_else(|| Ok(Some((lhs, toks))))
+    )
+}
+
+fn parse_and_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
+    debug!("parse_and_expr({:?})", toks);
+    parse_guard!(toks);
+
+    parse_equality_expr(toks).ro_and_then(|(lhs, toks)|
+        (parse_munch(toks, "&")
+            .ro_and_then(|toks| parse_and_expr(toks))
+            .ro_and_then(|(rhs, tail)| Ok(Some((Node::BitAnd(Box::new(lhs.clone()), Box::new(rhs)), tail))))
+        )
+        .ro_or_else(|| Ok(Some((lhs, toks))))
+    )
+}
+
 fn parse_equality_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
     debug!("parse_equality_expr({:?})", toks);
     parse_guard!(toks);
@@ -149,17 +201,52 @@ fn parse_relational_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
     )
 }
 
+/**
+Like the additive operators, these are left-associative: `a >> b << c` is `(a >> b) << c`.
+*/
 fn parse_shift_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
     debug!("parse_shift_expr({:?})", toks);
     parse_guard!(toks);
 
-    parse_unary_expr(toks).ro_and_then(|(lhs, toks)|
-        (parse_munch(toks, ">>")
-            .ro_and_then(|toks| parse_shift_expr(toks))
-            .ro_and_then(|(rhs, tail)| Ok(Some((Node::Rs(Box::new(lhs.clone()), Box::new(rhs)), tail))))
-        )
-        .ro_or_else(|| Ok(Some((lhs, toks))))
+    parse_additive_expr(toks).ro_and_then(|(lhs, toks)| parse_shift_tail(lhs, toks))
+}
+
+fn parse_shift_tail<S: AsRef<str> + Debug>(lhs: Node, toks: &[S]) -> ParseResult<S> {
+    debug!("parse_shift_tail({:?}, {:?})", lhs, toks);
+
+    (parse_munch(toks, ">>")
+        .ro_and_then(|toks| parse_additive_expr(toks))
+        .ro_and_then(|(rhs, tail)| parse_shift_tail(Node::Rs(Box::new(lhs.clone()), Box::new(rhs)), tail))
+    )
+    .ro_or_else(|| parse_munch(toks, "<<")
+        .ro_and_then(|toks| parse_additive_expr(toks))
+        .ro_and_then(|(rhs, tail)| parse_shift_tail(Node::Ls(Box::new(lhs.clone()), Box::new(rhs)), tail))
+    )
+    .ro_or_else(|| Ok(Some((lhs, toks))))
+}
+
+/**
+Unlike the other binary operators, these have to be parsed left-associatively, since `a - b - c` is *not* `a - (b - c)`.
+*/
+fn parse_additive_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
+    debug!("parse_additive_expr({:?})", toks);
+    parse_guard!(toks);
+
+    parse_unary_expr(toks).ro_and_then(|(lhs, toks)| parse_additive_tail(lhs, toks))
+}
+
+fn parse_additive_tail<S: AsRef<str> + Debug>(lhs: Node, toks: &[S]) -> ParseResult<S> {
+    debug!("parse_additive_tail({:?}, {:?})", lhs, toks);
+
+    (parse_munch(toks, "+")
+        .ro_and_then(|toks| parse_unary_expr(toks))
+        .ro_and_then(|(rhs, tail)| parse_additive_tail(Node::Add(Box::new(lhs.clone()), Box::new(rhs)), tail))
+    )
+    .ro_or_else(|| parse_munch(toks, "-")
+        .ro_and_then(|toks| parse_unary_expr(toks))
+        .ro_and_then(|(rhs, tail)| parse_additive_tail(Node::Sub(Box::new(lhs.clone()), Box::new(rhs)), tail))
     )
+    .ro_or_else(|| Ok(Some((lhs, toks))))
 }
 
 fn parse_unary_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
@@ -187,6 +274,13 @@ fn parse_unary_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
             .ro_and_then(|tail| Ok(Some((Node::SpVer(Box::new(node)), tail))))
         )
     )
+    .ro_or_else(|| parse_munch(toks, "SUBVER")
+        .ro_and_then(|toks| parse_munch(toks, "("))
+        .ro_and_then(|toks| parse_conditional_expr(toks))
+        .ro_and_then(|(node, toks)| parse_munch(toks, ")")
+            .ro_and_then(|tail| Ok(Some((Node::SubVer(Box::new(node)), tail))))
+        )
+    )
     .ro_or_else(|| parse_munch(toks, "WINAPI_FAMILY_PARTITION")
         .ro_and_then(|toks| parse_munch(toks, "("))
         .ro_and_then(|toks| parse_conditional_expr(toks))
@@ -195,13 +289,13 @@ fn parse_unary_expr<S: AsRef<str> + Debug>(toks: &[S]) -> ParseResult<S> {
         )
     )
     .ro_or_else(|| parse_munch(toks, "WINAPI_FAMILY_ONE_PARTITION")
-        /*
-        This basically does `((WINAPI_FAMILY & arg0) == arg1)`.  As far as I know, it's only used in *one* place: to determine if the family is *exactly* `WINAPI_PARTITION_APP`... but it doesn't appear to actually make any real difference in terms of our binding work, so we'll just ignore it.
-        */
         .ro_and_then(|toks| parse_munch(toks, "("))
         .ro_and_then(|toks| parse_conditional_expr(toks))
-        .ro_and_then(|(_, toks)| parse_munch(toks, ")")
-            .ro_and_then(|tail| Ok(Some((Node::Ignore, tail))))
+        .ro_and_then(|(set, toks)| parse_munch(toks, ",")
+            .ro_and_then(|toks| parse_conditional_expr(toks))
+            .ro_and_then(|(part, toks)| parse_munch(toks, ")")
+                .ro_and_then(|tail| Ok(Some((Node::OnePart(Box::new(set.clone()), Box::new(part)), tail))))
+            )
         )
     )
     .ro_or_else(|| parse_ident(toks)
@@ -0,0 +1,142 @@
+/*!
+Defines the `Cfg` expression type, used to render feature sets as `#[cfg]` attributes.
+*/
+use std::fmt;
+
+/**
+A `#[cfg]` predicate.
+
+Note that `All(vec![])` is always true, and `Any(vec![])` is always false, just as with Rust itself.
+*/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Cfg {
+    Feature(String),
+    TargetArch(&'static str),
+    Not(Box<Cfg>),
+    All(Vec<Cfg>),
+    Any(Vec<Cfg>),
+}
+
+impl Cfg {
+    pub fn feature<S: Into<String>>(s: S) -> Cfg {
+        Cfg::Feature(s.into())
+    }
+
+    /// Negates this predicate, cancelling out double negation.
+    pub fn not(self) -> Cfg {
+        match self {
+            Cfg::Not(c) => *c,
+            c => Cfg::Not(Box::new(c))
+        }
+    }
+
+    /// Returns `true` if this predicate is trivially true.
+    pub fn is_true(&self) -> bool {
+        match *self {
+            Cfg::All(ref cs) => cs.len() == 0,
+            _ => false
+        }
+    }
+
+    /// Returns `true` if this predicate is trivially false.
+    pub fn is_false(&self) -> bool {
+        match *self {
+            Cfg::Any(ref cs) => cs.len() == 0,
+            _ => false
+        }
+    }
+
+    /**
+    Rewrites this predicate into a shorter, equivalent form.
+
+    This flattens nested `all`/`any`s, removes duplicate and trivial terms, and unwraps single-element lists.
+    */
+    pub fn simplify(self) -> Cfg {
+        match self {
+            Cfg::Not(c) => {
+                let c = c.simplify();
+                if c.is_true() {
+                    Cfg::Any(vec![])
+                } else if c.is_false() {
+                    Cfg::All(vec![])
+                } else {
+                    c.not()
+                }
+            },
+            Cfg::All(cs) => {
+                let mut terms = vec![];
+                for c in cs {
+                    match c.simplify() {
+                        Cfg::All(inner) => extend_unique(&mut terms, inner),
+                        c => extend_unique(&mut terms, vec![c])
+                    }
+                }
+                if terms.iter().any(|c| c.is_false()) {
+                    Cfg::Any(vec![])
+                } else if terms.len() == 1 {
+                    terms.pop().unwrap()
+                } else {
+                    Cfg::All(terms)
+                }
+            },
+            Cfg::Any(cs) => {
+                let mut terms = vec![];
+                for c in cs {
+                    match c.simplify() {
+                        Cfg::Any(inner) => extend_unique(&mut terms, inner),
+                        c => extend_unique(&mut terms, vec![c])
+                    }
+                }
+                if terms.iter().any(|c| c.is_true()) {
+                    Cfg::All(vec![])
+                } else if terms.len() == 1 {
+                    terms.pop().unwrap()
+                } else {
+                    Cfg::Any(terms)
+                }
+            },
+            c => c
+        }
+    }
+
+    /**
+    Writes this predicate out as a `#[cfg]` attribute, followed by a space.
+
+    Nothing is written if the predicate is trivially true.
+    */
+    pub fn write_attr(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        if self.is_true() {
+            Ok(())
+        } else {
+            write!(fmt, "#[cfg({})] ", self)
+        }
+    }
+}
+
+fn extend_unique(terms: &mut Vec<Cfg>, cs: Vec<Cfg>) {
+    for c in cs {
+        if !terms.contains(&c) {
+            terms.push(c);
+        }
+    }
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fn list(fmt: &mut fmt::Formatter, name: &str, cs: &[Cfg]) -> Result<(), fmt::Error> {
+            try!(write!(fmt, "{}(", name));
+            for (i, c) in cs.iter().enumerate() {
+                try!(write!(fmt, "{}{}", if i == 0 { "" } else { ", " }, c));
+            }
+            write!(fmt, ")")
+        }
+
+        match *self {
+            Cfg::Feature(ref s) => write!(fmt, "feature={:?}", s),
+            Cfg::TargetArch(s) => write!(fmt, "target_arch={:?}", s),
+            Cfg::Not(ref c) => write!(fmt, "not({})", c),
+            Cfg::All(ref cs) => list(fmt, "all", cs),
+            Cfg::Any(ref cs) => list(fmt, "any", cs),
+        }
+    }
+}
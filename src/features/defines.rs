@@ -0,0 +1,160 @@
+/*!
+Defines the tracked define feature set component.
+
+These are extra, configurable feature dimensions for defines the processor otherwise knows nothing about (*e.g.* `_WIN32_IE` or `_DEBUG`).
+*/
+use std::fmt;
+use std::rc::Rc;
+use super::cfg::Cfg;
+
+/**
+A define whose value should be tracked as part of feature sets.
+
+The define can be in one of `values.len() + 1` states: undefined, or defined to one of the given values.  As with the C pre-processor, an undefined define is treated as `0` when used as a value.
+
+Each value comes with a cargo feature.  As with Windows versions, enabling a feature means "at least this value", so the features for all lower values should be enabled as well.
+*/
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TrackedDefine {
+    /// The name of the define.
+    pub name: String,
+
+    /// The possible values, in ascending order, along with the cargo feature for each.
+    pub values: Vec<(u32, String)>,
+}
+
+impl TrackedDefine {
+    /// The number of states this define can be in.
+    fn states(&self) -> usize {
+        self.values.len() + 1
+    }
+
+    /// The value of the define in the given state.
+    fn state_value(&self, state: usize) -> u32 {
+        if state == 0 { 0 } else { self.values[state - 1].0 }
+    }
+}
+
+/**
+A set of states for a single tracked define.
+*/
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct DefineSet {
+    define: Rc<TrackedDefine>,
+    states: u64,
+}
+
+impl DefineSet {
+    /**
+    Constructs the set of states in which the define's value satisfies `pred`.
+    */
+    pub fn value_where<F>(define: &Rc<TrackedDefine>, pred: F) -> DefineSet
+    where F: Fn(u32) -> bool {
+        assert!(define.states() <= 64, "too many values for tracked define {:?}", define.name);
+        let states = (0..define.states())
+            .filter(|&s| pred(define.state_value(s)))
+            .fold(0, |acc, s| acc | (1u64 << s));
+        DefineSet {
+            define: define.clone(),
+            states: states,
+        }
+    }
+
+    /**
+    Constructs the set of states in which the define is defined at all.
+    */
+    pub fn defined(define: &Rc<TrackedDefine>) -> DefineSet {
+        DefineSet::value_where(define, |_| true).remove_state(0)
+    }
+
+    /// The name of the define this is a set for.
+    pub fn name(&self) -> &str {
+        &self.define.name
+    }
+
+    /// Returns `true` if this set contains *any* states.
+    pub fn is_any(&self) -> bool {
+        self.states != 0
+    }
+
+    /// Returns `true` if this set contains *every* state.
+    pub fn is_all(&self) -> bool {
+        self.states == self.all_states()
+    }
+
+    /// Computes the complement of this set.
+    pub fn complement(self) -> DefineSet {
+        let all = self.all_states();
+        DefineSet {
+            states: !self.states & all,
+            define: self.define,
+        }
+    }
+
+    /// Computes the intersection of two sets.
+    pub fn intersect(self, other: DefineSet) -> DefineSet {
+        assert_eq!(self.define, other.define);
+        DefineSet {
+            states: self.states & other.states,
+            define: self.define,
+        }
+    }
+
+    /// Computes the union of two sets.
+    pub fn union(self, other: DefineSet) -> DefineSet {
+        assert_eq!(self.define, other.define);
+        DefineSet {
+            states: self.states | other.states,
+            define: self.define,
+        }
+    }
+
+    /**
+    Converts this set into a `#[cfg]` predicate.
+
+    Each run of consecutive states becomes "at least the first, but not the one after the last".
+    */
+    pub fn to_cfg(&self) -> Cfg {
+        if self.is_all() {
+            return Cfg::All(vec![]);
+        }
+
+        let n = self.define.states();
+        let has = |s: usize| self.states & (1u64 << s) != 0;
+        let feat = |s: usize| Cfg::feature(self.define.values[s - 1].1.clone());
+
+        let mut cfgs = vec![];
+        let mut s = 0;
+        while s < n {
+            if !has(s) { s += 1; continue; }
+            let first = s;
+            while s + 1 < n && has(s + 1) { s += 1; }
+            let last = s;
+
+            let mut terms = vec![];
+            if first != 0 { terms.push(feat(first)); }
+            if last + 1 != n { terms.push(feat(last + 1).not()); }
+            cfgs.push(Cfg::All(terms));
+            s += 1;
+        }
+        Cfg::Any(cfgs)
+    }
+
+    fn remove_state(self, state: usize) -> DefineSet {
+        DefineSet {
+            states: self.states & !(1u64 << state),
+            define: self.define,
+        }
+    }
+
+    fn all_states(&self) -> u64 {
+        (0..self.define.states()).fold(0, |acc, s| acc | (1u64 << s))
+    }
+}
+
+impl fmt::Display for DefineSet {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        assert!(self.is_any(), "can't have no states enabled for {:?}", self.define.name);
+        self.to_cfg().simplify().write_attr(fmt)
+    }
+}
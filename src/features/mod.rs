@@ -1,24 +1,34 @@
 /**
 Contains everything relating to the feature set abstraction.
 
-Feature sets are used to work out under what conditions something should exist.  Currently, this is based on three things:
+Feature sets are used to work out under what conditions something should exist.  Currently, this is based on four things:
 
 1. API Partitions (Desktop, Metro, Phone).
 2. Windows Versions.
 3. Architectures.
+4. Any additional defines the configuration asks us to track.
 */
+use std::collections::BTreeMap;
 use std::fmt;
+use std::rc::Rc;
 use clang;
+use {ExpConfig, WinVersion};
 
 pub mod archs;
 pub mod cc;
+pub mod cfg;
+pub mod defines;
 pub mod parts;
 pub mod scan;
 pub mod winvers;
 
+#[cfg(test)] mod tests;
+
 pub use self::archs::Architectures;
-pub use self::parts::Partitions;
-pub use self::scan::scan_features;
+pub use self::cfg::Cfg;
+pub use self::defines::{DefineSet, TrackedDefine};
+pub use self::parts::{Family, Partitions};
+pub use self::scan::{Fallback, scan_features};
 pub use self::winvers::WinVersions;
 
 /**
@@ -33,6 +43,9 @@ pub struct Features {
     pub winver: Option<WinVersions>,
     pub parts: Option<Partitions>,
     pub arch: Option<Architectures>,
+
+    /// Tracked defines, keyed by name.  A missing entry says nothing, just like `None` does for the other components.
+    pub defines: BTreeMap<String, DefineSet>,
 }
 
 impl Features {
@@ -51,6 +64,9 @@ impl Features {
         if let Some(ref arch) = self.arch {
             if !arch.is_any() { return Err("cannot have empty architecture set"); }
         }
+        if self.defines.values().any(|d| !d.is_any()) {
+            return Err("cannot have empty tracked define set");
+        }
         Ok(self)
     }
 
@@ -60,6 +76,72 @@ impl Features {
             parts: { debug!(".. parts..."); self.parts.map(|p| !p) },
             winver: { debug!(".. winver..."); self.winver.map(WinVersions::complement) },
             arch: { debug!(".. arch..."); self.arch.map(|a| !a) },
+            defines: { debug!(".. defines..."); self.defines.into_iter().map(|(k, d)| (k, d.complement())).collect() },
+        }
+    }
+
+    /// Converts this feature set into a single `#[cfg]` predicate.
+    pub fn to_cfg(&self) -> Cfg {
+        let mut cfgs = vec![];
+        if let Some(ref parts) = self.parts { cfgs.push(parts.to_cfg()); }
+        if let Some(ref winver) = self.winver { cfgs.push(winver.to_cfg()); }
+        if let Some(ref arch) = self.arch { cfgs.push(arch.to_cfg()); }
+        for define in self.defines.values() { cfgs.push(define.to_cfg()); }
+        Cfg::All(cfgs)
+    }
+
+    /**
+    Describes, in words, where this feature set is available.
+
+    Returns `None` if there are no restrictions worth mentioning.
+    */
+    pub fn describe(&self) -> Option<String> {
+        let mut parts = vec![];
+        if let Some(ver) = self.winver.as_ref().and_then(WinVersions::introduced) {
+            parts.push(format!("available since {}", version_name(ver)));
+        }
+        if let Some(ver) = self.winver.as_ref().and_then(WinVersions::removed) {
+            parts.push(format!("removed in {}", version_name(ver)));
+        }
+        if let Some(ref p) = self.parts {
+            if !p.is_all() {
+                parts.push(format!("partitions: {}", p.names().join(", ")));
+            }
+        }
+        if let Some(ref arch) = self.arch {
+            if !arch.is_all() {
+                parts.push(format!("architectures: {}", arch.names().join(", ")));
+            }
+        }
+        if parts.len() == 0 { None } else { Some(parts.join("; ")) }
+    }
+
+    /**
+    Removes any conditions which are always true across the given universe, and merges version ranges which cannot be distinguished within it.
+
+    Partitions are left alone, as they aren't something we expand for.
+    */
+    pub fn simplify(self, universe: &Universe) -> Features {
+        let arch = match self.arch {
+            Some(arch) if universe.archs.is_any() => {
+                let narrowed = arch & universe.archs;
+                if narrowed == universe.archs {
+                    None
+                } else if narrowed.is_empty() {
+                    // Doesn't exist in anything we expanded; leave it alone.
+                    Some(arch)
+                } else {
+                    Some(narrowed)
+                }
+            },
+            arch => arch
+        };
+        let winver = self.winver.and_then(|winver| winver.simplify_over(&universe.versions));
+        Features {
+            parts: self.parts,
+            winver: winver,
+            arch: arch,
+            defines: self.defines,
         }
     }
 
@@ -81,27 +163,33 @@ impl Features {
                 (Some(a), None) | (None, Some(a)) => Some(a),
                 (Some(a), Some(b)) => Some(a & b)
             },
+            defines: merge_defines(self.defines, other.defines, DefineSet::intersect),
         }
     }
 
-    /// Compute the union of two feature sets.
+    /**
+    Compute the union of two feature sets.
+
+    A component which says nothing on either side says nothing in the result.  Where the two sets differ in more than one component, this is the smallest feature set containing both, rather than their exact union.
+    */
     pub fn or(self, other: Features) -> Features {
+        let mut other_defines = other.defines;
         Features {
             parts: match (self.parts, other.parts) {
-                (None, None) => None,
-                (Some(a), None) | (None, Some(a)) => Some(a),
-                (Some(a), Some(b)) => Some(a | b)
+                (Some(a), Some(b)) => Some(a | b),
+                _ => None
             },
             winver: match (self.winver, other.winver) {
-                (None, None) => None,
-                (Some(a), None) | (None, Some(a)) => Some(a),
-                (Some(a), Some(b)) => Some(a.union(b))
+                (Some(a), Some(b)) => Some(a.union(b)),
+                _ => None
             },
             arch: match (self.arch, other.arch) {
-                (None, None) => None,
-                (Some(a), None) | (None, Some(a)) => Some(a),
-                (Some(a), Some(b)) => Some(a | b)
+                (Some(a), Some(b)) => Some(a | b),
+                _ => None
             },
+            defines: self.defines.into_iter()
+                .filter_map(|(k, l)| other_defines.remove(&k).map(|r| (k, l.union(r))))
+                .collect(),
         }
     }
 }
@@ -112,16 +200,59 @@ impl Default for Features {
             parts: None,
             winver: None,
             arch: None,
+            defines: BTreeMap::new(),
         }
     }
 }
 
+/**
+Combines two sets of tracked defines, using `f` where both sides say something about the same define.
+*/
+fn merge_defines<F>(
+    lhs: BTreeMap<String, DefineSet>,
+    rhs: BTreeMap<String, DefineSet>,
+    f: F,
+) -> BTreeMap<String, DefineSet>
+where F: Fn(DefineSet, DefineSet) -> DefineSet {
+    let mut acc = lhs;
+    for (k, r) in rhs {
+        let v = match acc.remove(&k) {
+            Some(l) => f(l, r),
+            None => r
+        };
+        acc.insert(k, v);
+    }
+    acc
+}
+
 impl fmt::Display for Features {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        if let Some(ref parts) = self.parts { try!(write!(fmt, "{}", parts)); }
-        if let Some(ref winver) = self.winver { try!(write!(fmt, "{}", winver)); }
-        if let Some(ref arch) = self.arch { try!(write!(fmt, "{}", arch)); }
-        Ok(())
+        self.to_cfg().simplify().write_attr(fmt)
+    }
+}
+
+/**
+Describes the space of configurations the headers were actually expanded for.
+
+Anything that's true across *all* of these doesn't need to be checked in the output.
+*/
+#[derive(Clone, Debug)]
+pub struct Universe {
+    archs: Architectures,
+    versions: Vec<u32>,
+}
+
+impl Universe {
+    pub fn from_exp_configs(exp_configs: &[ExpConfig]) -> Universe {
+        let archs = exp_configs.iter()
+            .fold(Architectures::empty(), |acc, c| acc | Architectures::from(c.arch));
+        let mut versions: Vec<_> = exp_configs.iter().map(|c| c.winver.1).collect();
+        versions.sort();
+        versions.dedup();
+        Universe {
+            archs: archs,
+            versions: versions,
+        }
     }
 }
 
@@ -143,6 +274,17 @@ impl From<WinVersions> for Features {
     }
 }
 
+impl From<DefineSet> for Features {
+    fn from(v: DefineSet) -> Features {
+        let mut defines = BTreeMap::new();
+        defines.insert(v.name().to_owned(), v);
+        Features {
+            defines: defines,
+            ..Features::default()
+        }
+    }
+}
+
 impl From<Architectures> for Features {
     fn from(v: Architectures) -> Features {
         Features {
@@ -152,9 +294,56 @@ impl From<Architectures> for Features {
     }
 }
 
+/**
+Gives a version a readable name, rounding up to the next known version.
+*/
+pub fn version_name(v: u32) -> String {
+    match WinVersion::from_u32_round_up(v) {
+        Some(wv) if wv as u32 == v => format!("{:?}", wv),
+        Some(wv) => format!("{:?} (0x{:08x})", wv, v),
+        None => format!("0x{:08x}", v)
+    }
+}
+
+/**
+Everything needed to evaluate conditional compilation expressions, beyond the expressions themselves.
+*/
+#[derive(Copy, Clone, Debug)]
+pub struct EvalContext<'a> {
+    /// Additional defines to be tracked as feature set dimensions.
+    pub tracked_defines: &'a [Rc<TrackedDefine>],
+
+    /// If `true`, subexpressions which can't be evaluated are ignored, rather than failing the whole expression.
+    pub lenient: bool,
+}
+
+impl<'a> EvalContext<'a> {
+    /// Returns a copy of this context which ignores subexpressions that can't be evaluated.
+    pub fn lenient(self) -> EvalContext<'a> {
+        EvalContext {
+            lenient: true,
+            ..self
+        }
+    }
+
+    /// Looks up a tracked define by name.
+    pub fn tracked_define(&self, name: &str) -> Option<&'a Rc<TrackedDefine>> {
+        self.tracked_defines.iter().find(|d| d.name == name)
+    }
+
+    /**
+    Determines whether this token is "important", either inherently or because the configuration says so.
+
+    See `is_important_define`.
+    */
+    pub fn is_important_define(&self, tok: &str) -> bool {
+        is_important_define(tok) || self.tracked_define(tok).is_some()
+    }
+}
+
 /// Determines whether any of the given tokens are "important".
-pub fn has_important_defines(toks: &[String]) -> bool {
-    toks.iter().any(|tok| is_important_define(&**tok))
+pub fn has_important_defines(toks: &[String], ctx: EvalContext) -> bool {
+    toks.iter().any(|tok| ctx.is_important_define(&**tok))
 }
 
 /**
@@ -193,6 +382,7 @@ pub fn is_important_define(tok: &str) -> bool {
         | "WINVER"
         | "_WIN32_WINNT"
         | "NTDDI_VERSION"
+        | "WINAPI_FAMILY"
         | "WINAPI_FAMILY_PARTITION"
         | "WINAPI_FAMILY_ONE_PARTITION"
         | "WINAPI_PARTITION_DESKTOP"
@@ -208,44 +398,45 @@ pub fn is_important_define(tok: &str) -> bool {
 /**
 Given an identifier, work out the feature set it represents.
 */
-pub fn define_feature(name: &str) -> Features {
-    debug!("define_feature({:?})", name);
-    match cc::Node::eval_defined(name) {
-        Ok(value) => {
-            match value.to_features() {
-                Ok(f) => f,
-                Err(err) => panic!("error defining feature {:?}: {}", name, err)
-            }
-        },
-        Err(err) => panic!("error defining feature {:?}: {}", name, err)
-    }
+pub fn define_feature(name: &str, loc: &clang::SourceLocation, ctx: EvalContext) -> Result<Features, String> {
+    debug!("define_feature({:?}, {})", name, loc.display_short());
+    cc::Node::eval_defined(name, ctx)
+        .and_then(|value| value.to_features())
+        .map_err(|err| cc_error(loc, &[name.to_owned()], format!("could not define feature: {}", err)))
 }
 
 /**
 Given a conditional compilation expression, work out the feature set it represents.
 */
-pub fn define_feature_expr(toks: &[String], loc: &clang::SourceLocation) -> Features {
+pub fn define_feature_expr(toks: &[String], loc: &clang::SourceLocation, ctx: EvalContext) -> Result<Features, String> {
     debug!("define_feature_expr({:?}, {})", toks, loc.display_short());
-    if !has_important_defines(toks) {
+    if !has_important_defines(toks, ctx) {
         debug!(".. nothing important");
-        return Features::default();
+        return Ok(Features::default());
     }
 
     let node = match cc::parse_conditional_expr(toks) {
         Ok(Some((node, tail))) => {
             if tail.len() != 0 {
-                panic!("could not fully parse cc expr at {} {:?}; leftover: {:?}", loc.display_short(), toks, tail)
+                return Err(cc_error(loc, toks, format!("could not fully parse cc expr; leftover: {:?}", tail)));
             }
             node
         },
-        Ok(None) => panic!("could not parse cc expr at {} {:?}", loc.display_short(), toks),
-        Err(err) => panic!("could not parse cc expr at {} {:?}: {}", loc.display_short(), toks, err)
+        Ok(None) => return Err(cc_error(loc, toks, "could not parse cc expr".into())),
+        Err(err) => return Err(cc_error(loc, toks, format!("could not parse cc expr: {}", err)))
     };
 
     debug!(".. node: {:?}", node);
 
-    match node.clone().eval().and_then(|v| v.to_features()) {
-        Ok(f) => { debug!(".. f: {:?}", f); f },
-        Err(err) => panic!("error evaluating expr at {} {:?}: {}", loc.display_short(), node, err)
+    match node.eval(ctx).and_then(|v| v.to_features()) {
+        Ok(f) => { debug!(".. f: {:?}", f); Ok(f) },
+        Err(err) => Err(cc_error(loc, toks, format!("error evaluating cc expr {:?}: {}", node, err)))
     }
 }
+
+/**
+Formats an error in a conditional compilation expression, along with where it is and what it looks like.
+*/
+fn cc_error(loc: &clang::SourceLocation, toks: &[String], msg: String) -> String {
+    format!("{}: {}\n    | {}", loc.display_short(), msg, toks.join(" "))
+}
@@ -2,18 +2,22 @@
 Defines the Partitions feature set component.
 */
 use std::fmt;
+use super::cfg::Cfg;
 
 bitflags! {
     /**
     Partitions are the way the Windows API is divided into different subsets.
 
-    In *theory*, these could all be disjoint, but, at least for now, `App` forms a strict subset of `Desktop`, and `Phone` doesn't appear to have *anything* in it at all.
+    In *theory*, these could all be disjoint, but in practice they overlap heavily: the desktop family also enables `App`, `PcApp` and `Games`, `System` is only enabled for the system and server families, and `Phone` doesn't appear to have *anything* in it at all.  See `Family` for how these combine into the things you can actually compile for.
     */
     flags Partitions: u8 {
-        const Desktop       = 0b001,
-        const App           = 0b010,
-        const DesktopApp    = 0b011,
-        const Phone         = 0b100,
+        const Desktop       = 0b0000_0001,
+        const App           = 0b0000_0010,
+        const Phone         = 0b0000_0100,
+        const PcApp         = 0b0000_1000,
+        const System        = 0b0001_0000,
+        const Games         = 0b0010_0000,
+        const Server        = 0b0100_0000,
     }
 }
 
@@ -23,31 +27,131 @@ impl Partitions {
         match s {
             "WINAPI_PARTITION_DESKTOP"  => Some(Partitions::Desktop),
             "WINAPI_PARTITION_APP"      => Some(Partitions::App),
-            "WINAPI_PARTITION_PC_APP"   => Some(Partitions::DesktopApp),
-            "WINAPI_PARTITION_PHONE"    => Some(Partitions::Phone),
+            "WINAPI_PARTITION_PC_APP"   => Some(Partitions::PcApp),
+            "WINAPI_PARTITION_PHONE"
+            | "WINAPI_PARTITION_PHONE_APP"
+            | "WINAPI_PARTITION_PHONE_RESTRICTED" => Some(Partitions::Phone),
+            "WINAPI_PARTITION_SYSTEM"   => Some(Partitions::System),
+            "WINAPI_PARTITION_GAMES"    => Some(Partitions::Games),
+            "WINAPI_PARTITION_SERVER"   => Some(Partitions::Server),
             _ => None
         }
     }
 }
 
-const CFG_FEATURE_PARTITION_DESKTOP: &'static str = "winapi_desktop";
-const CFG_FEATURE_PARTITION_APP: &'static str = "winapi_app";
-const CFG_FEATURE_PARTITION_PHONE: &'static str = "winapi_phone";
+/**
+An API family; *i.e.* a possible value of `WINAPI_FAMILY`.
+
+Each family is a particular combination of partitions, as defined by `winapifamily.h`.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Family {
+    DesktopApp,
+    App,
+    PhoneApp,
+    System,
+    Games,
+    Server,
+}
+
+impl Family {
+    pub const ALL: &'static [Family] = &[
+        Family::DesktopApp, Family::App, Family::PhoneApp,
+        Family::System, Family::Games, Family::Server,
+    ];
+
+    /// Work out if the given identifier maps to a known family.
+    pub fn from_define(s: &str) -> Option<Family> {
+        match s {
+            "WINAPI_FAMILY_DESKTOP_APP" => Some(Family::DesktopApp),
+            "WINAPI_FAMILY_APP"
+            | "WINAPI_FAMILY_PC_APP"    => Some(Family::App),
+            "WINAPI_FAMILY_PHONE_APP"   => Some(Family::PhoneApp),
+            "WINAPI_FAMILY_SYSTEM"      => Some(Family::System),
+            "WINAPI_FAMILY_GAMES"       => Some(Family::Games),
+            "WINAPI_FAMILY_SERVER"      => Some(Family::Server),
+            _ => None
+        }
+    }
+
+    /**
+    The partitions which make up this family; *i.e.* the bits of `WINAPI_FAMILY` when set to this family.
+    */
+    pub fn partitions(self) -> Partitions {
+        use self::Partitions as P;
+        match self {
+            Family::DesktopApp => P::Desktop | P::App | P::PcApp | P::Games,
+            Family::App => P::App | P::PcApp,
+            Family::PhoneApp => P::App | P::Phone,
+            Family::System => P::System,
+            Family::Games => P::Games,
+            Family::Server => P::System | P::Server,
+        }
+    }
+
+    /**
+    The partition feature set corresponding to compiling for *exactly* this family.
+    */
+    pub fn feature(self) -> Partitions {
+        match self {
+            Family::DesktopApp => Partitions::Desktop,
+            Family::App => Partitions::App,
+            Family::PhoneApp => Partitions::Phone,
+            Family::System => Partitions::System,
+            Family::Games => Partitions::Games,
+            Family::Server => Partitions::Server,
+        }
+    }
+
+    /**
+    The partition feature set covering every family for which `pred` is true.
+
+    Returns `None` if there are no such families.
+    */
+    pub fn features_where<F>(pred: F) -> Option<Partitions>
+    where F: Fn(Family) -> bool {
+        let parts = Family::ALL.iter().cloned()
+            .filter(|&f| pred(f))
+            .fold(Partitions::empty(), |acc, f| acc | f.feature());
+        if parts.is_any() { Some(parts) } else { None }
+    }
+}
+
+const CFG_FEATURES: &'static [(Partitions, &'static str)] = &[
+    (Partitions::Desktop, "winapi_desktop"),
+    (Partitions::App, "winapi_app"),
+    (Partitions::Phone, "winapi_phone"),
+    (Partitions::PcApp, "winapi_pc_app"),
+    (Partitions::System, "winapi_system"),
+    (Partitions::Games, "winapi_games"),
+    (Partitions::Server, "winapi_server"),
+];
+
+impl Partitions {
+    /// Returns the short names of the partitions in this set.
+    pub fn names(&self) -> Vec<&'static str> {
+        CFG_FEATURES.iter()
+            .filter(|&&(part, _)| self.intersects(part))
+            .map(|&(_, feat)| &feat["winapi_".len()..])
+            .collect()
+    }
+
+    /// Converts this set into a `#[cfg]` predicate.
+    pub fn to_cfg(&self) -> Cfg {
+        if self.is_all() {
+            return Cfg::All(vec![]);
+        }
+        // A declaration is available if *any* of its partitions are enabled.
+        Cfg::Any(CFG_FEATURES.iter()
+            .filter(|&&(part, _)| self.intersects(part))
+            .map(|&(_, feat)| Cfg::feature(feat))
+            .collect())
+    }
+}
 
 impl fmt::Display for Partitions {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         assert!(self.is_any(), "can't have no partitions enabled");
-        if !self.is_all() {
-            if (*self & Partitions::Desktop).is_any() {
-                try!(write!(fmt, "#[cfg(feature={:?})] ", CFG_FEATURE_PARTITION_DESKTOP));
-            }
-            if (*self & Partitions::App).is_any() {
-                try!(write!(fmt, "#[cfg(feature={:?})] ", CFG_FEATURE_PARTITION_APP));
-            }
-            if (*self & Partitions::Phone).is_any() {
-                try!(write!(fmt, "#[cfg(feature={:?})] ", CFG_FEATURE_PARTITION_PHONE));
-            }
-        }
-        Ok(())
+        self.to_cfg().simplify().write_attr(fmt)
     }
 }
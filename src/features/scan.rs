@@ -4,13 +4,38 @@ Deals with processing a translation unit's tokens to extract a feature set map.
 This is mostly in its own module to make it easier to exclude from logging.
 */
 use std::collections::BTreeMap;
+use FeatureErrorPolicy;
 use clang;
-use super::{Features, define_feature, define_feature_expr};
+use super::{EvalContext, Features, define_feature, define_feature_expr};
+
+/**
+Records a conditional compilation directive which could not be evaluated, and what was done about it.
+*/
+#[derive(Clone, Debug)]
+pub struct Fallback {
+    /// Where the directive is.
+    pub location: String,
+
+    /// What went wrong, including the directive itself.
+    pub error: String,
+
+    /// The policy used to recover.
+    pub policy: FeatureErrorPolicy,
+
+    /// The feature set used in place of the directive's condition.
+    pub result: Features,
+}
 
 /**
 This function will, given a list of token lints, compute a sparse feature set map.
+
+Directives which can't be evaluated are dealt with according to `policy`; every time this happens, a `Fallback` is returned along with the map.
 */
-pub fn scan_features(tls: Vec<(u32, Vec<clang::Token>)>) -> BTreeMap<u32, Features> {
+pub fn scan_features(
+    tls: Vec<(u32, Vec<clang::Token>)>,
+    ctx: EvalContext,
+    policy: FeatureErrorPolicy,
+) -> Result<(BTreeMap<u32, Features>, Vec<Fallback>), String> {
     debug!("scan_features([..; {}])", tls.len());
     /*
     The way this works is that we have to walk through *all* the lines, looking for preprocessor conditional compilation directives.  When we find them, we interpret them and push the enabled feature tests on to the stack.  Then, when we find something that *isn't* a conditional directive *and* the features have changed since the last time we did so, we add an entry to the map.
@@ -37,7 +62,44 @@ pub fn scan_features(tls: Vec<(u32, Vec<clang::Token>)>) -> BTreeMap<u32, Featur
         stack.pop();
     }
 
+    /*
+    Evaluates a directive's condition with `eval`, recovering from errors according to `policy`.
+
+    With `Unknown`, the condition is evaluated again, this time ignoring anything that can't be evaluated.  If even *that* fails, the condition is treated as saying nothing at all.
+    */
+    fn recover<F>(
+        eval: F,
+        loc: &clang::SourceLocation,
+        ctx: EvalContext,
+        policy: FeatureErrorPolicy,
+        fallbacks: &mut Vec<Fallback>,
+    ) -> Result<Features, String>
+    where F: Fn(EvalContext) -> Result<Features, String> {
+        use FeatureErrorPolicy as FEP;
+
+        let err = match eval(ctx) {
+            Ok(f) => return Ok(f),
+            Err(err) => err
+        };
+
+        let feat = match policy {
+            FEP::Abort => return Err(err),
+            FEP::AlwaysTrue => fd(),
+            FEP::Unknown => eval(ctx.lenient()).unwrap_or_else(|_| fd()),
+        };
+
+        warn!("{}\n.. falling back to {:?}: {:?}", err, policy, feat);
+        fallbacks.push(Fallback {
+            location: loc.display_short().to_string(),
+            error: err,
+            policy: policy,
+            result: feat.clone(),
+        });
+        Ok(feat)
+    }
+
     let mut map = BTreeMap::new();
+    let mut fallbacks = vec![];
 
     // Insert fallback.
     map.insert(0, fd());
@@ -54,18 +116,26 @@ pub fn scan_features(tls: Vec<(u32, Vec<clang::Token>)>) -> BTreeMap<u32, Featur
 
         if seq(&ts, 2, &["#", "if"]) {
             debug!(".. #if {:?}", &ts[2..]);
-            append(&mut stack, define_feature_expr(&ts[2..], &loc));
+            let f = try!(recover(|ctx| define_feature_expr(&ts[2..], &loc, ctx),
+                &loc, ctx, policy, &mut fallbacks));
+            append(&mut stack, f);
         } else if seq(&ts, 2, &["#", "ifdef"]) && ts.len() == 3 {
             debug!(".. #ifdef {:?}", &ts[2..]);
-            append(&mut stack, define_feature(&ts[2]));
+            let f = try!(recover(|ctx| define_feature(&ts[2], &loc, ctx),
+                &loc, ctx, policy, &mut fallbacks));
+            append(&mut stack, f);
         } else if seq(&ts, 2, &["#", "ifndef"]) && ts.len() == 3 {
             debug!(".. #ifndef {:?}", &ts[2..]);
-            append(&mut stack, define_feature(&ts[2]).complement());
+            let f = try!(recover(|ctx| define_feature(&ts[2], &loc, ctx).map(Features::complement),
+                &loc, ctx, policy, &mut fallbacks));
+            append(&mut stack, f);
             // debug!(".. #ifndef done");
         } else if seq(&ts, 2, &["#", "elif"]) {
             debug!(".. #elif {:?}", &ts[2..]);
             pop(&mut stack);
-            append(&mut stack, define_feature_expr(&ts[2..], &loc));
+            let f = try!(recover(|ctx| define_feature_expr(&ts[2..], &loc, ctx),
+                &loc, ctx, policy, &mut fallbacks));
+            append(&mut stack, f);
         } else if seq(&ts, 2, &["#", "else"]) {
             debug!(".. #else");
             pop(&mut stack);
@@ -89,6 +159,6 @@ pub fn scan_features(tls: Vec<(u32, Vec<clang::Token>)>) -> BTreeMap<u32, Featur
         }
     }
 
-    debug!(".. done ({} entries)", map.len());
-    map
+    debug!(".. done ({} entries, {} fallbacks)", map.len(), fallbacks.len());
+    Ok((map, fallbacks))
 }
@@ -0,0 +1,381 @@
+/*!
+Model-based tests for the feature set algebra.
+
+Each component is checked against a brute-force model: a set is just the collection of sample points it contains.  Because range boundaries are only ever drawn from the sample points, two sets with the same model are the same set.
+*/
+use std::collections::BTreeSet;
+use std::rc::Rc;
+use WinVersion;
+use super::{Architectures, DefineSet, Features, Partitions, TrackedDefine, WinVersions};
+
+const ITERATIONS: usize = 2000;
+
+/**
+A tiny xorshift generator, so that failures are reproducible without pulling in any extra dependencies.
+*/
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Rng {
+        Rng(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/**
+Every point a range may start or end at: the ends of the version space, every known version, and the point just after each.
+*/
+fn boundaries() -> Vec<u32> {
+    let mut pts = vec![0, 1, !0 - 1, !0];
+    for &v in WinVersion::ALL {
+        pts.push(v as u32);
+        pts.push(v as u32 + 1);
+    }
+    pts.sort();
+    pts.dedup();
+    pts
+}
+
+/**
+Every point membership is checked at.  Membership can only change at a boundary, and `!0` itself is never a member.
+*/
+fn samples() -> Vec<u32> {
+    boundaries().into_iter().filter(|&v| v != !0).collect()
+}
+
+fn gen_winvers(rng: &mut Rng, bounds: &[u32]) -> WinVersions {
+    match rng.below(8) {
+        0 => return (..).into(),
+        1 => return WinVersions::from_ranges(vec![]),
+        _ => ()
+    }
+    let mut pts: Vec<u32> = (0..2 * (1 + rng.below(4)))
+        .map(|_| bounds[rng.below(bounds.len())])
+        .collect();
+    pts.sort();
+    let ranges = pts.chunks(2).map(|ab| ab[0]..ab[1]).collect();
+    WinVersions::from_ranges(ranges)
+}
+
+/**
+The tracked defines feature sets are generated over.
+*/
+fn tracked_defines() -> Vec<Rc<TrackedDefine>> {
+    vec![
+        Rc::new(TrackedDefine {
+            name: "_WIN32_IE".into(),
+            values: vec![(0x0500, "ie5".into()), (0x0600, "ie6".into()), (0x0700, "ie7".into())],
+        }),
+        Rc::new(TrackedDefine {
+            name: "_DEBUG".into(),
+            values: vec![(1, "debug".into())],
+        }),
+    ]
+}
+
+/**
+Every value a tracked define can have, including `0` for "undefined".
+*/
+fn define_values(define: &TrackedDefine) -> Vec<u32> {
+    Some(0).into_iter().chain(define.values.iter().map(|&(v, _)| v)).collect()
+}
+
+fn gen_define_set(rng: &mut Rng, define: &Rc<TrackedDefine>) -> DefineSet {
+    let values: Vec<u32> = define_values(define).into_iter().filter(|_| rng.below(2) == 0).collect();
+    DefineSet::value_where(define, |v| values.contains(&v))
+}
+
+fn gen_parts(rng: &mut Rng) -> Partitions {
+    Partitions::from_bits_truncate(rng.next() as u8)
+}
+
+fn gen_arch(rng: &mut Rng) -> Architectures {
+    Architectures::from_bits_truncate(rng.next() as u8)
+}
+
+fn gen_features(rng: &mut Rng, bounds: &[u32], defines: &[Rc<TrackedDefine>]) -> Features {
+    let mut feat = Features {
+        winver: if rng.below(3) == 0 { None } else { Some(gen_winvers(rng, bounds)) },
+        parts: if rng.below(3) == 0 { None } else { Some(gen_parts(rng)) },
+        arch: if rng.below(3) == 0 { None } else { Some(gen_arch(rng)) },
+        ..Features::default()
+    };
+    for define in defines {
+        if rng.below(3) != 0 {
+            feat.defines.insert(define.name.clone(), gen_define_set(rng, define));
+        }
+    }
+    feat
+}
+
+/**
+Generates a pair of feature sets which only say something about the same, single component.
+*/
+fn gen_features_pair_in_one_component(
+    rng: &mut Rng,
+    bounds: &[u32],
+    defines: &[Rc<TrackedDefine>],
+) -> (Features, Features) {
+    let mut pair = (Features::default(), Features::default());
+    match rng.below(3 + defines.len()) {
+        0 => {
+            pair.0.winver = Some(gen_winvers(rng, bounds));
+            pair.1.winver = Some(gen_winvers(rng, bounds));
+        },
+        1 => {
+            pair.0.parts = Some(gen_parts(rng));
+            pair.1.parts = Some(gen_parts(rng));
+        },
+        2 => {
+            pair.0.arch = Some(gen_arch(rng));
+            pair.1.arch = Some(gen_arch(rng));
+        },
+        n => {
+            let define = &defines[n - 3];
+            pair.0.defines.insert(define.name.clone(), gen_define_set(rng, define));
+            pair.1.defines.insert(define.name.clone(), gen_define_set(rng, define));
+        }
+    }
+    pair
+}
+
+type Model = BTreeSet<u32>;
+
+fn model(wv: &WinVersions, samples: &[u32]) -> Model {
+    samples.iter().cloned().filter(|&v| wv.contains(v)).collect()
+}
+
+/**
+Checks that a set is in canonical form: either the single empty range, or non-empty ranges in order with gaps between them.
+*/
+fn assert_canonical(wv: &WinVersions) {
+    let ranges = wv.ranges();
+    if &*ranges == &[0..0] {
+        return;
+    }
+    assert!(ranges.len() > 0, "no ranges: {:?}", wv);
+    assert!(ranges.iter().all(|r| r.start < r.end), "empty range in {:?}", wv);
+    for pair in ranges.windows(2) {
+        assert!(pair[0].end < pair[1].start, "unmerged ranges in {:?}", wv);
+    }
+}
+
+#[test]
+fn test_winvers_model() {
+    let bounds = boundaries();
+    let samples = samples();
+    let all: Model = samples.iter().cloned().collect();
+    let mut rng = Rng::new();
+
+    for _ in 0..ITERATIONS {
+        let a = gen_winvers(&mut rng, &bounds);
+        let b = gen_winvers(&mut rng, &bounds);
+        let (ma, mb) = (model(&a, &samples), model(&b, &samples));
+        assert_canonical(&a);
+
+        let and = a.clone().intersect(b.clone());
+        assert_canonical(&and);
+        assert_eq!(model(&and, &samples), &ma & &mb, "{:?} & {:?} = {:?}", a, b, and);
+
+        let or = a.clone().union(b.clone());
+        assert_canonical(&or);
+        assert_eq!(model(&or, &samples), &ma | &mb, "{:?} | {:?} = {:?}", a, b, or);
+
+        let not = a.clone().complement();
+        assert_canonical(&not);
+        assert_eq!(model(&not, &samples), &all - &ma, "!{:?} = {:?}", a, not);
+
+        assert_eq!(a.is_any(), ma.len() > 0, "{:?}.is_any()", a);
+    }
+}
+
+#[test]
+fn test_winvers_laws() {
+    let bounds = boundaries();
+    let mut rng = Rng::new();
+
+    for _ in 0..ITERATIONS {
+        let a = gen_winvers(&mut rng, &bounds);
+        let b = gen_winvers(&mut rng, &bounds);
+
+        assert_eq!(a.clone().complement().complement(), a);
+        assert_eq!(a.clone().intersect(a.clone()), a);
+        assert_eq!(a.clone().union(a.clone()), a);
+        assert_eq!(a.clone().intersect(b.clone()), b.clone().intersect(a.clone()));
+        assert_eq!(a.clone().union(b.clone()), b.clone().union(a.clone()));
+        assert_eq!(
+            a.clone().intersect(b.clone()).complement(),
+            a.clone().complement().union(b.clone().complement()));
+        assert_eq!(
+            a.clone().union(b.clone()).complement(),
+            a.clone().complement().intersect(b.clone().complement()));
+    }
+}
+
+#[test]
+fn test_winvers_full_range() {
+    let all: WinVersions = (..).into();
+    let none = WinVersions::from_ranges(vec![]);
+    let low = WinVersions::from(..WinVersion::WIN7);
+    let high = WinVersions::from(WinVersion::WIN7..);
+
+    assert_eq!(all.clone().complement(), none);
+    assert_eq!(none.clone().complement(), all);
+    assert_eq!(low.clone().union(high.clone()), all);
+    assert_eq!(low.clone().intersect(high.clone()), none);
+    assert_eq!(low.clone().complement(), high);
+    assert_eq!(all.clone().intersect(low.clone()), low);
+    assert_eq!(none.clone().union(high.clone()), high);
+    assert!(!none.is_any());
+}
+
+/**
+A feature set is modelled component-wise, each component being the set of sample points it allows.  A component that says nothing (`None`, or no entry for a define) allows every point.
+*/
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct FModel {
+    winver: Model,
+    parts: u8,
+    arch: u8,
+    defines: Vec<Model>,
+}
+
+impl FModel {
+    fn new(f: &Features, samples: &[u32], defines: &[Rc<TrackedDefine>]) -> FModel {
+        FModel {
+            winver: match f.winver {
+                Some(ref wv) => model(wv, samples),
+                None => samples.iter().cloned().collect()
+            },
+            parts: f.parts.unwrap_or(Partitions::all()).bits(),
+            arch: f.arch.unwrap_or(Architectures::all()).bits(),
+            defines: defines.iter()
+                .map(|define| {
+                    let set = f.defines.get(&define.name);
+                    define_values(define).into_iter()
+                        .filter(|&v| match set {
+                            Some(set) => DefineSet::value_where(define, |x| x == v).intersect(set.clone()).is_any(),
+                            None => true
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns `true` if no point is allowed at all.
+    fn is_empty(&self) -> bool {
+        self.winver.is_empty() || self.parts == 0 || self.arch == 0 || self.defines.iter().any(|d| d.is_empty())
+    }
+
+    fn intersect(&self, other: &FModel) -> FModel {
+        FModel {
+            winver: &self.winver & &other.winver,
+            parts: self.parts & other.parts,
+            arch: self.arch & other.arch,
+            defines: self.defines.iter().zip(&other.defines).map(|(a, b)| a & b).collect(),
+        }
+    }
+
+    fn union(&self, other: &FModel) -> FModel {
+        FModel {
+            winver: &self.winver | &other.winver,
+            parts: self.parts | other.parts,
+            arch: self.arch | other.arch,
+            defines: self.defines.iter().zip(&other.defines).map(|(a, b)| a | b).collect(),
+        }
+    }
+
+    /**
+    Complements the components `f` says something about, leaving the rest allowing every point.
+
+    This is only the complement of the whole set when `f` says something about a single component.
+    */
+    fn complement(&self, f: &Features, all: &FModel, defines: &[Rc<TrackedDefine>]) -> FModel {
+        FModel {
+            winver: if f.winver.is_some() { &all.winver - &self.winver } else { all.winver.clone() },
+            parts: if f.parts.is_some() { all.parts & !self.parts } else { all.parts },
+            arch: if f.arch.is_some() { all.arch & !self.arch } else { all.arch },
+            defines: self.defines.iter().zip(&all.defines).zip(defines)
+                .map(|((d, a), define)| if f.defines.contains_key(&define.name) { a - d } else { a.clone() })
+                .collect(),
+        }
+    }
+}
+
+/// The number of components a feature set says something about.
+fn constrained(f: &Features) -> usize {
+    f.winver.is_some() as usize + f.parts.is_some() as usize + f.arch.is_some() as usize + f.defines.len()
+}
+
+#[test]
+fn test_features_model() {
+    let bounds = boundaries();
+    let samples = samples();
+    let defines = tracked_defines();
+    let all = FModel::new(&Features::default(), &samples, &defines);
+    let mut rng = Rng::new();
+
+    for _ in 0..ITERATIONS {
+        let a = gen_features(&mut rng, &bounds, &defines);
+        let b = gen_features(&mut rng, &bounds, &defines);
+        let (ma, mb) = (FModel::new(&a, &samples, &defines), FModel::new(&b, &samples, &defines));
+
+        // Feature sets are products of their components, so intersection is exact.
+        let and = a.clone().and(b.clone());
+        assert_eq!(FModel::new(&and, &samples, &defines), ma.intersect(&mb), "{:?} & {:?} = {:?}", a, b, and);
+
+        // The union of two products usually isn't one, so this is the smallest product containing both.  It's exact when they differ in at most one component.
+        let or = a.clone().or(b.clone());
+        assert_eq!(FModel::new(&or, &samples, &defines), ma.union(&mb), "{:?} | {:?} = {:?}", a, b, or);
+
+        // The complement is only a product when a single component says anything.  Otherwise, it must at least not overlap.
+        let not = a.clone().complement();
+        let mnot = FModel::new(&not, &samples, &defines);
+        if constrained(&a) == 1 {
+            assert_eq!(mnot, ma.complement(&a, &all, &defines), "!{:?} = {:?}", a, not);
+        }
+        if constrained(&a) >= 1 {
+            assert!(mnot.intersect(&ma).is_empty(), "!{:?} = {:?} overlaps", a, not);
+        }
+    }
+}
+
+#[test]
+fn test_features_laws() {
+    let bounds = boundaries();
+    let defines = tracked_defines();
+    let mut rng = Rng::new();
+
+    for _ in 0..ITERATIONS {
+        let a = gen_features(&mut rng, &bounds, &defines);
+        let b = gen_features(&mut rng, &bounds, &defines);
+
+        assert_eq!(a.clone().complement().complement(), a);
+        assert_eq!(a.clone().and(a.clone()), a);
+        assert_eq!(a.clone().or(a.clone()), a);
+        assert_eq!(a.clone().and(b.clone()), b.clone().and(a.clone()));
+        assert_eq!(a.clone().or(b.clone()), b.clone().or(a.clone()));
+
+        // De Morgan's laws only hold where complements are exact, which is when a single component says anything.
+        let (a, b) = gen_features_pair_in_one_component(&mut rng, &bounds, &defines);
+        assert_eq!(
+            a.clone().and(b.clone()).complement(),
+            a.clone().complement().or(b.clone().complement()));
+        assert_eq!(
+            a.clone().or(b.clone()).complement(),
+            a.clone().complement().and(b.clone().complement()));
+    }
+}
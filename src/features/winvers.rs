@@ -6,6 +6,7 @@ use std::cmp::{Ord, Ordering, PartialOrd};
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
 use itertools::Itertools;
 use WinVersion;
+use super::cfg::Cfg;
 
 /**
 This represents a set of Windows versions.
@@ -30,6 +31,95 @@ impl WinVersions {
         !self.is_any() || (self.0.len() == 1 && self.0[0].end == !0)
     }
 
+    /// Returns `true` if this set contains the given version.
+    pub fn contains(&self, v: u32) -> bool {
+        self.0.iter().any(|r| r.start <= v && v < r.end)
+    }
+
+    /**
+    Quantises this set to the given (sorted) list of versions.
+
+    Each version in `grid` stands for every version up to the next one; that whole span is included if and only if the grid version itself is.  Anything before the first grid version is judged by whether `0` is included.
+    */
+    pub fn snap_to(self, grid: &[u32]) -> WinVersions {
+        if grid.len() == 0 {
+            return self;
+        }
+        WinVersions::from_grid(grid, |v| self.contains(v))
+    }
+
+    /**
+    Constructs the set of versions for which `pred` is true, as judged at each known version.
+
+    This is exactly as precise as the version table in `data/winver.json`.
+    */
+    pub fn from_pred<F>(pred: F) -> WinVersions
+    where F: Fn(u32) -> bool {
+        let grid: Vec<u32> = WinVersion::ALL.iter().map(|&v| v as u32).collect();
+        WinVersions::from_grid(&grid, pred)
+    }
+
+    /**
+    Constructs a set from arbitrary ranges.
+
+    The ranges must be sorted, and must not overlap.
+    */
+    pub fn from_ranges(ranges: Vec<Range<u32>>) -> WinVersions {
+        let ranges: Vec<_> = ranges.into_iter().filter(|r| r.start < r.end).collect();
+        for pair in ranges.windows(2) {
+            assert!(pair[0].end <= pair[1].start, "overlapping or unsorted ranges: {:?}", ranges);
+        }
+        let mut acc: Vec<Range<u32>> = vec![];
+        for r in ranges {
+            if let Some(last) = acc.last_mut() {
+                if last.end == r.start {
+                    last.end = r.end;
+                    continue;
+                }
+            }
+            acc.push(r);
+        }
+        if acc.len() == 0 {
+            acc.push(0..0);
+        }
+        WinVersions(acc)
+    }
+
+    /**
+    Constructs a set from a predicate judged at each point in the given (sorted, non-empty) grid.
+
+    See `snap_to`.
+    */
+    fn from_grid<F>(grid: &[u32], pred: F) -> WinVersions
+    where F: Fn(u32) -> bool {
+        let mut ranges = vec![];
+        if grid[0] > 0 && pred(0) {
+            ranges.push(0..grid[0]);
+        }
+        for (i, &v) in grid.iter().enumerate() {
+            if pred(v) {
+                ranges.push(v..grid.get(i + 1).cloned().unwrap_or(!0));
+            }
+        }
+        WinVersions::from_ranges(ranges)
+    }
+
+    /// Returns the lowest version in this set, if it has a lower bound at all.
+    pub fn introduced(&self) -> Option<u32> {
+        match self.0.first() {
+            Some(r) if r.start != 0 && r.start < r.end => Some(r.start),
+            _ => None
+        }
+    }
+
+    /// Returns the version after the highest version in this set, if it has an upper bound at all.
+    pub fn removed(&self) -> Option<u32> {
+        match self.0.last() {
+            Some(r) if r.end != !0 && r.start < r.end => Some(r.end),
+            _ => None
+        }
+    }
+
     /// Returns a borrowed slice of the underlying numerical ranges.
     pub fn ranges(&self) -> &[Range<u32>] {
         &self.0
@@ -38,16 +128,20 @@ impl WinVersions {
     /// Computes the complement of this set.
     pub fn complement(self) -> WinVersions {
         debug!("WinVersions::complement({:?})", self);
-        if &*self.0 == &[0..!0] {
+        let WinVersions(ranges) = self.simplify();
+
+        if &*ranges == &[0..!0] {
             return WinVersions(vec![0..0]);
         }
 
-        if &*self.0 == &[0..0] {
+        if &*ranges == &[0..0] {
             return WinVersions(vec![0..!0]);
         }
 
-        let mut pts: Vec<_> = self.0.into_iter().flat_map(|ab| vec![ab.start, ab.end].into_iter()).collect();
-        pts.dedup();
+        /*
+        Because the ranges are now non-empty and don't abut, every point is distinct.
+        */
+        let pts: Vec<_> = ranges.into_iter().flat_map(|ab| vec![ab.start, ab.end].into_iter()).collect();
         debug!(".. pts: {:?}", pts);
 
         let pts: Vec<_> = match (pts[0] == 0, pts[pts.len()-1] == !0) {
@@ -109,7 +203,25 @@ impl WinVersions {
                 */
                 acc.push(a..j);
                 ijs = &ijs[1..];
-            } else if j < b {
+            } else if a <= i && j <= b {
+                /*
+                Emit i..j, drop ij.
+
+                    a ....... b
+                       i .. j
+                */
+                acc.push(i..j);
+                ijs = &ijs[1..];
+            } else if i <= a && b <= j {
+                /*
+                Emit a..b, drop ab.
+
+                       a .. b
+                    i ....... j
+                */
+                acc.push(a..b);
+                abs = &abs[1..];
+            } else if j < a {
                 /*
                 Drop ij.
 
@@ -176,6 +288,22 @@ impl WinVersions {
                     */
                     abs[0] = i..b;
                     inner(acc, abs, &mut ijs[1..])
+                } else if a <= i && j <= b {
+                    /*
+                    Drop ij.
+
+                        a ....... b
+                           i .. j
+                    */
+                    inner(acc, abs, &mut ijs[1..])
+                } else if i <= a && b <= j {
+                    /*
+                    Drop ab.
+
+                           a .. b
+                        i ....... j
+                    */
+                    inner(acc, &mut abs[1..], ijs)
                 } else if j < a {
                     /*
                     Emit i..j, drop ij.
@@ -200,16 +328,9 @@ impl WinVersions {
         WinVersions(ranges).simplify()
     }
 
-    /// Simplifies a set, joining abutting ranges together.
+    /// Simplifies a set, dropping empty ranges and joining abutting ranges together.
     fn simplify(self) -> Self {
-        let mut pts: Vec<_> = self.0.into_iter().flat_map(|ab| vec![ab.start, ab.end].into_iter()).collect();
-        pts.dedup();
-        let ranges = pts.iter().cloned()
-            .batching(|mut it| it.next().and_then(
-                |a| it.next().map(
-                    |b| a..b)))
-            .collect();
-        WinVersions(ranges)
+        WinVersions::from_ranges(self.0)
     }
 }
 
@@ -229,29 +350,71 @@ impl PartialOrd for WinVersions {
 
 const CFG_FEATURE_VERSION_PREFIX: &'static str = "winapi_ver_";
 
-impl fmt::Display for WinVersions {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        assert!(&*self.0 != &[0..0], "can't have no versions enabled");
-        const PREFIX: &'static str = CFG_FEATURE_VERSION_PREFIX;
-        const END: u32 = !0;
+impl WinVersions {
+    /**
+    Simplifies this set with respect to the given (sorted) list of versions we actually expanded for.
 
-        // Skip if there are no restrictions.
-        if &*self.0 == &[0..!0] { return Ok(()); }
-
-        // Here we go!
-        try!(write!(fmt, "#[cfg(any("));
-        for (i, Range { start: a, end: b }) in self.0.iter().cloned().enumerate() {
-            try!(write!(fmt, "{}", if i == 0 { "" } else { ", " }));
-            match (a, b) {
-                (0, b) => try!(write!(fmt, "not(feature=\"{}{:08x}\")", PREFIX, b)),
-                (a, END) => try!(write!(fmt, "feature=\"{}{:08x}\"", PREFIX, a)),
-                (a, b) => try!(write!(fmt,
-                    "all(feature=\"{0}{1:08x}\", not(feature=\"{0}{2:08x}\"))",
-                    PREFIX, a, b))
+    Ranges are merged if nothing we expanded for lies between them, and are extended out to the ends of the version space if nothing we expanded for lies beyond them.  Returns `None` if the set includes *every* version given, meaning it doesn't need to be checked at all.
+    */
+    pub fn simplify_over(self, versions: &[u32]) -> Option<WinVersions> {
+        if versions.len() == 0 {
+            return Some(self);
+        }
+
+        let ranges = {
+            let range_of = |v: u32| self.0.iter().find(|r| r.start <= v && v < r.end).cloned();
+
+            let mut ranges = vec![];
+            let mut i = 0;
+            while i < versions.len() {
+                let first = match range_of(versions[i]) {
+                    Some(r) => r,
+                    None => { i += 1; continue; }
+                };
+                let mut j = i;
+                while j + 1 < versions.len() && range_of(versions[j + 1]).is_some() {
+                    j += 1;
+                }
+                let last = range_of(versions[j]).unwrap();
+
+                let start = if i == 0 { 0 } else { first.start };
+                let end = if j + 1 == versions.len() { !0 } else { last.end };
+                ranges.push(start..end);
+                i = j + 1;
             }
+            ranges
+        };
+
+        if ranges.len() == 0 {
+            // Doesn't exist in anything we expanded; leave it alone.
+            Some(self)
+        } else if &*ranges == &[0..!0] {
+            None
+        } else {
+            Some(WinVersions(ranges))
         }
-        try!(write!(fmt, "))] "));
-        Ok(())
+    }
+
+    /// Converts this set into a `#[cfg]` predicate.
+    pub fn to_cfg(&self) -> Cfg {
+        const END: u32 = !0;
+        let feat = |v: u32| Cfg::feature(format!("{}{:08x}", CFG_FEATURE_VERSION_PREFIX, v));
+
+        Cfg::Any(self.0.iter().cloned()
+            .map(|Range { start: a, end: b }| match (a, b) {
+                (0, END) => Cfg::All(vec![]),
+                (0, b) => feat(b).not(),
+                (a, END) => feat(a),
+                (a, b) => Cfg::All(vec![feat(a), feat(b).not()])
+            })
+            .collect())
+    }
+}
+
+impl fmt::Display for WinVersions {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        assert!(&*self.0 != &[0..0], "can't have no versions enabled");
+        self.to_cfg().simplify().write_attr(fmt)
     }
 }
 
@@ -22,6 +22,7 @@ extern crate num;
 extern crate regex;
 
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use regex::Regex;
 use features::Features;
 
@@ -33,9 +34,16 @@ mod ppmac;
 mod process;
 mod util;
 
+pub use clang::DiagnosticSeverity;
+pub use features::{Partitions, TrackedDefine};
 pub use generated::winver::WinVersion;
 pub use process::process_header;
 
+/**
+The (virtual) path the `prelude` is given.
+*/
+const PRELUDE_PATH: &'static str = "__prelude.h";
+
 /**
 This represents a single target architecture.
 */
@@ -48,15 +56,18 @@ pub enum Architecture {
 }
 
 impl Architecture {
-    fn to_features(self) -> Features {
+    /// The size of a data pointer on this architecture, in bytes.
+    pub fn pointer_size(self) -> usize {
         use self::Architecture::*;
-        use features::Architectures as FA;
-        let arch = match self {
-            X86_32 => FA::X86_32,
-            X86_64 => FA::X86_64,
-            Arm => FA::Arm,
-        };
-        Features::from(arch)
+        match self {
+            X86_32 | Arm => 4,
+            X86_64 => 8,
+        }
+    }
+
+    fn to_features(self) -> Features {
+        use features::Architectures;
+        Features::from(Architectures::from(self))
     }
 }
 
@@ -203,9 +214,104 @@ pub struct GenConfig {
     And a pattern `^tag`, the processor will rename `tagFoo` to `Quxx` in the output.  It will also *omit* the `Quxx` -> `Foo` typedef entirely.
     */
     pub non_canonical_tag_names: Vec<Regex>,
+
+    /**
+    Which character set the bindings are being generated for.
+
+    This determines what `TEXT(_)` and friends expand to when translating string macros.
+    */
+    pub char_set: CharSet,
+
+    /**
+    Types for integer macros whose spelling matches a pattern.
+
+    The first matching pattern wins.  The type is the *C* name of an integer type (*e.g.* `UINT`), which must be declared before any macro that uses it.  Macros which don't match any pattern are typed according to any casts they contain, or failing that, by the usual C literal typing rules.
+    */
+    pub int_macro_types: Vec<(Regex, String)>,
+
+    /**
+    Families of integer constants which should additionally be emitted as newtypes.
+    */
+    pub const_groups: Vec<ConstGroup>,
+
+    /**
+    If set, restricts output to declarations available in at least one of these partitions.
+
+    Declarations available in *all* of them are emitted without a partition `#[cfg]`.
+    */
+    pub target_partitions: Option<Partitions>,
+
+    /**
+    If `true`, version ranges are snapped to the versions we expanded for, plus every known `WinVersion`.  This also logs a report of where each declaration's availability changes.
+    */
+    pub snap_versions: bool,
+
+    /**
+    If `true`, declarations which are not available past some version are marked `#[deprecated]`.
+    */
+    pub deprecate_removed: bool,
+
+    /**
+    Additional defines (*e.g.* `_WIN32_IE` or `_DEBUG`) whose values should be tracked as feature set dimensions, rather than ignored.
+
+    Note that these don't change how the headers are *expanded*; if you need the declarations for every value to be visible, also add an appropriate `-D` to `switches`.
+    */
+    pub tracked_defines: Vec<Rc<TrackedDefine>>,
+
+    /**
+    What to do when a conditional compilation directive can't be parsed or evaluated, based on the path to the file that contains it.
+
+    The first matching pattern wins.  Files which don't match any pattern use `FeatureErrorPolicy::Abort`.  Every fallback taken is reported at the end of the run.
+    */
+    pub feature_error_policies: Vec<(Regex, FeatureErrorPolicy)>,
+
+    /**
+    Clang diagnostics at or above this severity cause processing to stop.  Anything less severe is merely logged.
+
+    Headers parsed solely to extract their conditional compilation directives are not checked.
+    */
+    pub diagnostic_threshold: DiagnosticSeverity,
+
+    /**
+    If `true`, *all* comments preceding a declaration are turned into documentation, not just documentation comments (`/** */`, `///`, *etc.*).
+
+    The Windows headers mostly use plain comments, so this is needed to get much documentation out of them.  Unfortunately, it also picks up a fair amount of noise.
+    */
+    pub parse_all_comments: bool,
+
+    /**
+    If `true`, pointer parameters annotated as inputs (`_In_`, `_In_reads_(n)`, *etc.*) but *not* outputs are translated as `*const`, even if the C type isn't `const`-qualified.
+    */
+    pub sal_const_inputs: bool,
+
+    /**
+    Functions whose name matches *any* of these regular expressions are exempt from `sal_const_inputs`.
+
+    This is for functions whose annotations are wrong, or which write through an "input" pointer regardless.
+    */
+    pub sal_const_input_exceptions: Vec<Regex>,
+
+    /**
+    Files whose contents are supplied directly, rather than read from disk, as `(path, contents)`.
+
+    An overlay replaces the file at `path` if there is one, and creates it if there isn't.  This can be used to patch broken SDK headers without touching the SDK, or to write a synthetic entry header which includes several others.  Paths must be spelled the way Clang would spell them when opening the file, so absolute paths are the safest choice.
+    */
+    pub overlays: Vec<(String, String)>,
+
+    /**
+    Source which is included before anything else, *e.g.* to pre-define macros the headers can't cope with.
+    */
+    pub prelude: Option<String>,
 }
 
 impl GenConfig {
+    /// Returns the configured C type for the integer macro with the given spelling, if any.
+    fn int_macro_type(&self, name: &str) -> Option<&str> {
+        self.int_macro_types.iter()
+            .find(|&&(ref re, _)| re.is_match(name))
+            .map(|&(_, ref ty)| &**ty)
+    }
+
     /// Determines whether or not the given tag name is canonical.
     fn is_tag_name_non_canonical(&self, name: &str) -> bool {
         for &ref re in &self.non_canonical_tag_names {
@@ -214,9 +320,47 @@ impl GenConfig {
         false
     }
 
+    /// Returns the policy for conditional expression errors in the file at the given path.
+    fn feature_error_policy(&self, path: &str) -> FeatureErrorPolicy {
+        self.feature_error_policies.iter()
+            .find(|&&(ref re, _)| re.is_match(path))
+            .map(|&(_, policy)| policy)
+            .unwrap_or(FeatureErrorPolicy::Abort)
+    }
+
+    /// Determines whether `sal_const_inputs` applies to the function with the given name.
+    fn use_sal_const_inputs(&self, name: &str) -> bool {
+        self.sal_const_inputs
+            && !self.sal_const_input_exceptions.iter().any(|re| re.is_match(name))
+    }
+
     /// Return the switches that should be passed to Clang, indepedent of expansion.
-    fn switches(&self) -> &[String] {
-        &self.switches
+    fn switches(&self) -> Vec<String> {
+        let comment_switches: &[&str] = if self.parse_all_comments { &["-fparse-all-comments"] } else { &[] };
+        let prelude_switches: &[&str] = if self.prelude.is_some() { &["-include", PRELUDE_PATH] } else { &[] };
+        self.switches.iter().cloned()
+            .chain(self.char_set.switches().iter().cloned().map(Into::into))
+            .chain(comment_switches.iter().cloned().map(Into::into))
+            .chain(prelude_switches.iter().cloned().map(Into::into))
+            .collect()
+    }
+
+    /// Returns the contents of the overlay for the file at the given path, if there is one.
+    fn overlay(&self, path: &str) -> Option<&str> {
+        if path == PRELUDE_PATH {
+            return self.prelude.as_ref().map(|s| &**s);
+        }
+        self.overlays.iter()
+            .find(|&&(ref p, _)| p == path)
+            .map(|&(_, ref contents)| &**contents)
+    }
+
+    /// Returns the overlays (including the prelude) in the form Clang wants them.
+    fn unsaved_files(&self) -> Vec<clang::UnsavedFile> {
+        self.overlays.iter()
+            .map(|&(ref path, ref contents)| clang::UnsavedFile::new(path, contents))
+            .chain(self.prelude.iter().map(|prelude| clang::UnsavedFile::new(PRELUDE_PATH, prelude)))
+            .collect()
     }
 
     /// Determines whether or not the declaration at the given `Cursor` should be ignored.
@@ -303,3 +447,107 @@ pub enum NativeCallConv {
     C,
     Stdcall,
 }
+
+/**
+Represents the character set that `TCHAR`-style declarations resolve to.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum CharSet {
+    /// `TCHAR` is `CHAR`; `TEXT("x")` is a narrow string.
+    Ansi,
+    /// `TCHAR` is `WCHAR`; `TEXT("x")` is a wide string.
+    Unicode,
+}
+
+impl CharSet {
+    /// The Clang switches needed to select this character set.
+    fn switches(self) -> &'static [&'static str] {
+        use self::CharSet::*;
+        match self {
+            Ansi => &[],
+            Unicode => &["-DUNICODE", "-D_UNICODE"],
+        }
+    }
+}
+
+/**
+What to do when a conditional compilation directive can't be parsed or evaluated.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum FeatureErrorPolicy {
+    /// Evaluate whatever parts of the expression can be; anything else says nothing about the feature set.
+    Unknown,
+    /// Treat the condition as always being true.
+    AlwaysTrue,
+    /// Stop processing with an error.
+    Abort,
+}
+
+/**
+A family of related integer constants that should also be emitted as a newtype.
+
+Each member is emitted as an associated constant on the newtype, in addition to the usual loose `const`.  The newtype wraps the type of the first member encountered.
+*/
+#[derive(Clone, Debug)]
+pub struct ConstGroup {
+    /// Name of the newtype.
+    pub name: String,
+
+    /// Which constants belong to this group.
+    pub members: ConstGroupMembers,
+
+    /// If `true`, the constants are flags, and the newtype gets the bitwise operators.
+    pub flags: bool,
+}
+
+impl ConstGroup {
+    /**
+    Works out the name of the associated constant for the given member, or `None` if it isn't a member.
+
+    `header` and `line` are where the constant was defined.
+    */
+    fn assoc_name(&self, name: &str, header: &str, line: u32) -> Option<String> {
+        use self::ConstGroupMembers::*;
+        match self.members {
+            Prefix(ref re) => match re.find(name) {
+                Some((0, end)) => {
+                    let rest = &name[end..];
+                    match rest.chars().next() {
+                        None | Some('0'...'9') => Some(name.into()),
+                        Some(_) => Some(rest.into()),
+                    }
+                },
+                _ => None
+            },
+            SourceRange { ref file, first_line, last_line } => {
+                if file == header && first_line <= line && line <= last_line {
+                    Some(name.into())
+                } else {
+                    None
+                }
+            },
+            List(ref names) => {
+                if names.iter().any(|n| n == name) {
+                    Some(name.into())
+                } else {
+                    None
+                }
+            },
+        }
+    }
+}
+
+/**
+Ways of specifying the members of a `ConstGroup`.
+*/
+#[derive(Clone, Debug)]
+pub enum ConstGroupMembers {
+    /// Constants whose names match this pattern at the start.  The matched prefix is stripped to form the associated constant's name, unless that would leave it empty or starting with a digit.
+    Prefix(Regex),
+
+    /// Constants defined in the given header (*e.g.* `"winuser"`), between the given lines inclusive.
+    SourceRange { file: String, first_line: u32, last_line: u32 },
+
+    /// Exactly these constants.
+    List(Vec<String>),
+}
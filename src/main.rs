@@ -69,9 +69,9 @@ fn try_main() -> Result<(), String> {
     info!("Running with local\\config.json...");
     let json_config: Config = rustc_serialize::json::decode(&read_file("local/config.json")).unwrap();
     let header = json_config.header;
-    let gen_config = json_config.generation.into_gen_config();
+    let gen_config = try!(json_config.generation.into_gen_config());
     let out_config = json_config.output.into_out_config();
-    bg::process_header(&header, &gen_config, &out_config);
+    try!(bg::process_header(&header, &gen_config, &out_config));
     Ok(())
 }
 
@@ -95,11 +95,29 @@ pub struct GenConfig {
     pub ignore_file_paths: Vec<String>,
     pub switches: Vec<String>,
     pub non_canonical_tag_names: Vec<String>,
+    pub char_set: Option<CharSet>,
+    pub int_macro_types: Option<Vec<(String, String)>>,
+    pub const_groups: Option<Vec<ConstGroup>>,
+    pub target_partitions: Option<Vec<String>>,
+    pub snap_versions: Option<bool>,
+    pub deprecate_removed: Option<bool>,
+    pub tracked_defines: Option<Vec<TrackedDefine>>,
+    pub feature_error_policies: Option<Vec<(String, FeatureErrorPolicy)>>,
+    pub diagnostic_threshold: Option<DiagnosticSeverity>,
+    pub parse_all_comments: Option<bool>,
+    pub sal_const_inputs: Option<bool>,
+    pub sal_const_input_exceptions: Option<Vec<String>>,
+    pub overlays: Option<Vec<Overlay>>,
+    pub prelude: Option<String>,
 }
 
 impl GenConfig {
-    pub fn into_gen_config(self) -> bg::GenConfig {
-        bg::GenConfig {
+    pub fn into_gen_config(self) -> Result<bg::GenConfig, String> {
+        let const_groups = try!(self.const_groups.unwrap_or(vec![]).into_iter()
+            .map(ConstGroup::into_const_group)
+            .collect::<Result<Vec<_>, _>>());
+
+        Ok(bg::GenConfig {
             exp_configs: self.expansion_configs.into_iter().map(ExpConfig::into_exp_config).collect(),
             dont_ignore_decl_spelling: self.dont_ignore_decl_spelling.into_iter().map(|s| re(&s)).collect(),
             ignore_decls: self.ignore_decls.into_iter().map(|(f,k,s)| (f,k.into(),s)).collect(),
@@ -107,6 +125,136 @@ impl GenConfig {
             ignore_file_paths: self.ignore_file_paths.into_iter().map(|s| re(&s)).collect(),
             switches: self.switches,
             non_canonical_tag_names: self.non_canonical_tag_names.into_iter().map(|s| re(&s)).collect(),
+            char_set: self.char_set.unwrap_or(CharSet::Unicode).into_char_set(),
+            int_macro_types: self.int_macro_types.unwrap_or(vec![]).into_iter().map(|(p, t)| (re(&p), t)).collect(),
+            const_groups: const_groups,
+            target_partitions: self.target_partitions.map(|parts| parts.iter()
+                .map(|p| bg::Partitions::from_define(p)
+                    .unwrap_or_else(|| panic!("unknown partition {:?}", p)))
+                .fold(bg::Partitions::empty(), |acc, p| acc | p)),
+            snap_versions: self.snap_versions.unwrap_or(false),
+            deprecate_removed: self.deprecate_removed.unwrap_or(false),
+            tracked_defines: self.tracked_defines.unwrap_or(vec![]).into_iter()
+                .map(|d| std::rc::Rc::new(d.into_tracked_define())).collect(),
+            feature_error_policies: self.feature_error_policies.unwrap_or(vec![]).into_iter()
+                .map(|(p, policy)| (re(&p), policy.into_feature_error_policy())).collect(),
+            diagnostic_threshold: self.diagnostic_threshold.unwrap_or(DiagnosticSeverity::Fatal).into_diagnostic_severity(),
+            parse_all_comments: self.parse_all_comments.unwrap_or(false),
+            sal_const_inputs: self.sal_const_inputs.unwrap_or(false),
+            sal_const_input_exceptions: self.sal_const_input_exceptions.unwrap_or(vec![]).into_iter().map(|s| re(&s)).collect(),
+            overlays: self.overlays.unwrap_or(vec![]).into_iter().map(Overlay::into_overlay).collect(),
+            prelude: self.prelude,
+        })
+    }
+}
+
+#[derive(Clone, Debug, RustcDecodable, RustcEncodable)]
+pub struct Overlay {
+    pub path: String,
+    pub contents: Option<String>,
+    pub source: Option<String>,
+}
+
+impl Overlay {
+    pub fn into_overlay(self) -> (String, String) {
+        let contents = match (self.contents, self.source) {
+            (Some(contents), None) => contents,
+            (None, Some(source)) => read_file(&source),
+            _ => panic!("overlay {:?} needs exactly one of \"contents\" or \"source\"", self.path)
+        };
+        (self.path, contents)
+    }
+}
+
+#[derive(Clone, Debug, RustcDecodable, RustcEncodable)]
+pub struct ConstGroup {
+    pub name: String,
+    pub prefix: Option<String>,
+    pub source_range: Option<(String, u32, u32)>,
+    pub members: Option<Vec<String>>,
+    pub flags: Option<bool>,
+}
+
+impl ConstGroup {
+    pub fn into_const_group(self) -> Result<bg::ConstGroup, String> {
+        use bg::ConstGroupMembers as CGM;
+        let members = match (self.prefix, self.source_range, self.members) {
+            (Some(prefix), None, None) => CGM::Prefix(re(&format!("^(?:{})", prefix))),
+            (None, Some((file, first_line, last_line)), None) => CGM::SourceRange {
+                file: file,
+                first_line: first_line,
+                last_line: last_line,
+            },
+            (None, None, Some(members)) => CGM::List(members),
+            _ => return Err(format!("const group {:?} must have exactly one of prefix, source_range or members", self.name))
+        };
+        Ok(bg::ConstGroup {
+            name: self.name,
+            members: members,
+            flags: self.flags.unwrap_or(false),
+        })
+    }
+}
+
+#[derive(Clone, Debug, RustcDecodable, RustcEncodable)]
+pub struct TrackedDefine {
+    pub name: String,
+    pub values: Vec<(String, String)>,
+}
+
+impl TrackedDefine {
+    pub fn into_tracked_define(self) -> bg::TrackedDefine {
+        let mut values: Vec<_> = self.values.into_iter()
+            .map(|(v, feat)| (parse_int(&v), feat))
+            .collect();
+        values.sort();
+        bg::TrackedDefine {
+            name: self.name,
+            values: values,
+        }
+    }
+}
+
+fn parse_int(s: &str) -> u32 {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u32::from_str_radix(&s[2..], 16).unwrap()
+    } else {
+        s.parse().unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, RustcDecodable, RustcEncodable)]
+pub enum FeatureErrorPolicy {
+    Unknown,
+    AlwaysTrue,
+    Abort,
+}
+
+impl FeatureErrorPolicy {
+    pub fn into_feature_error_policy(self) -> bg::FeatureErrorPolicy {
+        use self::FeatureErrorPolicy::*;
+        match self {
+            Unknown => bg::FeatureErrorPolicy::Unknown,
+            AlwaysTrue => bg::FeatureErrorPolicy::AlwaysTrue,
+            Abort => bg::FeatureErrorPolicy::Abort,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, RustcDecodable, RustcEncodable)]
+pub enum DiagnosticSeverity {
+    Warning,
+    Error,
+    Fatal,
+}
+
+impl DiagnosticSeverity {
+    pub fn into_diagnostic_severity(self) -> bg::DiagnosticSeverity {
+        use self::DiagnosticSeverity::*;
+        match self {
+            Warning => bg::DiagnosticSeverity::Warning,
+            Error => bg::DiagnosticSeverity::Error,
+            Fatal => bg::DiagnosticSeverity::Fatal,
         }
     }
 }
@@ -189,6 +337,22 @@ impl NativeCallConv {
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, RustcDecodable, RustcEncodable)]
+pub enum CharSet {
+    Ansi,
+    Unicode,
+}
+
+impl CharSet {
+    pub fn into_char_set(self) -> bg::CharSet {
+        use self::CharSet::*;
+        match self {
+            Ansi => bg::CharSet::Ansi,
+            Unicode => bg::CharSet::Unicode,
+        }
+    }
+}
+
 pub fn read_symbol_list(path: &str) -> HashMap<String, Vec<String>> {
     use std::fs;
     use std::io;
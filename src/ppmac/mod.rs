@@ -8,7 +8,12 @@ pub enum Node {
     Conditional { cond: Box<Node>, then_expr: Box<Node>, else_expr: Box<Node> },
     Ident(String),
     Integer(u64, Signed, Size),
-    String(String, CharWidth),
+    /// A `FIELD_OFFSET(ty, field)` or `offsetof(ty, field)` expression.  `field` may be a dotted path.
+    OffsetOf { ty: Box<Node>, field: String },
+    /// A `sizeof(type)` or `sizeof expr` expression.
+    SizeOf(Box<Node>),
+    /// A string literal, with escapes decoded into code units and adjacent literals concatenated.
+    String(Vec<u32>, CharWidth),
     Type(String, bool),
     Unary(UnaryOp, Box<Node>),
 }
@@ -50,6 +55,7 @@ pub enum Signed {
 pub enum Size {
     Unknown,
     Long,
+    LongLong,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -316,11 +322,15 @@ pub mod parse {
 
     /*
 
-    type_id => identifier "*"? // Simplified.  A lot.
+    type_id => ("struct" | "union" | "enum")? identifier "*"? // Simplified.  A lot.
 
     */
     fn type_id(toks: Toks) -> Result<Node> {
-        parse_identifier(toks)
+        munch!("struct")(toks)
+            .or_else(munch!("union"))
+            .or_else(munch!("enum"))
+            .or_else(|toks| Parsed((), toks))
+            .and_then(parse_identifier)
             .and_then_with(|ident, toks| {
                 let mut ident = DynMove(Some(ident));
                 munch!("*")(toks)
@@ -329,7 +339,7 @@ pub mod parse {
             })
     }
 
-    // unary => ("-" | "~") cast | postfix; // REWIND cast
+    // unary => ("-" | "~") cast | sizeof | postfix; // REWIND cast
     fn unary(toks: Toks) -> Result<Node> {
         munch!("-")(toks)
             .and_then(cast)
@@ -348,9 +358,24 @@ pub mod parse {
                 )
             )
         )
+        .or_else(sizeof)
         .or_else(postfix)
     }
 
+    // sizeof => "sizeof" "(" type_id ")" | "sizeof" unary; // REWIND type_id
+    fn sizeof(toks: Toks) -> Result<Node> {
+        munch!("sizeof")(toks)
+            .and_then(|toks| munch!("(")(toks)
+                .and_then(type_id)
+                .and_then_with(|ty, toks| munch!(")")(toks)
+                    .and_then(|toks| Parsed(Node::SizeOf(Box::new(ty)), toks))
+                )
+                .or_else(|_| unary(toks)
+                    .map(|expr| Node::SizeOf(Box::new(expr)))
+                )
+            )
+    }
+
     // postfix => primary ( "(" expression_list? ")" )*;
     fn postfix(toks: Toks) -> Result<Node> {
         fn try_call(lhs: Node, toks: Toks) -> Result<Node> {
@@ -396,9 +421,10 @@ pub mod parse {
             .or_else(|toks| Parsed(vec![], toks))
     }
 
-    // primary => literal | identifier | "(" expression ")"; // REWIND expression
+    // primary => literal | offsetof | identifier | "(" expression ")"; // REWIND expression
     fn primary(toks: Toks) -> Result<Node> {
         literal(toks)
+            .or_else(offsetof)
             .or_else(identifier)
             .or_else(|toks| munch!("(")(toks)
                 .and_then(expression)
@@ -410,6 +436,42 @@ pub mod parse {
             )
     }
 
+    // offsetof => ("FIELD_OFFSET" | "offsetof") "(" type_id "," identifier ("." identifier)* ")";
+    fn offsetof(toks: Toks) -> Result<Node> {
+        fn field_path(path: String, toks: Toks) -> Result<String> {
+            let mut path = DynMove(Some(path));
+            munch!(".")(toks)
+                .and_then(parse_identifier)
+                .and_then_with(|ident, toks| {
+                    let path = format!("{}.{}", path.take(), ident);
+                    field_path(path, toks)
+                })
+                .or_else(|toks| Parsed(path.take(), toks))
+        }
+
+        munch!("FIELD_OFFSET")(toks)
+            .or_else(munch!("offsetof"))
+            .and_then(munch!("("))
+            .and_then(type_id)
+            .and_then_with(|ty, toks| {
+                let mut ty = DynMove(Some(ty));
+                munch!(",")(toks)
+                    .and_then(parse_identifier)
+                    .and_then_with(field_path)
+                    .and_then_with(|field, toks| munch!(")")(toks)
+                        .and_then(|toks|
+                            Parsed(
+                                Node::OffsetOf {
+                                    ty: Box::new(ty.take()),
+                                    field: field,
+                                },
+                                toks
+                            )
+                        )
+                    )
+            })
+    }
+
     // literal => integer_literal | character_literal | floating_literal | string_literal;
     fn literal(toks: Toks) -> Result<Node> {
         integer_literal(toks)
@@ -420,8 +482,8 @@ pub mod parse {
 
     lazy_static! {
         static ref RE_IDENT_LITERAL: Regex = Regex::new(r#"^([A-Za-z_][A-Za-z0-9_]*)$"#).unwrap();
-        static ref RE_INT_DEC_LITERAL: Regex = Regex::new(r#"^([0-9]+)([uU]?[lL]?|[lL]?[uU]?)$"#).unwrap();
-        static ref RE_INT_HEX_LITERAL: Regex = Regex::new(r#"^0[Xx]([0-9A-Fa-f]+)([uU]?[lL]?|[lL]?[uU]?)$"#).unwrap();
+        static ref RE_INT_DEC_LITERAL: Regex = Regex::new(r#"^([0-9]+)([uU]?(?:[lL]{0,2}|i64)|(?:[lL]{0,2}|i64)[uU]?)$"#).unwrap();
+        static ref RE_INT_HEX_LITERAL: Regex = Regex::new(r#"^0[Xx]([0-9A-Fa-f]+)([uU]?(?:[lL]{0,2}|i64)|(?:[lL]{0,2}|i64)[uU]?)$"#).unwrap();
         static ref RE_STR_LITERAL: Regex = Regex::new(r#"^(L?)"(.*)"$"#).unwrap();
     }
 
@@ -447,7 +509,13 @@ pub mod parse {
 
         fn parse_int<'a>(digits: &str, suffix: &str, radix: u32, toks: Toks<'a>) -> Result<'a, Node> {
             let sign = if suffix.contains("u") || suffix.contains("U") { Signed::No } else { Signed::Yes };
-            let size = if suffix.contains("l") || suffix.contains("L") { Size::Long } else { Size::Unknown };
+            let size = if suffix.contains("ll") || suffix.contains("LL") || suffix.contains("i64") {
+                Size::LongLong
+            } else if suffix.contains("l") || suffix.contains("L") {
+                Size::Long
+            } else {
+                Size::Unknown
+            };
             return Parsed(
                 Node::Integer(u64::from_str_radix(digits, radix).unwrap(), sign, size),
                 toks
@@ -469,19 +537,153 @@ pub mod parse {
         Mismatch("expected integer literal".into(), toks)
     }
 
+    /*
+
+    string_literal => string_token+;
+
+    Adjacent literals are concatenated, as in translation phase 6.  If *any* of the pieces is wide, the whole thing is.
+
+    */
     fn string_literal(toks: Toks) -> Result<Node> {
         check_empty!(toks);
 
-        if let Some(cap) = RE_STR_LITERAL.captures(&toks[0]) {
+        let mut units = vec![];
+        let mut width = CharWidth::Narrow;
+        let mut rest = toks;
+
+        while let Some(cap) = rest.get(0).and_then(|tok| RE_STR_LITERAL.captures(tok)) {
             let prefix = cap.at(1).unwrap();
             let content = cap.at(2).unwrap();
-            let width = if prefix == "L" { CharWidth::Wide } else { CharWidth::Narrow };
-            return Parsed(
-                Node::String(content.into(), width),
-                &toks[1..]
-            )
+            let piece_width = if prefix == "L" { CharWidth::Wide } else { CharWidth::Narrow };
+            if piece_width == CharWidth::Wide { width = CharWidth::Wide; }
+            match unescape(content, piece_width) {
+                Ok(piece) => units.push((piece, piece_width)),
+                Err(err) => return Mismatch(err.into(), rest)
+            }
+            rest = &rest[1..];
+        }
+
+        if units.len() == 0 {
+            return Mismatch("expected string literal".into(), toks);
+        }
+
+        /*
+        Narrow pieces in a wide literal are widened code unit by code unit, which is only correct for ASCII.  Anything else would need to be transcoded from the execution character set, which we don't know.
+        */
+        let mut result = vec![];
+        for (piece, piece_width) in units {
+            if width == CharWidth::Wide && piece_width == CharWidth::Narrow
+                && piece.iter().any(|&cu| cu > 0x7f)
+            {
+                return Mismatch("non-ASCII narrow literal concatenated with wide literal".into(), toks);
+            }
+            result.extend(piece);
+        }
+
+        Parsed(Node::String(result, width), rest)
+    }
+
+    /**
+    Decodes the escape sequences in the body of a string literal into code units.
+
+    Narrow literals produce UTF-8 code units, wide literals produce UTF-16 code units.
+    */
+    fn unescape(s: &str, width: CharWidth) -> ::std::result::Result<Vec<u32>, String> {
+        fn push_char(units: &mut Vec<u32>, c: char, width: CharWidth) {
+            let mut buf = String::new();
+            buf.push(c);
+            match width {
+                CharWidth::Narrow => units.extend(buf.bytes().map(|b| b as u32)),
+                CharWidth::Wide => units.extend(buf.utf16_units().map(|u| u as u32)),
+            }
+        }
+
+        fn push_code_point(units: &mut Vec<u32>, cp: u32, width: CharWidth) -> ::std::result::Result<(), String> {
+            match ::std::char::from_u32(cp) {
+                Some(c) => { push_char(units, c, width); Ok(()) },
+                None => Err(format!("invalid universal character name U+{:04X}", cp))
+            }
+        }
+
+        let max_unit = match width {
+            CharWidth::Narrow => 0xff,
+            CharWidth::Wide => 0xffff,
+        };
+
+        let mut units = vec![];
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                push_char(&mut units, c, width);
+                continue;
+            }
+
+            let c = match chars.next() {
+                Some(c) => c,
+                None => return Err("unterminated escape sequence".into())
+            };
+
+            match c {
+                'a' => units.push(0x07),
+                'b' => units.push(0x08),
+                'f' => units.push(0x0c),
+                'n' => units.push(0x0a),
+                'r' => units.push(0x0d),
+                't' => units.push(0x09),
+                'v' => units.push(0x0b),
+                '\\' | '\'' | '"' | '?' => units.push(c as u32),
+
+                '0'...'7' => {
+                    let mut v = c.to_digit(8).unwrap();
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(8)) {
+                            Some(d) => { v = v * 8 + d; chars.next(); },
+                            None => break
+                        }
+                    }
+                    if v > max_unit {
+                        return Err(format!("octal escape out of range: {:o}", v));
+                    }
+                    units.push(v);
+                },
+
+                'x' => {
+                    let mut v: u32 = 0;
+                    let mut digits = 0;
+                    while let Some(d) = chars.peek().and_then(|c| c.to_digit(16)) {
+                        v = match v.checked_mul(16) {
+                            Some(v) => v + d,
+                            None => return Err("hex escape out of range".into())
+                        };
+                        digits += 1;
+                        chars.next();
+                    }
+                    if digits == 0 {
+                        return Err("\\x used with no following hex digits".into());
+                    }
+                    if v > max_unit {
+                        return Err(format!("hex escape out of range: {:x}", v));
+                    }
+                    units.push(v);
+                },
+
+                'u' | 'U' => {
+                    let len = if c == 'u' { 4 } else { 8 };
+                    let mut v: u32 = 0;
+                    for _ in 0..len {
+                        match chars.next().and_then(|c| c.to_digit(16)) {
+                            Some(d) => v = v * 16 + d,
+                            None => return Err("incomplete universal character name".into())
+                        }
+                    }
+                    try!(push_code_point(&mut units, v, width));
+                },
+
+                c => return Err(format!("unknown escape sequence \\{}", c))
+            }
         }
 
-        Mismatch("expected string literal".into(), toks)
+        Ok(units)
     }
 }
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use {GenConfig, WinVersion};
+use features::{Features, WinVersions, version_name};
+
+use super::output::OutputItems;
+
+/**
+Works out the list of versions which feature sets should be snapped to.
+
+This is every version we expanded for, plus every version we know the name of.
+*/
+pub fn version_grid(gen_config: &GenConfig) -> Vec<u32> {
+    let mut grid: Vec<u32> = gen_config.exp_configs.iter()
+        .map(|c| c.winver.1)
+        .chain(WinVersion::ALL.iter().map(|&v| v as u32))
+        .collect();
+    grid.sort();
+    grid.dedup();
+    grid
+}
+
+/**
+Quantises the version component of every item's feature set to the given grid.
+*/
+pub fn snap_versions(items: &mut OutputItems, grid: &[u32]) {
+    use std::mem::replace;
+    debug!("snap_versions(_, {:?})", grid);
+
+    fn snap(feat: &mut Features, grid: &[u32]) {
+        if let Some(winver) = replace(&mut feat.winver, None) {
+            feat.winver = Some(winver.snap_to(grid));
+        }
+    }
+
+    for (_, decls) in items.fn_aliases.iter_mut() {
+        for &mut (_, _, ref mut feat, _, _) in decls.iter_mut() {
+            snap(feat, grid);
+        }
+    }
+    for (_, decls) in items.fn_items.iter_mut() {
+        for &mut (_, ref mut feat, _, _, _) in decls.iter_mut() {
+            snap(feat, grid);
+        }
+    }
+    for (_, decls) in items.header_items.iter_mut() {
+        for &mut (_, _, ref mut feat, _, _) in decls.iter_mut() {
+            snap(feat, grid);
+        }
+    }
+    for (_, decls) in items.var_items.iter_mut() {
+        for &mut (_, ref mut feat, _, _) in decls.iter_mut() {
+            snap(feat, grid);
+        }
+    }
+}
+
+/**
+Reports every item whose availability changes between consecutive versions in the grid.
+
+Items with more than one declaration are considered available wherever *any* of their declarations are.
+*/
+pub fn report_availability(items: &OutputItems, grid: &[u32]) {
+    let mut avail: BTreeMap<String, WinVersions> = BTreeMap::new();
+    {
+        let mut add = |name: &str, feat: &Features| {
+            let winver = match feat.winver {
+                Some(ref winver) => winver.clone(),
+                None => (..).into()
+            };
+            let winver = match avail.remove(name) {
+                Some(prev) => prev.union(winver),
+                None => winver
+            };
+            avail.insert(name.into(), winver);
+        };
+
+        for (name, decls) in &items.fn_aliases {
+            for &(_, _, ref feat, _, _) in decls { add(name, feat); }
+        }
+        for (name, decls) in &items.fn_items {
+            for &(_, ref feat, _, _, _) in decls { add(name, feat); }
+        }
+        for (name, decls) in &items.header_items {
+            for &(_, _, ref feat, _, _) in decls { add(name, feat); }
+        }
+        for (name, decls) in &items.var_items {
+            for &(_, ref feat, _, _) in decls { add(name, feat); }
+        }
+    }
+
+    info!("availability changes:");
+    for (name, winver) in avail {
+        let mut prev = None;
+        for &v in grid {
+            let cur = winver.contains(v);
+            match (prev, cur) {
+                (Some(false), true) => info!(".. {}: introduced in {}", name, version_name(v)),
+                (Some(true), false) => info!(".. {}: removed in {}", name, version_name(v)),
+                _ => ()
+            }
+            prev = Some(cur);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+/*!
+Translates the comments attached to declarations into Rust documentation.
+
+By default, Clang only attaches "documentation" comments (`/** */`, `///`, *etc.*) to declarations.  The Windows headers mostly use plain comments, so the `parse_all_comments` option is needed to get much out of this.
+*/
+use clang::{Comment, CommentKind, Cursor};
+use super::sal::ParamSal;
+
+/**
+The documentation for a declaration, broken into the bits Rust docs like to keep separate.
+*/
+#[derive(Clone, Debug, Default)]
+struct Docs {
+    paragraphs: Vec<String>,
+    params: Vec<(String, String)>,
+    returns: Vec<String>,
+}
+
+impl Docs {
+    fn is_empty(&self) -> bool {
+        self.paragraphs.len() == 0 && self.params.len() == 0 && self.returns.len() == 0
+    }
+
+    fn into_markdown(self) -> String {
+        let mut sections = self.paragraphs;
+
+        if self.params.len() > 0 {
+            sections.push("# Parameters".into());
+            sections.push(self.params.into_iter()
+                .map(|(name, desc)| format!("* `{}`: {}", name, desc))
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+
+        if self.returns.len() > 0 {
+            sections.push("# Returns".into());
+            sections.extend(self.returns);
+        }
+
+        sections.join("\n\n")
+    }
+}
+
+/**
+Returns a `#[doc]` attribute (followed by a space) for the comment attached to the given cursor, or nothing if there isn't one.
+*/
+pub fn doc_attr(cur: &Cursor) -> String {
+    to_attr(docs_for(cur))
+}
+
+/**
+Like `doc_attr`, except that the given parameters are also listed, along with their comments and SAL annotations.
+
+`sal` must have one entry per parameter.
+*/
+pub fn doc_attr_with_params(cur: &Cursor, params: &[Cursor], sal: &[ParamSal]) -> String {
+    let mut docs = docs_for(cur);
+    let mut described = vec![];
+    for (param, sal) in params.iter().zip(sal.iter()) {
+        let name = param.spelling();
+        if name == "" {
+            continue;
+        }
+
+        let desc = match docs.params.iter().position(|&(ref n, _)| *n == name) {
+            Some(i) => docs.params.remove(i).1,
+            None => docs_for(param).paragraphs.join(" ")
+        };
+
+        let entry = match (sal.is_empty(), desc == "") {
+            (true, true) => continue,
+            (true, false) => desc,
+            (false, true) => format!("`{}`", sal),
+            (false, false) => format!("`{}` {}", sal, desc),
+        };
+        described.push((name, entry));
+    }
+
+    // Keep anything the comment documents that isn't actually a parameter.
+    described.extend(docs.params.drain(..));
+    docs.params = described;
+    to_attr(docs)
+}
+
+fn to_attr(docs: Docs) -> String {
+    if docs.is_empty() {
+        String::new()
+    } else {
+        format!("#[doc={:?}] ", docs.into_markdown())
+    }
+}
+
+fn docs_for(cur: &Cursor) -> Docs {
+    let mut docs = Docs::default();
+    let comment = match cur.parsed_comment() {
+        Some(comment) => comment,
+        None => return docs
+    };
+    debug!("docs_for({}): {:?}", cur, cur.raw_comment());
+
+    for child in comment.children() {
+        match child.kind() {
+            CommentKind::Paragraph => push_non_empty(&mut docs.paragraphs, render_inline(&child)),
+            CommentKind::ParamCommand => {
+                let name = child.param_name().unwrap_or(String::new());
+                let desc = child.paragraph().map(|p| render_inline(&p)).unwrap_or(String::new());
+                if name != "" && desc != "" {
+                    docs.params.push((name, desc));
+                }
+            },
+            CommentKind::BlockCommand => {
+                let name = child.command_name().unwrap_or(String::new());
+                let desc = child.paragraph().map(|p| render_inline(&p)).unwrap_or(String::new());
+                match &*name {
+                    "brief" | "short" | "details" => push_non_empty(&mut docs.paragraphs, desc),
+                    "return" | "returns" | "result" => push_non_empty(&mut docs.returns, desc),
+                    _ => push_non_empty(&mut docs.paragraphs, format!("**{}**: {}", name, desc))
+                }
+            },
+            CommentKind::VerbatimBlockCommand => {
+                let lines: Vec<_> = child.children().into_iter()
+                    .filter_map(|line| line.text())
+                    .collect();
+                docs.paragraphs.push(format!("```text\n{}\n```", lines.join("\n")));
+            },
+            CommentKind::VerbatimLine => {
+                push_non_empty(&mut docs.paragraphs, child.text().unwrap_or(String::new()).trim().into());
+            },
+            _ => ()
+        }
+    }
+
+    docs
+}
+
+fn push_non_empty(acc: &mut Vec<String>, s: String) {
+    if s != "" {
+        acc.push(s);
+    }
+}
+
+/**
+Renders the inline content of a paragraph as a single line of Markdown.
+*/
+fn render_inline(para: &Comment) -> String {
+    use clang::InlineCommandRenderKind as RK;
+
+    let mut parts = vec![];
+    for child in para.children() {
+        match child.kind() {
+            CommentKind::Text => parts.push(child.text().unwrap_or(String::new())),
+            CommentKind::InlineCommand => {
+                let args = child.args().join(" ");
+                parts.push(match child.render_kind().unwrap_or(RK::Normal) {
+                    RK::Normal => args,
+                    RK::Bold => format!("**{}**", args),
+                    RK::Monospaced => format!("`{}`", args),
+                    RK::Emphasized => format!("*{}*", args),
+                });
+            },
+            // HTML tags are dropped, but their contents (which are just text) are kept.
+            _ => ()
+        }
+    }
+
+    // Comments are wrapped to suit C source, not Markdown; reflow them.
+    let text = parts.join(" ");
+    let words: Vec<_> = text.split_whitespace().collect();
+    words.join(" ")
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use GenConfig;
+use features::Features;
+
+use super::escape_ident;
+use super::output::OutputItems;
+use super::trans_macros::{IntConst, IntTy};
+
+/**
+Tracks which header and underlying type each group's newtype was given.
+
+Both are taken from the first member encountered, so that every member ends up attached to the same declaration.
+*/
+pub type GroupMap = HashMap<String, GroupInfo>;
+
+#[derive(Clone, Debug)]
+pub struct GroupInfo {
+    header: String,
+    ty: IntTy,
+}
+
+/**
+Adds a translated constant to every group it belongs to.
+
+Membership is entirely down to the `const_groups` in the `GenConfig`; we make no attempt to guess.
+*/
+pub fn add_const_to_groups(
+    name: &str,
+    value: &IntConst,
+    header: &str,
+    line: u32,
+    feat: &Features,
+    annot: &str,
+    gen_config: &GenConfig,
+    groups: &mut GroupMap,
+    output: &mut OutputItems,
+) {
+    for group in &gen_config.const_groups {
+        let assoc = match group.assoc_name(name, header, line) {
+            Some(assoc) => assoc,
+            None => continue
+        };
+        debug!("add_const_to_groups: {} is {}::{}", name, group.name, assoc);
+
+        let info = groups.entry(group.name.clone())
+            .or_insert_with(|| GroupInfo {
+                header: header.into(),
+                ty: value.ty.clone(),
+            })
+            .clone();
+
+        let group_name = escape_ident(group.name.clone());
+        let decl = newtype_decl(&group_name, &info.ty, group.flags);
+        output.add_header_item(group.name.clone(), info.header.clone(), feat.clone(), decl, annot.into());
+
+        let value = value.clone().convert(info.ty.clone());
+        let decl = format!("impl {g} {{ pub const {a}: {g} = {g}({v}); }}",
+            g = group_name,
+            a = escape_ident(assoc.clone()),
+            v = value.render());
+        output.add_header_item(format!("{}::{}", group.name, assoc), info.header, feat.clone(), decl, annot.into());
+    }
+}
+
+/**
+Generates the newtype declaration, along with the bitwise operators if the group is a set of flags.
+*/
+fn newtype_decl(name: &str, ty: &IntTy, flags: bool) -> String {
+    let mut decl = format!(
+        "#[repr(transparent)] #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)] pub struct {n}(pub {t});",
+        n = name, t = ty.name);
+
+    if flags {
+        for &(tr, method, op) in &[
+            ("BitOr", "bitor", "|"),
+            ("BitAnd", "bitand", "&"),
+            ("BitXor", "bitxor", "^"),
+        ] {
+            decl.push_str(&format!(
+                " ${{feat}}impl ::core::ops::{tr} for {n} {{ type Output = {n}; fn {m}(self, rhs: {n}) -> {n} {{ {n}(self.0 {op} rhs.0) }} }}",
+                tr = tr, m = method, n = name, op = op));
+            decl.push_str(&format!(
+                " ${{feat}}impl ::core::ops::{tr}Assign for {n} {{ fn {m}_assign(&mut self, rhs: {n}) {{ self.0 = self.0 {op} rhs.0; }} }}",
+                tr = tr, m = method, n = name, op = op));
+        }
+        decl.push_str(&format!(
+            " ${{feat}}impl ::core::ops::Not for {n} {{ type Output = {n}; fn not(self) -> {n} {{ {n}(!self.0) }} }}",
+            n = name));
+    }
+
+    decl
+}
@@ -0,0 +1,71 @@
+/*!
+Works out which declarations the headers mark as deprecated, so the generated items can be marked `#[deprecated]` as well.
+
+This covers `__declspec(deprecated)`, `__attribute__((deprecated))`, `[[deprecated]]` and the `DEPRECATED`-style macros that expand to them.
+*/
+use clang::{Availability, Cursor};
+
+/**
+Returns a `#[deprecated]` attribute (followed by a space) if the given declaration is deprecated, or nothing if it isn't.
+*/
+pub fn deprecated_attr(cur: &Cursor) -> String {
+    match deprecation_note(cur) {
+        Some(note) => format!("#[deprecated(note={:?})] ", note),
+        None => String::new()
+    }
+}
+
+/**
+Returns the reason the given declaration is deprecated, or `None` if it isn't.
+*/
+pub fn deprecation_note(cur: &Cursor) -> Option<String> {
+    let avail = cur.platform_availability();
+    let attr = deprecated_attribute(cur);
+
+    let is_deprecated = cur.availability() == Availability::Deprecated
+        || avail.always_deprecated
+        || attr.is_some();
+    if !is_deprecated {
+        return None;
+    }
+
+    let note = avail.deprecated_message
+        .or_else(|| avail.platforms.iter().filter_map(|p| p.message.clone()).next())
+        .or_else(|| attr.and_then(|msg| msg))
+        .unwrap_or_else(|| "deprecated in the Windows headers".into());
+    debug!("deprecation_note({}): {:?}", cur, note);
+    Some(note)
+}
+
+/**
+Looks for a `deprecated` attribute amongst the children of a declaration.
+
+Older versions of libclang expose these as `UnexposedAttr`, which says nothing about availability, so this falls back on the attribute's tokens.  The result is `Some(message)` if there is such an attribute.
+*/
+fn deprecated_attribute(cur: &Cursor) -> Option<Option<String>> {
+    for attr in cur.attributes() {
+        let toks: Vec<String> = attr.tokenize().into_iter().map(|tok| tok.spelling()).collect();
+        let pos = match toks.iter().position(|tok| tok == "deprecated") {
+            Some(pos) => pos,
+            None => continue
+        };
+
+        let msg = toks[pos + 1..].iter()
+            .skip_while(|tok| *tok == "(")
+            .next()
+            .and_then(|tok| unquote(tok));
+        return Some(msg);
+    }
+    None
+}
+
+/**
+Turns a (narrow) C string literal into its contents.
+*/
+fn unquote(tok: &str) -> Option<String> {
+    if tok.len() < 2 || !tok.starts_with("\"") || !tok.ends_with("\"") {
+        return None;
+    }
+    let s = tok[1..tok.len()-1].replace("\\\"", "\"").replace("\\\\", "\\");
+    if s == "" { None } else { Some(s) }
+}
@@ -5,38 +5,61 @@ use clang::{
     self,
     RcIndexExt,
 };
-use features::{Features, scan_features};
-use util;
+use features::{EvalContext, Features, scan_features};
 
 use super::{Cache, TuCache};
 
 /**
 Calculate the feature set map for a given file.
 */
-fn get_all_features<'a>(file: clang::File, cache: &'a mut Cache) -> &'a BTreeMap<u32, Features> {
+fn get_all_features<'a>(file: clang::File, cache: &'a mut Cache) -> Result<&'a BTreeMap<u32, Features>, String> {
     let path = file.file_name();
-    let tu_cache = &mut cache.tu;
-    let fmap = cache.features.entry(path.clone()).or_insert_with(||
-        scan_features(get_token_lines(file, tu_cache)));
+    if !cache.features.contains_key(&path) {
+        let gen_config = cache.tu.gen_config;
+        let ctx = EvalContext {
+            tracked_defines: &gen_config.tracked_defines,
+            lenient: false,
+        };
+        let policy = gen_config.feature_error_policy(&path);
+        let tls = get_token_lines(file, &mut cache.tu);
+        let (fmap, fallbacks) = try!(scan_features(tls, ctx, policy));
+        cache.feature_fallbacks.extend(fallbacks);
+        cache.features.insert(path.clone(), fmap);
+    }
 
-    fmap
+    Ok(&cache.features[&path])
 }
 
 /**
 Calculate the feature set at a given line.
 */
-pub fn get_features_at(file: clang::File, line: u32, cache: &mut Cache) -> Features {
+pub fn get_features_at(file: clang::File, line: u32, cache: &mut Cache) -> Result<Features, String> {
     use std::collections::Bound;
 
     debug!("get_features_at({:?}, {}, _)", file.file_name(), line);
 
-    let fmap = get_all_features(file, cache);
-    fmap.range(Bound::Unbounded, Bound::Included(&line)).next_back()
+    let fmap = try!(get_all_features(file, cache));
+    Ok(fmap.range(Bound::Unbounded, Bound::Included(&line)).next_back()
         .map(|(i, v)| {
             debug!(".. found: {}: {:?}", i, v);
             v.clone()
         })
-        .unwrap_or_else(|| Features::default())
+        .unwrap_or_else(|| Features::default()))
+}
+
+/**
+Logs every conditional compilation directive which had to be recovered from.
+*/
+pub fn report_fallbacks(cache: &Cache) {
+    if cache.feature_fallbacks.len() == 0 {
+        return;
+    }
+
+    warn!("{} conditional expression(s) could not be evaluated:", cache.feature_fallbacks.len());
+    for fallback in &cache.feature_fallbacks {
+        warn!(".. {}: treated as {:?}, giving {:?}", fallback.location, fallback.policy, fallback.result);
+        warn!("   {}", fallback.error);
+    }
 }
 
 /**
@@ -47,11 +70,10 @@ fn get_token_lines(file: clang::File, tu_cache: &mut TuCache) -> Vec<(u32, Vec<c
     let path = file.file_name();
 
     // Architecture shouldn't matter since we just want the tokens.
-    let tu = tu_cache.parse_translation_unit(&path, &ExpConfig::DUMMY_CFG).unwrap();
+    let tu = tu_cache.parse_unchecked(&path, &ExpConfig::DUMMY_CFG).unwrap();
 
     // Get the set of line numbers which *contain* a line continuation.
-    let cont_lines: Vec<_> = util::read_lines(&path)
-        .map(|rs| rs.unwrap())
+    let cont_lines: Vec<_> = tu_cache.read_lines(&path).into_iter()
         .enumerate()
         .filter(|&(_, ref s)| s.trim_right().ends_with("\\"))
         // +1 because enumerate is 0-based, line numbers are 1-based
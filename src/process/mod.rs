@@ -10,17 +10,25 @@ use clang::{
     self,
     Index, RcIndexExt,
     TranslationUnit, TranslationUnitFlags,
-    Cursor,
+    Cursor, DiagnosticSeverity,
 };
-use features::Features;
+use features::{Fallback as FeatureFallback, Features, Universe};
 
+mod availability;
+mod comments;
+mod const_groups;
+mod deprecation;
 mod features;
 mod output;
 mod renames;
+mod sal;
 mod sanity;
 mod trans_decls;
+mod trans_inline;
 mod trans_macros;
 
+#[cfg(test)] mod tests;
+
 use self::output::OutputItems;
 use self::renames::Renames;
 
@@ -54,6 +62,9 @@ pub struct Cache<'a> {
 
     /// Evaluated per-line feature sets.
     features: HashMap<String, BTreeMap<u32, Features>>,
+
+    /// Conditional compilation directives which had to be recovered from whilst computing `features`.
+    feature_fallbacks: Vec<FeatureFallback>,
 }
 
 impl<'a> Cache<'a> {
@@ -61,6 +72,7 @@ impl<'a> Cache<'a> {
         Cache {
             tu: TuCache::new(index, gen_config),
             features: HashMap::new(),
+            feature_fallbacks: vec![],
         }
     }
 
@@ -97,6 +109,16 @@ impl<'a> TuCache<'a> {
         }
     }
 
+    /**
+    Returns the lines of the file at the given path, taking overlays into account.
+    */
+    pub fn read_lines(&self, path: &str) -> Vec<String> {
+        match self.gen_config.overlay(path) {
+            Some(contents) => contents.lines().map(Into::into).collect(),
+            None => ::util::read_lines(path).map(|rs| rs.unwrap()).collect()
+        }
+    }
+
     /**
     Parse a translation unit with the given expansion config.
 
@@ -106,6 +128,21 @@ impl<'a> TuCache<'a> {
         &mut self,
         path: &str,
         exp_config: &ExpConfig,
+    ) -> Result<Rc<TranslationUnit>, String> {
+        let tu = try!(self.parse_unchecked(path, exp_config).map_err(|err| format!("could not parse {:?}: {}", path, err)));
+        try!(check_diagnostics(&tu, path, self.gen_config.diagnostic_threshold));
+        Ok(tu)
+    }
+
+    /**
+    Parse a translation unit with the given expansion config, without checking for diagnostics.
+
+    This is for when all that's wanted are the tokens: individual headers are rarely self-contained, so parsing them by themselves produces all sorts of spurious errors.
+    */
+    pub fn parse_unchecked(
+        &mut self,
+        path: &str,
+        exp_config: &ExpConfig,
     ) -> Result<Rc<TranslationUnit>, clang::ErrorCode> {
         let index_opts = TranslationUnitFlags::None
             | TranslationUnitFlags::DetailedPreprocessingRecord
@@ -120,14 +157,14 @@ impl<'a> TuCache<'a> {
             return Ok(rc_tu.clone())
         }
 
-        let switches: Vec<String> = self.gen_config.switches().iter().map(|s| s.clone())
+        let switches: Vec<String> = self.gen_config.switches().into_iter()
             .chain(exp_config.switches().into_iter())
             .collect();
 
         let tu = try!(self.index.parse_translation_unit(
             path,
             &switches,
-            &[],
+            &self.gen_config.unsaved_files(),
             index_opts,
         ));
         self.cache.insert(key, tu.clone());
@@ -135,6 +172,39 @@ impl<'a> TuCache<'a> {
     }
 }
 
+/**
+Logs the diagnostics from a freshly parsed translation unit.
+
+Fails if any of them are at or above `threshold`; otherwise they are just logged.
+*/
+fn check_diagnostics(tu: &TranslationUnit, path: &str, threshold: DiagnosticSeverity) -> Result<(), String> {
+    let diags = tu.diagnostics();
+    let mut failures = 0;
+
+    for diag in &diags {
+        let severity = diag.severity();
+        if severity >= threshold {
+            error!("{}", diag);
+            failures += 1;
+        } else if severity >= DiagnosticSeverity::Warning {
+            warn!("{}", diag);
+        } else {
+            debug!("{}", diag);
+        }
+
+        for fix_it in diag.fix_its() {
+            debug!(".. fix-it: {:?} at {}", fix_it.replacement,
+                fix_it.range.map(|r| r.start().display_short().to_string()).unwrap_or("?".into()));
+        }
+    }
+
+    if failures > 0 {
+        Err(format!("{} diagnostic(s) at or above {} when parsing {:?}", failures, threshold, path))
+    } else {
+        Ok(())
+    }
+}
+
 /**
 This is the unique key for each entry in the `TuCache`.
 */
@@ -149,7 +219,8 @@ impl TuCacheKey {
 /**
 This is effectively the "entry point" for processing.  Given a header and a configuration, it attempts to generate a Rust binding.
 */
-pub fn process_header(path: &str, gen_config: &GenConfig, out_config: &OutConfig) {
+pub fn process_header(path: &str, gen_config: &GenConfig, out_config: &OutConfig) -> Result<(), String> {
+    try!(clang::load());
     info!("using clang version {}", clang::version());
 
     let index = Index::create(
@@ -164,11 +235,39 @@ pub fn process_header(path: &str, gen_config: &GenConfig, out_config: &OutConfig
     for exp_config in &gen_config.exp_configs {
         info!("expanding with config {:?}", exp_config);
         info!(".. switches: {:?}", exp_config.switches());
-        let tu = cache.tu.parse_translation_unit(path, exp_config).ok().expect("parse TU");
+        let tu = match cache.tu.parse_translation_unit(path, exp_config) {
+            Ok(tu) => tu,
+            Err(err) => {
+                features::report_fallbacks(&cache);
+                return Err(err);
+            }
+        };
         let renames = renames::scan_for_renames(tu.clone(), gen_config);
-        trans_decls::process_decls(tu, gen_config, exp_config, &mut out_items, &mut cache, &renames);
+        let result = trans_decls::process_decls(tu, gen_config, exp_config, &mut out_items, &mut cache, &renames);
+        if let Err(err) = result {
+            features::report_fallbacks(&cache);
+            return Err(err);
+        }
+    }
+
+    if let Some(parts) = gen_config.target_partitions {
+        info!("restricting output to partitions {:?}...", parts);
+        out_items.restrict_partitions(parts);
+    }
+
+    if gen_config.snap_versions {
+        info!("snapping versions...");
+        let grid = availability::version_grid(gen_config);
+        availability::snap_versions(&mut out_items, &grid);
+        availability::report_availability(&out_items, &grid);
     }
 
+    info!("adding availability docs...");
+    out_items.add_availability_docs(gen_config.deprecate_removed);
+
+    info!("simplifying features...");
+    out_items.simplify_features(&Universe::from_exp_configs(&gen_config.exp_configs));
+
     info!("generating output...");
     let mut out_files = output::OutputFiles::new(out_config);
     output::output_header_items(&out_items, &mut out_files);
@@ -177,30 +276,9 @@ pub fn process_header(path: &str, gen_config: &GenConfig, out_config: &OutConfig
 
     info!("sanity-checking features...");
     sanity::sanity_check_features(&mut cache);
-}
-
-/**
-A helper method that yields the "next" cursor to process from both a primary sequence and a list of deferred items.
-*/
-fn next_from(
-    dc: &mut ::std::vec::IntoIter<Cursor>,
-    d: &mut Vec<Cursor>,
-    di: &mut Option<::std::vec::IntoIter<Cursor>>
-) -> Option<Cursor> {
-    use std::mem::replace;
-
-    if let Some(cur) = di.as_mut().and_then(|di| di.next()) {
-        return Some(cur)
-    }
-
-    *di = None;
-
-    if d.len() > 0 {
-        *di = Some(replace(d, vec![]).into_iter());
-        return next_from(dc, d, di);
-    }
 
-    dc.next()
+    features::report_fallbacks(&cache);
+    Ok(())
 }
 
 fn escape_ident(ident: String) -> String {
@@ -251,10 +329,9 @@ fn name_for_maybe_anon(decl_cur: &Cursor, renames: &Renames) -> Result<(String,
         Err(cur) => cur
     };
 
-    // TODO: Use clang_Cursor_isAnonymous once its released.
     let name = cur.spelling();
 
-    if name == "" {
+    if cur.is_anonymous() {
         /*
         This is *probably* an anonymous type.  We need to give it a name that will be both reasonable *and* stable across invocations.
         */
@@ -262,21 +339,17 @@ fn name_for_maybe_anon(decl_cur: &Cursor, renames: &Renames) -> Result<(String,
         /*
         First, we will try to give this type a name derived from its parent, assuming it is, in fact, defined *within* something else.
 
-        The name will be of the form `{parent}_Child_{i}`, where `{parent}` is the name of the parent type, and `{i}` is the number of steps it takes to arrive back at this declaration's cursor.
+        The name will be of the form `{parent}_Child_{i}`, where `{parent}` is the name of the parent type, and `{i}` is the number of steps it takes to arrive back at this declaration's cursor.  The index was recorded when scanning for renames.
         */
-        if let Some(par_cur) = cur.lexical_parent() {
+        if let (Some(par_cur), Some(i)) = (cur.lexical_parent(), renames.anon_index(cur)) {
             use clang::CursorKind as CK;
             if match par_cur.kind() {
                 CK::StructDecl | CK::UnionDecl => true,
                 _ => false
             } {
                 let (par_name, _) = try!(name_for_maybe_anon(&par_cur, renames));
-                for (i, child_cur) in par_cur.children().into_iter().enumerate() {
-                    if child_cur == *cur {
-                        let name = format!("{}_Child_{}", par_name, i);
-                        return Ok((name, file_stem(&cur)));
-                    }
-                }
+                let name = format!("{}_Child_{}", par_name, i);
+                return Ok((name, file_stem(&cur)));
             }
         }
 
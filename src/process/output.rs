@@ -1,9 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path;
 use itertools::Itertools;
 use OutConfig;
-use features::Features;
+use features::{Features, Partitions, Universe, WinVersions, version_name};
 
 /**
 An "abstract" calling convention.
@@ -50,6 +50,9 @@ pub struct OutputItems {
 
     /// `[name => [(feat, decl, annot)]]`
     pub var_items: HashMap<String, Vec<(u64, Features, String, String)>>,
+
+    /// Names of variables which are imported from a DLL, rather than linked statically.
+    pub dllimport_vars: HashSet<String>,
 }
 
 impl OutputItems {
@@ -60,6 +63,7 @@ impl OutputItems {
             fn_items: HashMap::new(),
             header_items: HashMap::new(),
             var_items: HashMap::new(),
+            dllimport_vars: HashSet::new(),
         }
     }
 
@@ -159,6 +163,14 @@ impl OutputItems {
         self.next_seq_id += 1;
     }
 
+    /**
+    Marks a variable as being imported from a DLL.
+    */
+    pub fn add_dllimport_var(&mut self, name: String) {
+        debug!("add_dllimport_var({:?})", name);
+        self.dllimport_vars.insert(name);
+    }
+
     /**
     Adds a variable declaration.
 
@@ -190,6 +202,143 @@ impl OutputItems {
         decls.push((self.next_seq_id, feat, decl, annot));
         self.next_seq_id += 1;
     }
+
+    /**
+    Prefixes every item with a `#[doc]` attribute describing where it's available, and optionally a `#[deprecated]` attribute if it was removed in some later version.
+
+    This has to happen *before* feature sets are simplified, since simplification can drop the very bounds we want to describe.
+    */
+    pub fn add_availability_docs(&mut self, deprecate_removed: bool) {
+        fn annotate(feat: &Features, decl: &mut String, deprecate_removed: bool) {
+            // Commented-out declarations don't get anything.
+            if decl.starts_with("//") { return; }
+
+            // Associated constants come wrapped in an `impl` block, where the attributes would do nothing; they belong on the constant itself.
+            let at = if decl.starts_with("impl ") {
+                decl.find("{ ").map(|i| i + 2).unwrap_or(0)
+            } else {
+                0
+            };
+
+            let mut attrs = String::new();
+            if let Some(desc) = feat.describe() {
+                attrs.push_str(&format!("#[doc={:?}] ", format!("Windows API: {}.\n", desc)));
+            }
+            // The headers may already have deprecated this, and Rust won't accept two.
+            if deprecate_removed && !item_is_deprecated(&decl[at..]) {
+                if let Some(ver) = feat.winver.as_ref().and_then(WinVersions::removed) {
+                    attrs.push_str(&format!("#[deprecated(note={:?})] ",
+                        format!("removed in {}", version_name(ver))));
+                }
+            }
+            let annotated = format!("{}{}{}", &decl[..at], attrs, &decl[at..]);
+            *decl = annotated;
+        }
+
+        for (_, decls) in self.fn_aliases.iter_mut() {
+            for &mut (_, _, ref feat, ref mut decl, _) in decls.iter_mut() {
+                annotate(feat, decl, deprecate_removed);
+            }
+        }
+        for (_, decls) in self.fn_items.iter_mut() {
+            for &mut (_, ref feat, _, ref mut decl, _) in decls.iter_mut() {
+                annotate(feat, decl, deprecate_removed);
+            }
+        }
+        for (_, decls) in self.header_items.iter_mut() {
+            for &mut (_, _, ref feat, ref mut decl, _) in decls.iter_mut() {
+                annotate(feat, decl, deprecate_removed);
+            }
+        }
+        for (_, decls) in self.var_items.iter_mut() {
+            for &mut (_, ref feat, ref mut decl, _) in decls.iter_mut() {
+                annotate(feat, decl, deprecate_removed);
+            }
+        }
+    }
+
+    /**
+    Simplifies the feature set of every item with respect to the configurations the headers were expanded for.
+    */
+    pub fn simplify_features(&mut self, universe: &Universe) {
+        use std::mem::replace;
+        debug!("simplify_features({:?})", universe);
+
+        fn simplify(feat: &mut Features, universe: &Universe) {
+            let old = replace(feat, Features::default());
+            *feat = old.simplify(universe);
+        }
+
+        for (_, decls) in self.fn_aliases.iter_mut() {
+            for &mut (_, _, ref mut feat, _, _) in decls.iter_mut() {
+                simplify(feat, universe);
+            }
+        }
+        for (_, decls) in self.fn_items.iter_mut() {
+            for &mut (_, ref mut feat, _, _, _) in decls.iter_mut() {
+                simplify(feat, universe);
+            }
+        }
+        for (_, decls) in self.header_items.iter_mut() {
+            for &mut (_, _, ref mut feat, _, _) in decls.iter_mut() {
+                simplify(feat, universe);
+            }
+        }
+        for (_, decls) in self.var_items.iter_mut() {
+            for &mut (_, ref mut feat, _, _) in decls.iter_mut() {
+                simplify(feat, universe);
+            }
+        }
+    }
+
+    /**
+    Drops every item not available in any of the given partitions.
+
+    Surviving items have their partitions narrowed to `parts`; if that covers *all* of `parts`, the partition component is removed entirely, since it's then implied by the target.
+    */
+    pub fn restrict_partitions(&mut self, parts: Partitions) {
+        use std::mem::replace;
+        debug!("restrict_partitions({:?})", parts);
+
+        fn restrict(feat: &mut Features, parts: Partitions) -> bool {
+            let narrowed = match feat.parts {
+                None => return true,
+                Some(fp) => fp & parts
+            };
+            if narrowed.is_empty() { return false; }
+            feat.parts = if narrowed == parts { None } else { Some(narrowed) };
+            true
+        }
+
+        for (_, decls) in self.fn_aliases.iter_mut() {
+            let old = replace(decls, vec![]);
+            *decls = old.into_iter()
+                .filter_map(|(id, alias, mut feat, decl, annot)|
+                    if restrict(&mut feat, parts) { Some((id, alias, feat, decl, annot)) } else { None })
+                .collect();
+        }
+        for (_, decls) in self.fn_items.iter_mut() {
+            let old = replace(decls, vec![]);
+            *decls = old.into_iter()
+                .filter_map(|(id, mut feat, cconv, decl, annot)|
+                    if restrict(&mut feat, parts) { Some((id, feat, cconv, decl, annot)) } else { None })
+                .collect();
+        }
+        for (_, decls) in self.header_items.iter_mut() {
+            let old = replace(decls, vec![]);
+            *decls = old.into_iter()
+                .filter_map(|(id, header, mut feat, decl, annot)|
+                    if restrict(&mut feat, parts) { Some((id, header, feat, decl, annot)) } else { None })
+                .collect();
+        }
+        for (_, decls) in self.var_items.iter_mut() {
+            let old = replace(decls, vec![]);
+            *decls = old.into_iter()
+                .filter_map(|(id, mut feat, decl, annot)|
+                    if restrict(&mut feat, parts) { Some((id, feat, decl, annot)) } else { None })
+                .collect();
+        }
+    }
 }
 
 /**
@@ -197,7 +346,7 @@ This cache owns the output files and saves us from constantly opening and closin
 */
 pub struct OutputFiles<'a> {
     out_config: &'a OutConfig,
-    files: HashMap<path::PathBuf, (fs::File, Option<(Features, AbsCallConv)>)>,
+    files: HashMap<path::PathBuf, (fs::File, Option<(Features, AbsCallConv, bool)>)>,
 }
 
 impl<'a> OutputFiles<'a> {
@@ -219,19 +368,26 @@ impl<'a> OutputFiles<'a> {
         }
     }
 
-    pub fn emit_to_library(&mut self, name: &str, feat: &Features, cconv: Option<AbsCallConv>, decl: &str, annot: &str) {
+    /**
+    Emits a declaration to a library file.
+
+    Declarations with a calling convention go in an `extern` block.  If `link` is set, that block is also linked to the library, which Rust needs in order to import data from a DLL.
+    */
+    pub fn emit_to_library(&mut self, name: &str, feat: &Features, cconv: Option<AbsCallConv>, link: bool, decl: &str, annot: &str) {
         use std::io::prelude::*;
         let (file, group) = self.get_file(name, &self.out_config.library_path);
 
         if let Some(cconv) = cconv {
+            let link_attr = if link { format!("#[link(name={:?}, kind=\"dylib\")]\n", name) } else { String::new() };
+
             // Change grouping if necessary.
             match *group {
-                Some((ref gf, ref gcc)) if gf == feat && *gcc == cconv => (),
+                Some((ref gf, ref gcc, gl)) if gf == feat && *gcc == cconv && gl == link => (),
                 Some(_) => {
-                    writeln!(file, "}}\n{}\nextern {:?} {{", feat, cconv.as_str()).unwrap();
+                    writeln!(file, "}}\n{}\n{}extern {:?} {{", feat, link_attr, cconv.as_str()).unwrap();
                 },
                 None => {
-                    writeln!(file, "{}\nextern {:?} {{", feat, cconv.as_str()).unwrap();
+                    writeln!(file, "{}\n{}extern {:?} {{", feat, link_attr, cconv.as_str()).unwrap();
                 }
             }
 
@@ -239,7 +395,7 @@ impl<'a> OutputFiles<'a> {
             writeln!(file, "    {} /* {} */", decl, annot).unwrap();
 
             // Update the "last" group.
-            *group = Some((feat.clone(), cconv));
+            *group = Some((feat.clone(), cconv, link));
         } else {
             if group.is_some() {
                 writeln!(file, "}}").unwrap();
@@ -270,7 +426,7 @@ impl<'a> OutputFiles<'a> {
         &'b mut self,
         name: &str,
         pattern: &str
-    ) -> (&'b mut fs::File, &'b mut Option<(Features, AbsCallConv)>) {
+    ) -> (&'b mut fs::File, &'b mut Option<(Features, AbsCallConv, bool)>) {
         use std::path::PathBuf;
         let mut path = PathBuf::from(&self.out_config.output_dir);
         path.push(pattern.replace("{}", name));
@@ -280,6 +436,57 @@ impl<'a> OutputFiles<'a> {
     }
 }
 
+/**
+Returns `true` if the item itself (rather than, say, one of its fields) has a `#[deprecated]` attribute.
+
+Only the attributes in front of the item are looked at.  They're skipped one at a time, minding brackets and string literals.
+*/
+fn item_is_deprecated(decl: &str) -> bool {
+    let mut rest = decl.trim_left();
+    while rest.starts_with("#[") {
+        if rest.starts_with("#[deprecated") {
+            return true;
+        }
+        rest = match attr_len(rest) {
+            Some(len) => rest[len..].trim_left(),
+            None => return false
+        };
+    }
+    false
+}
+
+/**
+Returns the length of the attribute at the start of `s`, up to and including its closing `]`.
+*/
+fn attr_len(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if in_str {
+            match (escaped, c) {
+                (true, _) => escaped = false,
+                (false, '\\') => escaped = true,
+                (false, '"') => in_str = false,
+                _ => ()
+            }
+            continue;
+        }
+        match c {
+            '"' => in_str = true,
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            },
+            _ => ()
+        }
+    }
+    None
+}
+
 pub fn output_header_items(items: &OutputItems, output: &mut OutputFiles) {
     let mut lines = vec![];
     for (_, decls) in &items.header_items {
@@ -304,32 +511,35 @@ pub fn output_func_items(items: &OutputItems, output: &mut OutputFiles, out_conf
     for (name, decls) in &items.fn_items {
         for &(_, ref feat, ref cconv, ref decl, ref annot) in decls {
             for &ref lib in out_config.get_fn_libs(name) {
-                lines.push((lib, feat, name, Some(*cconv), decl, annot));
+                lines.push((lib, feat, name, Some(*cconv), false, decl, annot));
             }
         }
     }
     for (name, decls) in &items.fn_aliases {
         for &(_, ref alias, ref feat, ref decl, ref annot) in decls {
             for &ref lib in out_config.get_fn_libs(alias) {
-                lines.push((lib, feat, name, None, decl, annot));
+                lines.push((lib, feat, name, None, false, decl, annot));
             }
         }
     }
     for (name, decls) in &items.var_items {
+        // Only link libraries we actually know the variable is in; the fallbacks are guesses.
+        let link = items.dllimport_vars.contains(name)
+            && out_config.function_library_map.contains_key(name);
         for &(_, ref feat, ref decl, ref annot) in decls {
             for &ref lib in out_config.get_fn_libs(name) {
-                lines.push((lib, feat, name, Some(AbsCallConv::System), decl, annot));
+                lines.push((lib, feat, name, Some(AbsCallConv::System), link, decl, annot));
             }
         }
     }
     lines.sort();
 
     let lines = lines.into_iter()
-        .group_by_lazy(|&(lib, feat, _, cconv, _, _)| (lib, feat, cconv));
+        .group_by_lazy(|&(lib, feat, _, cconv, link, _, _)| (lib, feat, cconv, link));
 
-    for ((lib, feat, cconv), group) in &lines {
-        for (_, _, _, _, decl, annot) in group {
-            output.emit_to_library(lib, feat, cconv, decl, annot);
+    for ((lib, feat, cconv, link), group) in &lines {
+        for (_, _, _, _, _, decl, annot) in group {
+            output.emit_to_library(lib, feat, cconv, link, decl, annot);
         }
     }
 }
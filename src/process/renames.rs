@@ -5,16 +5,29 @@ use clang::{
     self,
     TranslationUnit,
     Cursor,
+    Position,
+    Visitor,
+    Walk,
 };
 
-use super::next_from;
-
 pub struct Renames {
     renames: HashMap<Cursor, Cursor>,
     invalidations: HashSet<Cursor>,
+
+    /// Where each anonymous struct, union or enum nested in a struct or union sits amongst its parent's children.
+    anon_indices: HashMap<Cursor, usize>,
 }
 
 impl Renames {
+    /**
+    Returns which child of its (struct or union) parent an anonymous declaration is.
+
+    This is what anonymous declarations are named after.
+    */
+    pub fn anon_index(&self, decl_cur: &Cursor) -> Option<usize> {
+        self.anon_indices.get(decl_cur).cloned()
+    }
+
     pub fn add_rename(&mut self, from: Cursor, to: Cursor) {
         assert!(!self.is_renamed(&from), "definition is already renamed");
         assert!(!self.invalidations.contains(&to), "already have invalidation");
@@ -63,6 +76,7 @@ impl Default for Renames {
         Renames {
             renames: HashMap::new(),
             invalidations: HashSet::new(),
+            anon_indices: HashMap::new(),
         }
     }
 }
@@ -75,32 +89,57 @@ pub fn scan_for_renames(tu: Rc<TranslationUnit>, gen_config: &GenConfig) -> Rena
     2. Types which *have* a name in tag space, but the *canonical* name is given via typedef.
 
     We handle both of these by scanning through all the typedefs.  If we find one whose subject is one of the above types, we record the subject's cursor and the *new* name, as well as an "invalidation" of the typedef's cursor.
+
+    Typedefs can only appear at file scope, so the only reason to descend into anything is to record where anonymous types nested inside structs and unions are.
     */
     info!("scanning for renames...");
-    let mut renames = Renames::default();
-    let mut decl_curs = tu.cursor().children().into_iter();
-    let mut deferred: Vec<Cursor> = vec![];
-    let mut deferred_iter = None;
-
-    while let Some(decl_cur) = next_from(&mut decl_curs, &mut deferred, &mut deferred_iter) {
-        if !gen_config.should_ignore_from_file(&decl_cur) {
-            let rename = scan_decl_for_rename(decl_cur, gen_config, &renames, &mut |cur| deferred.push(cur));
-            if let Some((from, to)) = rename {
-                renames.add_rename(from, to);
+    let mut scanner = RenameScanner {
+        gen_config: gen_config,
+        renames: Renames::default(),
+    };
+    clang::visit::walk(&tu.cursor(), &mut scanner);
+    scanner.renames
+}
+
+struct RenameScanner<'a> {
+    gen_config: &'a GenConfig,
+    renames: Renames,
+}
+
+impl<'a> Visitor for RenameScanner<'a> {
+    fn enter(&mut self, cur: &Cursor, pos: &Position) -> Walk {
+        use clang::CursorKind as CK;
+
+        let is_record = match cur.kind() {
+            CK::StructDecl | CK::UnionDecl => true,
+            _ => false
+        };
+
+        if pos.depth() == 0 {
+            if !self.gen_config.should_ignore_from_file(cur) {
+                let rename = scan_decl_for_rename(cur.clone(), self.gen_config, &self.renames);
+                if let Some((from, to)) = rename {
+                    self.renames.add_rename(from, to);
+                }
+            }
+        } else if cur.is_anonymous() {
+            match cur.kind() {
+                CK::StructDecl | CK::UnionDecl | CK::EnumDecl => {
+                    self.renames.anon_indices.insert(cur.clone(), pos.index);
+                },
+                _ => ()
             }
         }
-    }
 
-    renames
+        if is_record { Walk::Recurse } else { Walk::Prune }
+    }
 }
 
-fn scan_decl_for_rename<Defer>(
+fn scan_decl_for_rename(
     decl_cur: Cursor,
     gen_config: &GenConfig,
     renames: &Renames,
-    mut defer: Defer,
-) -> Option<(Cursor, Cursor)>
-where Defer: FnMut(Cursor) {
+) -> Option<(Cursor, Cursor)> {
     use clang::CursorKind as CK;
     use clang::TypeKind as TK;
 
@@ -113,9 +152,10 @@ where Defer: FnMut(Cursor) {
 
     let ty = decl_cur.typedef_decl_underlying_type();
 
-    // Resolve unexposed types if possible.
+    // Resolve unexposed and elaborated types if possible.
     let ty = match ty.kind() {
         TK::Unexposed => ty.canonical(),
+        TK::Elaborated => ty.named(),
         _ => ty
     };
 
@@ -147,12 +187,9 @@ where Defer: FnMut(Cursor) {
         }
     };
 
-    // Defer all the child cursors.
-    for child in ty_defn.children() { defer(child); }
-
     // Check if it's already canonical.
     let ty_name = ty_defn.spelling();
-    if !gen_config.is_tag_name_non_canonical(&ty_name) {
+    if !ty_defn.is_anonymous() && !gen_config.is_tag_name_non_canonical(&ty_name) {
         debug!(".. ignoring canonical name {:?}", ty_name);
         return None;
     }
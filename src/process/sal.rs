@@ -0,0 +1,207 @@
+/*!
+Recovers SAL (Microsoft's "Source Annotation Language") annotations on function parameters.
+
+As far as Clang is concerned, SAL annotations (`_In_`, `_Out_writes_(n)`, *etc.*) are macros which expand to nothing, so they have to be dug out of the raw tokens of the declaration.
+*/
+use std::fmt;
+use clang::Cursor;
+
+/**
+Which way data flows through a parameter.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Direction {
+    In,
+    Out,
+    InOut,
+}
+
+/**
+A single annotation, along with its arguments (if any).
+*/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SalAnnotation {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl SalAnnotation {
+    /// The direction this annotation specifies, if it specifies one at all.
+    pub fn direction(&self) -> Option<Direction> {
+        let name = self.name.trim_matches('_').to_lowercase();
+        let first = name.split('_').next().unwrap_or("");
+        match first {
+            "in" => Some(Direction::In),
+            "out" | "outptr" | "outref" | "deref" => Some(Direction::Out),
+            "inout" | "inoutref" => Some(Direction::InOut),
+            _ => None
+        }
+    }
+
+    /// Returns `true` if this annotation allows the parameter to be null.
+    pub fn is_optional(&self) -> bool {
+        self.name == "OPTIONAL"
+            || self.name.contains("_opt")
+            || self.name.contains("maybenull")
+            || self.name.starts_with("_Reserved_")
+            || self.name.starts_with("__reserved")
+    }
+}
+
+impl fmt::Display for SalAnnotation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        try!(write!(fmt, "{}", self.name));
+        if self.args.len() > 0 {
+            try!(write!(fmt, "({})", self.args.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+/**
+All of the annotations on a single parameter.
+*/
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ParamSal(pub Vec<SalAnnotation>);
+
+impl ParamSal {
+    /// The direction of the parameter, as given by the first annotation that says.
+    pub fn direction(&self) -> Option<Direction> {
+        self.0.iter().filter_map(SalAnnotation::direction).next()
+    }
+
+    /// Returns `true` if the parameter may be null.
+    pub fn is_optional(&self) -> bool {
+        self.0.iter().any(SalAnnotation::is_optional)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+}
+
+impl fmt::Display for ParamSal {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (i, annot) in self.0.iter().enumerate() {
+            try!(write!(fmt, "{}{}", if i == 0 { "" } else { " " }, annot));
+        }
+        Ok(())
+    }
+}
+
+/**
+Determines whether the given token is a SAL annotation.
+
+This covers SAL 2 (`_In_opt_`), SAL 1 (`__in_ecount(n)`), and the old `IN`/`OUT`/`OPTIONAL` markers.
+*/
+pub fn is_sal_annotation(tok: &str) -> bool {
+    match tok {
+        "IN" | "OUT" | "OPTIONAL" => return true,
+        _ => ()
+    }
+
+    let sal1_prefixes = ["__in", "__out", "__inout", "__deref", "__reserved"];
+    if sal1_prefixes.iter().any(|p| tok == *p || tok.starts_with(&format!("{}_", p))) {
+        return true;
+    }
+
+    tok.len() > 2
+        && tok.starts_with("_")
+        && tok.ends_with("_")
+        && tok[1..2].chars().all(|c| c.is_uppercase())
+}
+
+/**
+Works out the annotations for every parameter of a function declaration.
+
+Annotations are taken from the tokens between the parameter and the preceding `(` or `,`, as well as from the parameter's own tokens.
+*/
+pub fn function_param_sal(func: &Cursor, params: &[Cursor]) -> Vec<ParamSal> {
+    let toks: Vec<(u32, String)> = func.tokenize().into_iter()
+        .map(|tok| (tok.location().file_location().3, tok.spelling()))
+        .collect();
+
+    params.iter()
+        .map(|param| {
+            let start = match param.extent() {
+                Some(extent) => extent.start().file_location().3,
+                None => return ParamSal::default()
+            };
+            let end = param.extent().map(|e| e.end().file_location().3).unwrap_or(start);
+
+            // Walk backwards from the start of the parameter to the delimiter before it.
+            let first = toks.iter().position(|&(off, _)| off >= start).unwrap_or(toks.len());
+            let mut prefix = vec![];
+            let mut depth = 0;
+            for &(_, ref tok) in toks[..first].iter().rev() {
+                match &**tok {
+                    ")" => depth += 1,
+                    "(" if depth == 0 => break,
+                    "(" => depth -= 1,
+                    "," if depth == 0 => break,
+                    _ => ()
+                }
+                prefix.push(tok.clone());
+            }
+            prefix.reverse();
+
+            let own = toks[first..].iter()
+                .take_while(|&&(off, _)| off < end)
+                .map(|&(_, ref tok)| tok.clone());
+
+            let all: Vec<String> = prefix.into_iter().chain(own).collect();
+            ParamSal(parse_annotations(&all))
+        })
+        .collect()
+}
+
+/**
+Extracts the SAL annotations from a sequence of tokens, ignoring everything else.
+*/
+pub fn parse_annotations(toks: &[String]) -> Vec<SalAnnotation> {
+    let mut annots = vec![];
+    let mut i = 0;
+    while i < toks.len() {
+        if !is_sal_annotation(&toks[i]) {
+            i += 1;
+            continue;
+        }
+
+        let name = toks[i].clone();
+        let mut args = vec![];
+        i += 1;
+
+        if i < toks.len() && toks[i] == "(" {
+            let mut depth = 0;
+            let mut arg: Vec<&str> = vec![];
+            i += 1;
+            while i < toks.len() {
+                match &*toks[i] {
+                    ")" if depth == 0 => break,
+                    "," if depth == 0 => {
+                        args.push(arg.join(" "));
+                        arg = vec![];
+                        i += 1;
+                        continue;
+                    },
+                    "(" => depth += 1,
+                    ")" => depth -= 1,
+                    _ => ()
+                }
+                arg.push(&toks[i]);
+                i += 1;
+            }
+            if arg.len() > 0 {
+                args.push(arg.join(" "));
+            }
+            // Skip the closing paren.
+            i += 1;
+        }
+
+        annots.push(SalAnnotation {
+            name: name,
+            args: args,
+        });
+    }
+    annots
+}
@@ -8,19 +8,19 @@ pub fn sanity_check_features(cache: &mut Cache) {
     let mut weird_vers = BTreeSet::new();
 
     cache.iter_features(|path, line, &ref feat| {
-        use features::Partitions;
+        use features::Family;
 
         /*
         What we're looking for are any features that might mess up the expansion.  This currently means:
 
         - Features with upper limits on versions.
-        - Features that *do not* target the desktop.
+        - Features that *do not* target anything the desktop family can see.
         */
 
         let mut suspect = vec![];
 
         if let Some(ref parts) = feat.parts {
-            if (parts.clone() & Partitions::DesktopApp).is_empty() {
+            if !parts.intersects(Family::DesktopApp.partitions()) {
                 suspect.push("non-desktop-app");
             }
         }
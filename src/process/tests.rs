@@ -0,0 +1,85 @@
+/*!
+Tests which parse small headers supplied as overlays, so they don't depend on the Windows SDK being present.
+*/
+use std::collections::HashSet;
+use std::rc::Rc;
+use {CharSet, DiagnosticSeverity, ExpConfig, GenConfig};
+use clang::{self, Cursor, CursorKind, Index, TranslationUnit};
+use super::TuCache;
+
+const HEADER: &'static str = "overlay_test.h";
+
+fn gen_config(header: &str, prelude: Option<&str>) -> GenConfig {
+    GenConfig {
+        exp_configs: vec![],
+        switches: vec!["-fms-extensions".into()],
+        dont_ignore_decl_spelling: vec![],
+        ignore_decls: HashSet::new(),
+        ignore_decl_spellings: vec![],
+        ignore_file_paths: vec![],
+        non_canonical_tag_names: vec![],
+        char_set: CharSet::Unicode,
+        int_macro_types: vec![],
+        const_groups: vec![],
+        target_partitions: None,
+        snap_versions: false,
+        deprecate_removed: false,
+        tracked_defines: vec![],
+        feature_error_policies: vec![],
+        diagnostic_threshold: DiagnosticSeverity::Error,
+        parse_all_comments: false,
+        sal_const_inputs: false,
+        sal_const_input_exceptions: vec![],
+        overlays: vec![(HEADER.into(), header.into())],
+        prelude: prelude.map(Into::into),
+    }
+}
+
+fn parse(gen_config: &GenConfig) -> Rc<TranslationUnit> {
+    clang::load().unwrap();
+    let mut cache = TuCache::new(Index::create(false, false), gen_config);
+    cache.parse_translation_unit(HEADER, &ExpConfig::DUMMY_CFG).unwrap()
+}
+
+fn find(tu: &TranslationUnit, kind: CursorKind, name: &str) -> Cursor {
+    tu.cursor().children().into_iter()
+        .find(|cur| cur.kind() == kind && cur.spelling() == name)
+        .unwrap_or_else(|| panic!("no {:?} named {:?}", kind, name))
+}
+
+#[test]
+fn test_var_decl_initializer() {
+    let gen_config = gen_config("
+        extern int tbl[256];
+        __declspec(dllimport) extern const unsigned char T[16];
+        static const int k = 5;
+        static const int arr[2] = { 1, 2 };
+    ", None);
+    let tu = parse(&gen_config);
+
+    // The array sizes are expressions, but they aren't initialisers.
+    assert!(find(&tu, CursorKind::VarDecl, "tbl").var_decl_initializer().is_none());
+    assert!(find(&tu, CursorKind::VarDecl, "T").var_decl_initializer().is_none());
+
+    let init = find(&tu, CursorKind::VarDecl, "k").var_decl_initializer().expect("initialiser for k");
+    assert_eq!(init.kind(), CursorKind::IntegerLiteral);
+    let init = find(&tu, CursorKind::VarDecl, "arr").var_decl_initializer().expect("initialiser for arr");
+    assert_eq!(init.kind(), CursorKind::InitListExpr);
+}
+
+#[test]
+fn test_overlay_and_prelude() {
+    let gen_config = gen_config("
+        #ifdef PRELUDE_VALUE
+        int overlaid_fn(void);
+        extern int overlaid_tbl[PRELUDE_VALUE];
+        #endif
+    ", Some("#define PRELUDE_VALUE 42\n"));
+    let tu = parse(&gen_config);
+
+    // The macro comes from the prelude, and the declarations it guards from the overlaid header.
+    find(&tu, CursorKind::MacroDefinition, "PRELUDE_VALUE");
+    find(&tu, CursorKind::FunctionDecl, "overlaid_fn");
+    let tbl = find(&tu, CursorKind::VarDecl, "overlaid_tbl");
+    assert_eq!(tbl.type_().array_size(), 42);
+}
@@ -5,13 +5,19 @@ use clang::{
     self,
     TranslationUnit,
     Cursor, CursorKind,
+    Position, Visitor, Walk,
 };
 use features::Features;
 
-use super::{EMIT_STUBS, Cache, NameMap, add_to_name_map_checked, escape_ident, file_stem, mod_qual, name_for_maybe_anon, next_from};
+use super::{EMIT_STUBS, Cache, NameMap, add_to_name_map_checked, escape_ident, file_stem, mod_qual, name_for_maybe_anon};
+use super::comments::{doc_attr, doc_attr_with_params};
 use super::features::get_features_at;
 use super::output::{AbsCallConv, OutputItems};
+use super::const_groups::GroupMap;
+use super::deprecation::deprecated_attr;
 use super::renames::Renames;
+use super::sal::{Direction, ParamSal, function_param_sal};
+use super::trans_macros::ConstMap;
 
 pub fn process_decls(
     tu: Rc<TranslationUnit>,
@@ -20,71 +26,126 @@ pub fn process_decls(
     output: &mut OutputItems,
     cache: &mut Cache,
     renames: &Renames,
-) {
-    let feat_mask = exp_config.arch.to_features();
+) -> Result<(), String> {
+    let (mut name_map, second_pass) = {
+        let mut visitor = DeclVisitor {
+            gen_config: gen_config,
+            feat_mask: exp_config.arch.to_features(),
+            native_cc: exp_config.native_cc,
+            output: output,
+            cache: cache,
+            renames: renames,
+            name_map: NameMap::new(),
+            second_pass: vec![],
+            error: None,
+        };
+        clang::visit::walk(&tu.cursor(), &mut visitor);
+        if let Some(err) = visitor.error {
+            return Err(err);
+        }
+        (visitor.name_map, visitor.second_pass)
+    };
+
+    let mut const_map = ConstMap::new();
+    let mut groups = GroupMap::new();
+    for (cur, feat) in second_pass {
+        let cur_copy = cur.clone();
+        let result = super::trans_macros::process_macro_defn(cur, output, feat, gen_config, exp_config, &mut name_map, &mut const_map, &mut groups);
+        if let Err(err) = result {
+            warn!("could-not-translate misc {}: {}", cur_copy, err);
+        }
+    }
+
+    Ok(())
+}
+
+/**
+Walks the top-level declarations of a translation unit, along with any structs and unions nested inside them, processing each one.
 
-    let mut name_map = NameMap::new();
-    let mut decl_curs = tu.cursor().children().into_iter();
-    let mut deferred: Vec<Cursor> = vec![];
-    let mut deferred_iter = None;
-    let mut second_pass = vec![];
+Macro definitions are collected for a second pass, since they can refer to declarations which come after them.
+*/
+struct DeclVisitor<'a> {
+    gen_config: &'a GenConfig,
+    feat_mask: Features,
+    native_cc: NativeCallConv,
+    output: &'a mut OutputItems,
+    cache: &'a mut Cache,
+    renames: &'a Renames,
+    name_map: NameMap,
+    second_pass: Vec<(Cursor, Features)>,
+    error: Option<String>,
+}
+
+impl<'a> Visitor for DeclVisitor<'a> {
+    fn enter(&mut self, decl_cur: &Cursor, pos: &Position) -> Walk {
+        use clang::CursorKind as CK;
+
+        let is_record = match decl_cur.kind() {
+            CK::StructDecl | CK::UnionDecl => true,
+            _ => false
+        };
+
+        // Inside a struct or union, only nested structs and unions are declarations in their own right.
+        if pos.depth() > 0 && !is_record {
+            return Walk::Prune;
+        }
 
-    while let Some(decl_cur) = next_from(&mut decl_curs, &mut deferred, &mut deferred_iter) {
         /*
         Something to be aware of: a symbol might match the ignore patterns, but have been renamed to something that *doesn't*.  We need to check for this.
         */
-        if gen_config.should_ignore(&decl_cur) && !renames.is_renamed(&decl_cur) {
+        if self.gen_config.should_ignore(decl_cur) && !self.renames.is_renamed(decl_cur) {
             debug!("ignoring: {}", decl_cur);
-        } else if renames.is_invalidated(&decl_cur) {
+            return Walk::Prune;
+        } else if self.renames.is_invalidated(decl_cur) {
             debug!("invalidated: {}", decl_cur);
-        } else {
-            process_decl(
-                decl_cur,
-                feat_mask.clone(),
-                exp_config.native_cc,
-                output,
-                cache,
-                renames,
-                &mut name_map,
-                &mut |cur| deferred.push(cur),
-                &mut |cur, feats| second_pass.push((cur, feats)),
-            );
+            return Walk::Prune;
         }
-    }
 
-    for (cur, feat) in second_pass {
-        let cur_copy = cur.clone();
-        let result = super::trans_macros::process_macro_defn(cur, output, feat, &mut name_map);
-        if let Err(err) = result {
-            warn!("could-not-translate misc {}: {}", cur_copy, err);
+        let result = process_decl(
+            decl_cur.clone(),
+            self.gen_config,
+            self.feat_mask.clone(),
+            self.native_cc,
+            &mut *self.output,
+            &mut *self.cache,
+            self.renames,
+            &mut self.name_map,
+            &mut self.second_pass,
+        );
+
+        match result {
+            Err(err) => {
+                self.error = Some(err);
+                Walk::Stop
+            },
+            Ok(()) if is_record && decl_cur.is_definition() => Walk::Recurse,
+            Ok(()) => Walk::Prune
         }
     }
 }
 
 /**
 Processes a single declaration.
+
+Failing to translate the declaration is *not* an error; only failing to work out its feature set is.
 */
-fn process_decl<Defer, Pass>(
+fn process_decl(
     decl_cur: Cursor,
+    gen_config: &GenConfig,
     feat_mask: Features,
     native_cc: NativeCallConv,
     output: &mut OutputItems,
     cache: &mut Cache,
     renames: &Renames,
     name_map: &mut NameMap,
-    defer: &mut Defer,
-    pass: &mut Pass,
-)
-where
-    Defer: FnMut(Cursor),
-    Pass: FnMut(Cursor, Features),
-{
+    second_pass: &mut Vec<(Cursor, Features)>,
+) -> Result<(), String> {
     use clang::CursorKind as CK;
 
     let decl_kind = match decl_cur.kind() {
         CK::InclusionDirective
         | CK::MacroInstantiation
-        => return,
+        => return Ok(()),
         kind => kind
     };
 
@@ -95,9 +156,10 @@ where
         decl_kind,
         decl_cur.spelling());
 
-    let feat = decl_loc.file()
-        .map(|file| get_features_at(file, decl_loc.line(), cache))
-        .unwrap_or_else(|| Features::default());
+    let feat = match decl_loc.file() {
+        Some(file) => try!(get_features_at(file, decl_loc.line(), cache)),
+        None => Features::default()
+    };
 
     debug!(".. process_decl feat: {:?}", feat);
 
@@ -123,13 +185,13 @@ where
         | CK::MacroInstantiation
         => unreachable!(),
 
-        CK::StructDecl => process_struct_decl(decl_cur, output, feat, renames, name_map, native_cc, defer),
-        CK::UnionDecl => process_union_decl(decl_cur, output, feat, renames, name_map, native_cc, defer),
-        CK::EnumDecl => process_enum_decl(decl_cur, output, feat, renames, name_map, native_cc, defer),
-        CK::FunctionDecl => process_function_decl(decl_cur, output, feat, renames, name_map, native_cc),
+        CK::StructDecl => process_struct_decl(decl_cur, output, feat, renames, name_map, native_cc),
+        CK::UnionDecl => process_union_decl(decl_cur, output, feat, renames, name_map, native_cc),
+        CK::EnumDecl => process_enum_decl(decl_cur, output, feat, renames, name_map, native_cc),
+        CK::FunctionDecl => process_function_decl(decl_cur, gen_config, output, feat, renames, name_map, native_cc),
         CK::VarDecl => process_var_decl(decl_cur, output, feat, renames, name_map, native_cc),
         CK::TypedefDecl => process_typedef_decl(decl_cur, output, feat, renames, name_map, native_cc),
-        CK::MacroDefinition => { pass(decl_cur, feat); Ok(()) },
+        CK::MacroDefinition => { second_pass.push((decl_cur, feat)); Ok(()) },
 
         kind => {
             warn!("could-not-translate unsupported {:?} {} at {}",
@@ -141,22 +203,21 @@ where
     if let Err(err) = result {
         warn!("could-not-translate misc {}: {}", decl_cur_copy, err);
     }
+
+    Ok(())
 }
 
 /**
 Process a single structure declaration.
 */
-fn process_struct_decl<Defer>(
+fn process_struct_decl(
     decl_cur: Cursor,
     output: &mut OutputItems,
     feat: Features,
     renames: &Renames,
     name_map: &mut NameMap,
     native_cc: NativeCallConv,
-    defer: &mut Defer,
-) -> Result<(), String>
-where Defer: FnMut(Cursor)
-{
+) -> Result<(), String> {
     use clang::CursorKind as CK;
 
     debug!("process_struct_decl({}, ..)", decl_cur);
@@ -172,7 +233,7 @@ where Defer: FnMut(Cursor)
         (false, true) => {
             // There *is no* definition!
             debug!(".. no definition found");
-            let decl = format!("#[repr(C)] pub struct {};", escape_ident(name.clone()));
+            let decl = format!("{}#[repr(C)] pub struct {};", item_attrs(&decl_cur), escape_ident(name.clone()));
             try!(add_to_name_map_checked(name_map, name.clone(), decl_cur.clone()));
             output.add_header_item(name, header, feat, decl, annot);
             return Ok(())
@@ -187,8 +248,7 @@ where Defer: FnMut(Cursor)
             CK::StructDecl
             | CK::UnionDecl
             => {
-                // Defer.
-                defer(child_cur);
+                // Nested declarations are processed separately, by `DeclVisitor`.
             },
 
             CK::FieldDecl => {
@@ -211,7 +271,7 @@ where Defer: FnMut(Cursor)
                         return Err(err);
                     }
                 };
-                fields.push(format!("{}: {}", escape_ident(field_name), ty));
+                fields.push(format!("{}{}: {}", item_attrs(&child_cur), escape_ident(field_name), ty));
             },
 
             CK::UnexposedAttr => {
@@ -225,11 +285,13 @@ where Defer: FnMut(Cursor)
     let decl = match fields.len() {
         // Why did this have to be special-cased? :(
         0 => format!(
-            "#[repr(C)] pub struct {name};",
+            "{doc}#[repr(C)] pub struct {name};",
+            doc = item_attrs(&decl_cur),
             name = escape_ident(name.clone()),
         ),
         _ => format!(
-            "#[repr(C)] pub struct {name} {{ {fields} }}",
+            "{doc}#[repr(C)] pub struct {name} {{ {fields} }}",
+            doc = item_attrs(&decl_cur),
             name = escape_ident(name.clone()),
             fields = fields.join(", "),
         )
@@ -243,17 +305,14 @@ where Defer: FnMut(Cursor)
 /**
 Process a single union declaration.
 */
-fn process_union_decl<Defer>(
+fn process_union_decl(
     decl_cur: Cursor,
     output: &mut OutputItems,
     feat: Features,
     renames: &Renames,
     name_map: &mut NameMap,
     native_cc: NativeCallConv,
-    defer: &mut Defer,
-) -> Result<(), String>
-where Defer: FnMut(Cursor)
-{
+) -> Result<(), String> {
     use std::cmp::max;
     use clang::CursorKind as CK;
 
@@ -287,7 +346,7 @@ where Defer: FnMut(Cursor)
         (false, true) => {
             // There *is no* definition!
             debug!(".. no definition found");
-            let decl = format!("#[repr(C)] pub /*union*/ struct {};", escape_ident(name.clone()));
+            let decl = format!("{}#[repr(C)] pub /*union*/ struct {};", item_attrs(&decl_cur), escape_ident(name.clone()));
             try!(add_to_name_map_checked(name_map, name.clone(), decl_cur.clone()));
             output.add_header_item(name, header, feat, decl, annot);
             return Ok(())
@@ -306,8 +365,7 @@ where Defer: FnMut(Cursor)
             CK::StructDecl
             | CK::UnionDecl
             => {
-                // Defer.
-                defer(child_cur);
+                // Nested declarations are processed separately, by `DeclVisitor`.
             },
 
             CK::FieldDecl => {
@@ -386,8 +444,9 @@ where Defer: FnMut(Cursor)
     }
 
     let decl = format!(
-        "#[repr(C)] pub /*union*/ struct {name} {{ {payloads} }} \
+        "{doc}#[repr(C)] pub /*union*/ struct {name} {{ {payloads} }} \
             {fields}",
+        doc = item_attrs(&decl_cur),
         name = escape_ident(name.clone()),
         payloads = payload_fields.into_iter().enumerate()
             .map(|(i, s)| format!("_payload{}: {}", i, s))
@@ -412,17 +471,14 @@ where Defer: FnMut(Cursor)
 /**
 Process a single enum declaration.
 */
-fn process_enum_decl<Defer>(
+fn process_enum_decl(
     decl_cur: Cursor,
     output: &mut OutputItems,
     feat: Features,
     renames: &Renames,
     name_map: &mut NameMap,
     native_cc: NativeCallConv,
-    _defer: &mut Defer,
-) -> Result<(), String>
-where Defer: FnMut(Cursor)
-{
+) -> Result<(), String> {
     use clang::TypeKind as TK;
 
     debug!("process_enum_decl({}, ..)", decl_cur);
@@ -463,12 +519,12 @@ where Defer: FnMut(Cursor)
         let val = var_cur.enum_constant_decl_value();
         if !var_values.contains_key(&val) {
             let sp = escape_ident(sp);
-            vars.push(format!("{} = {}", sp, val));
+            vars.push(format!("{}{} = {}", item_attrs(&var_cur), sp, val));
             var_names.push(sp.clone());
             var_values.insert(val, sp);
         } else {
             let real_var = var_values.get(&val).expect("existing enum variant");
-            dup_vars.push(format!(" pub const {0}: {1} = {1}::{2};", escape_ident(sp), name, real_var));
+            dup_vars.push(format!(" {0}pub const {1}: {2} = {2}::{3};", deprecated_attr(&var_cur), escape_ident(sp), name, real_var));
         }
     }
 
@@ -478,7 +534,8 @@ where Defer: FnMut(Cursor)
     }
 
     let decl = format!(
-        "{repr} pub enum {name} {{{vars}}} pub use self::{name}::{{{var_names}}};{dup_vars}",
+        "{doc}{repr} pub enum {name} {{{vars}}} pub use self::{name}::{{{var_names}}};{dup_vars}",
+        doc = item_attrs(&decl_cur),
         repr = base_ty.map(|t| format!("#[repr({})]", t)).unwrap_or_else(|| "#[repr(C)]".into()),
         name = escape_ident(name.clone()),
         vars = vars.join(", "),
@@ -496,6 +553,7 @@ Process a single function declaration.
 */
 fn process_function_decl(
     decl_cur: Cursor,
+    gen_config: &GenConfig,
     output: &mut OutputItems,
     feat: Features,
     renames: &Renames,
@@ -511,8 +569,7 @@ fn process_function_decl(
     // Is this an inline function?
     let children = decl_cur.children();
     if children.len() > 0 && children.last().unwrap().kind() == CursorKind::CompoundStmt {
-        // Err... *might be*
-        return Err("inline-fn".into());
+        return super::trans_inline::process_inline_fn(decl_cur, output, feat, renames, name_map, native_cc);
     }
 
     let ty = decl_cur.type_();
@@ -534,12 +591,18 @@ fn process_function_decl(
         format!(" -> {}", try!(trans_type(ty.result(), renames, native_cc)))
     };
 
-    let mut args: Vec<_> = try!(decl_cur.children().into_iter()
+    let params: Vec<_> = decl_cur.children().into_iter()
         .filter(|cur| cur.kind() == CK::ParmDecl)
-        .map(|cur| -> Result<_, String> {
+        .collect();
+
+    let sal = function_param_sal(&decl_cur, &params);
+    let const_inputs = gen_config.use_sal_const_inputs(&name);
+
+    let mut args: Vec<_> = try!(params.iter().zip(sal.iter())
+        .map(|(cur, sal)| -> Result<_, String> {
             let name = escape_ident(cur.spelling());
             let name = if name != "" { name } else { String::from("_") };
-            let ty = try!(trans_type(cur.type_(), renames, native_cc));
+            let ty = try!(trans_param_type(cur, sal, const_inputs, renames, name_map, native_cc));
             Ok(format!("{}: {}", name, ty))
         })
         .collect());
@@ -551,7 +614,9 @@ fn process_function_decl(
     let args = args.join(", ");
 
     let decl = format!(
-        r#"pub fn {name}({args}){res_ty};"#,
+        r#"{doc}{deprecated}pub fn {name}({args}){res_ty};"#,
+        doc = doc_attr_with_params(&decl_cur, &params, &sal),
+        deprecated = deprecated_attr(&decl_cur),
         name = escape_ident(name.clone()),
         args = args,
         res_ty = res_ty,
@@ -563,8 +628,142 @@ fn process_function_decl(
     Ok(())
 }
 
+/**
+Translates the type of a function parameter, taking its SAL annotations into account.
+
+Function pointers are normally translated as `Option<_>`, since they can be null.  A parameter annotated as a mandatory input, however, cannot be.
+
+If `const_inputs` is set, pointers to data annotated as inputs (and *not* outputs) are translated as `*const`, even if the C type lacks a `const`.  Where a pointer typedef has a `const` counterpart (`LPSTR` and `LPCSTR`), the counterpart is used.  Opaque handles (`HANDLE`, `HWND`, *etc.*) are left alone.
+*/
+fn trans_param_type(
+    param_cur: &Cursor,
+    sal: &ParamSal,
+    const_inputs: bool,
+    renames: &Renames,
+    name_map: &NameMap,
+    native_cc: NativeCallConv
+) -> Result<String, String> {
+    use clang::TypeKind as TK;
+
+    let ty = param_cur.type_();
+    let canon_ty = ty.canonical();
+    let is_fn_ptr = canon_ty.kind() == TK::Pointer
+        && match canon_ty.pointee().kind() {
+            TK::FunctionProto | TK::FunctionNoProto => true,
+            _ => false
+        };
+    let is_input_only = sal.direction() == Some(Direction::In)
+        && sal.0.iter().all(|a| a.direction().map(|d| d == Direction::In).unwrap_or(true));
+    let is_required = is_input_only && !sal.is_optional();
+
+    if const_inputs && is_input_only && !is_fn_ptr {
+        if let Some(ptr_ty) = desugar_to_pointer(ty.clone()) {
+            let pointee_ty = ptr_ty.pointee();
+            if !pointee_ty.is_const_qualified() && !is_opaque_pointee(&pointee_ty) {
+                if let Some(const_decl) = const_pointer_typedef(&ty, &pointee_ty, name_map) {
+                    debug!("trans_param_type: treating {} as {} ({})", param_cur, const_decl.spelling(), sal);
+                    return Ok(format!("{}{}", mod_qual(&const_decl), escape_ident(const_decl.spelling())));
+                }
+                debug!("trans_param_type: treating {} as *const ({})", param_cur, sal);
+                return Ok(format!("*const {}", try!(trans_type(pointee_ty, renames, native_cc))));
+            }
+        }
+    }
+
+    let rust_ty = try!(trans_type(ty, renames, native_cc));
+
+    if is_fn_ptr && is_required && rust_ty.starts_with("Option<") && rust_ty.ends_with(">") {
+        debug!("trans_param_type: {} is a required function pointer ({})", param_cur, sal);
+        Ok(rust_ty["Option<".len()..rust_ty.len()-1].into())
+    } else {
+        Ok(rust_ty)
+    }
+}
+
+/**
+Returns `true` if a pointer to the given type is an opaque handle (`HANDLE`, `HWND`, *etc.*) rather than a pointer to data.
+
+Handles are either `void *`, or pointers to the dummy `__` structures `DECLARE_HANDLE` defines (or to structures which aren't defined at all).
+*/
+fn is_opaque_pointee(pointee_ty: &clang::Type) -> bool {
+    use clang::TypeKind as TK;
+
+    let canon_ty = pointee_ty.canonical();
+    match canon_ty.kind() {
+        TK::Void => true,
+        TK::Record => {
+            let decl_cur = canon_ty.declaration();
+            decl_cur.definition().is_none() || decl_cur.spelling().ends_with("__")
+        },
+        _ => false
+    }
+}
+
+/**
+Finds the `const` counterpart of a pointer typedef, following the Win32 naming convention (`LPSTR` and `LPCSTR`, `PWSTR` and `PCWSTR`, *etc.*).
+
+The counterpart must point to the same type, only `const`-qualified.
+*/
+fn const_pointer_typedef(ty: &clang::Type, pointee_ty: &clang::Type, name_map: &NameMap) -> Option<Cursor> {
+    use clang::CursorKind as CK;
+    use clang::TypeKind as TK;
+
+    let ty = match ty.kind() {
+        TK::Elaborated => ty.named(),
+        _ => ty.clone()
+    };
+    if ty.kind() != TK::Typedef {
+        return None;
+    }
+
+    let name = ty.declaration().spelling();
+    let const_name = if name.starts_with("LP") {
+        format!("LPC{}", &name[2..])
+    } else if name.starts_with("P") {
+        format!("PC{}", &name[1..])
+    } else {
+        return None;
+    };
+
+    let const_decl = match name_map.get(&const_name) {
+        Some(decl_cur) if decl_cur.kind() == CK::TypedefDecl => decl_cur.clone(),
+        _ => return None
+    };
+
+    let want = format!("const {}", pointee_ty.canonical().spelling());
+    match desugar_to_pointer(const_decl.typedef_decl_underlying_type()) {
+        Some(ptr_ty) if ptr_ty.pointee().canonical().spelling() == want => Some(const_decl),
+        _ => None
+    }
+}
+
+/**
+Strips typedefs from a type until a pointer type is reached.
+
+Unlike using the canonical type, this keeps the pointee's typedefs (*e.g.* `LPWSTR` becomes `WCHAR *`, not `unsigned short *`).
+*/
+fn desugar_to_pointer(ty: clang::Type) -> Option<clang::Type> {
+    use clang::TypeKind as TK;
+
+    let mut ty = ty;
+    loop {
+        match ty.kind() {
+            TK::Pointer => return Some(ty),
+            TK::Typedef => ty = ty.declaration().typedef_decl_underlying_type(),
+            TK::Elaborated => ty = ty.named(),
+            _ => return None
+        }
+    }
+}
+
 /**
 Process a single variable declaration.
+
+What a variable becomes depends on its linkage:
+
+- Data with external linkage and no initialiser is imported from a library, and becomes an `extern` static.  If it's `dllimport`ed, the extern block is linked to the library so that Rust goes through the import table.
+- A `const` with an initialiser (typically a header-local `static const`) becomes a Rust `const` with the same value.
+- Anything else (mutable definitions, internal declarations with no value, *etc.*) has nothing to link against and no value to copy, so it's skipped.
 */
 fn process_var_decl(
     decl_cur: Cursor,
@@ -574,29 +773,113 @@ fn process_var_decl(
     name_map: &mut NameMap,
     native_cc: NativeCallConv
 ) -> Result<(), String> {
+    use clang::{Linkage, StorageClass};
+
     debug!("process_var_decl({}, _)", decl_cur);
 
     let name = decl_cur.spelling();
+    let linkage = decl_cur.linkage();
+    let storage = decl_cur.storage_class();
+    let init = decl_cur.var_decl_initializer();
+    let ty = decl_cur.type_();
+    let is_const = ty.is_const_qualified();
 
-    // TODO: Check linkage.
+    debug!(".. linkage: {:?}, storage: {:?}, init: {}", linkage, storage, init.is_some());
+
+    if let Some(init) = init {
+        if !is_const {
+            info!("skipping mutable variable definition {} at {}", name, decl_cur.location().display_short());
+            return Ok(());
+        }
+        return process_const_var_decl(decl_cur, init, output, feat, renames, name_map, native_cc);
+    }
+
+    match (linkage, storage) {
+        (Linkage::External, StorageClass::Extern)
+        | (Linkage::External, StorageClass::None)
+        => (),
+        _ => {
+            info!("skipping variable {} with {:?} linkage and {:?} storage at {}",
+                name, linkage, storage, decl_cur.location().display_short());
+            return Ok(());
+        }
+    }
 
-    let ty = decl_cur.type_();
-    let is_mut = !ty.is_const_qualified();
     let tys = try!(trans_type(ty, renames, native_cc));
 
     let decl = format!(
-        "pub static {is_mut}{name}: {ty};",
-        is_mut = if is_mut { "mut " } else { "" },
+        "{doc}pub static {is_mut}{name}: {ty};",
+        doc = item_attrs(&decl_cur),
+        is_mut = if is_const { "" } else { "mut " },
         name = escape_ident(name.clone()),
         ty = tys,
     );
 
     let annot = decl_cur.location().display_short().to_string();
     try!(add_to_name_map_checked(name_map, name.clone(), decl_cur.clone()));
+    if decl_cur.is_dllimport() {
+        output.add_dllimport_var(name.clone());
+    }
     output.add_var_item(name, feat, decl, annot);
     Ok(())
 }
 
+/**
+Process a constant variable definition, translating it as a Rust `const` with the same value.
+*/
+fn process_const_var_decl(
+    decl_cur: Cursor,
+    init_cur: Cursor,
+    output: &mut OutputItems,
+    feat: Features,
+    renames: &Renames,
+    name_map: &mut NameMap,
+    native_cc: NativeCallConv
+) -> Result<(), String> {
+    use clang::EvalResult;
+    use clang::TypeKind as TK;
+
+    debug!("process_const_var_decl({}, {}, _)", decl_cur, init_cur);
+
+    let name = decl_cur.spelling();
+    let header = file_stem(&decl_cur);
+    let annot = decl_cur.location().display_short().to_string();
+
+    // Arrays would need a value type, not the pointer `trans_type` gives them.
+    let ty = decl_cur.type_();
+    match ty.canonical().kind() {
+        TK::ConstantArray | TK::IncompleteArray => return Err(format!("const-array {}", name)),
+        _ => ()
+    }
+
+    // Where libclang can evaluate an arithmetic constant itself, use that: it doesn't depend on how much of the initialiser we can translate.
+    let evaluated = match ty.canonical().kind() {
+        TK::Bool => None,
+        kind if (kind as u32) >= (TK::FirstBuiltin as u32) && (kind as u32) <= (TK::LastBuiltin as u32) => decl_cur.evaluate(),
+        _ => None
+    };
+
+    let tys = try!(trans_type(ty, renames, native_cc));
+    let value = match evaluated {
+        Some(EvalResult::Int(v)) => v.to_string(),
+        Some(EvalResult::UInt(v)) => v.to_string(),
+        Some(EvalResult::Float(v)) if v.is_finite() => format!("{:?}", v),
+        _ => try!(super::trans_inline::trans_const_init(&init_cur, renames, name_map, native_cc))
+    };
+
+    let decl = format!(
+        "{doc}pub const {name}: {ty} = {value};",
+        doc = item_attrs(&decl_cur),
+        name = escape_ident(name.clone()),
+        ty = tys,
+        value = value,
+    );
+
+    try!(add_to_name_map_checked(name_map, name.clone(), decl_cur.clone()));
+    output.add_header_item(name, header, feat, decl, annot);
+    Ok(())
+}
+
 /**
 Process a single structure declaration.
 */
@@ -616,9 +899,10 @@ fn process_typedef_decl(
 
     let ty = decl_cur.typedef_decl_underlying_type();
 
-    // Resolve unexposed types if possible.
+    // Resolve unexposed and elaborated types if possible.
     let ty = match ty.kind() {
         TK::Unexposed => ty.canonical(),
+        TK::Elaborated => ty.named(),
         _ => ty
     };
 
@@ -642,7 +926,7 @@ fn process_typedef_decl(
 
     let ty = try!(trans_type(ty, renames, native_cc));
 
-    let decl = format!("pub type {} = {};", escape_ident(name.clone()), ty);
+    let decl = format!("{}pub type {} = {};", item_attrs(&decl_cur), escape_ident(name.clone()), ty);
 
     let annot = decl_cur.location().display_short().to_string();
     try!(add_to_name_map_checked(name_map, name.clone(), decl_cur.clone()));
@@ -650,12 +934,19 @@ fn process_typedef_decl(
     Ok(())
 }
 
+/**
+Returns the attributes (documentation and deprecation) that every translated item should carry.
+*/
+fn item_attrs(cur: &Cursor) -> String {
+    format!("{}{}", doc_attr(cur), deprecated_attr(cur))
+}
+
 /**
 Translate a type into an equivalent Rust type reference.
 
 Note that this **is not** for translating type declarations; you cannot just pass a structure definition.
 */
-fn trans_type(ty: clang::Type, renames: &Renames, native_cc: NativeCallConv) -> Result<String, String> {
+pub fn trans_type(ty: clang::Type, renames: &Renames, native_cc: NativeCallConv) -> Result<String, String> {
     use clang::TypeKind as TK;
     debug!("trans_type({:?} {:?}, _)", ty.kind(), ty.spelling());
 
@@ -679,6 +970,8 @@ fn trans_type(ty: clang::Type, renames: &Renames, native_cc: NativeCallConv) ->
             }
         },
 
+        TK::Elaborated => trans_type(ty.named(), renames, native_cc),
+
         // Basic types.
         TK::Void => Ok("::libc::c_void".into()),
         TK::Char_U | TK::UChar => Ok("::libc::c_uchar".into()),
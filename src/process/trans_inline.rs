@@ -0,0 +1,497 @@
+use std::collections::HashSet;
+use NativeCallConv;
+use clang::{self, Cursor, CursorKind, EvalResult, TypeKind};
+use features::Features;
+
+use super::{NameMap, add_to_name_map_checked, escape_ident, file_stem, mod_qual};
+use super::deprecation::deprecated_attr;
+use super::output::OutputItems;
+use super::renames::Renames;
+use super::trans_decls::trans_type;
+
+/**
+Process a function declaration that has a body.
+
+The body is translated into an `unsafe fn` header item, provided it only uses constructs with an obvious Rust equivalent: arithmetic, field access, pointer dereferences, and calls to other translated functions.  Anything else is rejected with an `inline-fn-unsupported` error saying what and where.
+*/
+pub fn process_inline_fn(
+    decl_cur: Cursor,
+    output: &mut OutputItems,
+    feat: Features,
+    renames: &Renames,
+    name_map: &mut NameMap,
+    native_cc: NativeCallConv
+) -> Result<(), String> {
+    debug!("process_inline_fn({}, _)", decl_cur);
+
+    let ty = decl_cur.type_();
+    if ty.is_function_type_variadic() {
+        return Err("inline-fn-unsupported variadic".into());
+    }
+
+    let name = decl_cur.spelling();
+    let header = file_stem(&decl_cur);
+    let annot = decl_cur.location().display_short().to_string();
+
+    let (res_ty, args, body) = {
+        let mut trans = FnTrans {
+            renames: renames,
+            name_map: name_map,
+            native_cc: native_cc,
+            locals: HashSet::new(),
+        };
+
+        let res_ty = if ty.result().kind() == TypeKind::Void {
+            String::new()
+        } else {
+            format!(" -> {}", try!(trans.ty(ty.result())))
+        };
+
+        let children = decl_cur.children();
+
+        let mut args = vec![];
+        for cur in children.iter().filter(|cur| cur.kind() == CursorKind::ParmDecl) {
+            let arg_name = escape_ident(cur.spelling());
+            if arg_name == "" {
+                return Err("inline-fn-unsupported unnamed parameter".into());
+            }
+            args.push(format!("mut {}: {}", arg_name, try!(trans.ty(cur.type_()))));
+            trans.locals.insert(cur.spelling());
+        }
+
+        let body = try!(trans.stmt(children.last().expect("inline fn body")));
+        (res_ty, args, body)
+    };
+
+    let decl = format!(
+        "{deprecated}#[allow(unused_mut, unused_parens)] #[inline] pub unsafe fn {name}({args}){res_ty} {body}",
+        deprecated = deprecated_attr(&decl_cur),
+        name = escape_ident(name.clone()),
+        args = args.join(", "),
+        res_ty = res_ty,
+        body = body,
+    );
+
+    try!(add_to_name_map_checked(name_map, name.clone(), decl_cur.clone()));
+    output.add_header_item(name, header, feat, decl, annot);
+    Ok(())
+}
+
+/**
+Translates the initialiser of a constant into a Rust expression.
+
+This accepts the same expressions as inline function bodies, minus references to locals (there aren't any).
+*/
+pub fn trans_const_init(
+    init_cur: &Cursor,
+    renames: &Renames,
+    name_map: &NameMap,
+    native_cc: NativeCallConv
+) -> Result<String, String> {
+    let mut trans = FnTrans {
+        renames: renames,
+        name_map: name_map,
+        native_cc: native_cc,
+        locals: HashSet::new(),
+    };
+    trans.expr(init_cur)
+}
+
+fn unsupported(cur: &Cursor, what: &str) -> String {
+    format!("inline-fn-unsupported {} at {}", what, cur.location().display_short())
+}
+
+/**
+Returns the Rust primitive corresponding to a builtin C integer type, as laid out on Windows.
+*/
+fn int_prim(kind: TypeKind) -> Option<&'static str> {
+    use clang::TypeKind as TK;
+    match kind {
+        TK::Char_S | TK::SChar => Some("i8"),
+        TK::Char_U | TK::UChar | TK::Bool => Some("u8"),
+        TK::Short => Some("i16"),
+        TK::UShort | TK::WChar | TK::Char16 => Some("u16"),
+        TK::Int | TK::Long => Some("i32"),
+        TK::UInt | TK::ULong | TK::Char32 => Some("u32"),
+        TK::LongLong => Some("i64"),
+        TK::ULongLong => Some("u64"),
+        _ => None
+    }
+}
+
+/**
+Strips the type suffix (`u`, `L`, `i64`, `ui64`, *etc.*) from an integer literal.
+*/
+fn strip_int_suffix(tok: &str) -> &str {
+    let lower = tok.to_lowercase();
+    for suffix in &["ui64", "ui32", "ui16", "ui8", "i64", "i32", "i16", "i8"] {
+        if lower.ends_with(suffix) {
+            return &tok[..tok.len() - suffix.len()];
+        }
+    }
+    tok.trim_right_matches(|c| match c { 'u' | 'U' | 'l' | 'L' => true, _ => false })
+}
+
+/**
+Strips any parentheses and implicit conversions from around an expression.
+*/
+fn strip_expr(cur: &Cursor) -> Cursor {
+    match cur.kind() {
+        CursorKind::ParenExpr | CursorKind::UnexposedExpr => {
+            let mut children = cur.children();
+            if children.len() == 1 {
+                strip_expr(&children.pop().unwrap())
+            } else {
+                cur.clone()
+            }
+        },
+        _ => cur.clone()
+    }
+}
+
+struct FnTrans<'a> {
+    renames: &'a Renames,
+    name_map: &'a NameMap,
+    native_cc: NativeCallConv,
+    /// Names of parameters and local variables.
+    locals: HashSet<String>,
+}
+
+impl<'a> FnTrans<'a> {
+    fn ty(&self, ty: clang::Type) -> Result<String, String> {
+        trans_type(ty, self.renames, self.native_cc)
+    }
+
+    fn block(&mut self, cur: &Cursor) -> Result<String, String> {
+        let stmt = try!(self.stmt(cur));
+        if cur.kind() == CursorKind::CompoundStmt {
+            Ok(stmt)
+        } else {
+            Ok(format!("{{ {} }}", stmt))
+        }
+    }
+
+    fn stmt(&mut self, cur: &Cursor) -> Result<String, String> {
+        use clang::CursorKind as CK;
+
+        let children = cur.children();
+        match cur.kind() {
+            CK::CompoundStmt => {
+                let mut stmts = vec![];
+                for child in &children {
+                    stmts.push(try!(self.stmt(child)));
+                }
+                Ok(format!("{{ {} }}", stmts.join(" ")))
+            },
+            CK::ReturnStmt => match children.first() {
+                Some(expr) => Ok(format!("return {};", try!(self.expr(expr)))),
+                None => Ok("return;".into())
+            },
+            CK::DeclStmt => {
+                let mut stmts = vec![];
+                for var in &children {
+                    if var.kind() != CK::VarDecl {
+                        return Err(unsupported(var, "local declaration"));
+                    }
+                    let init = match var.children().into_iter().filter(|c| c.kind().is_expression()).last() {
+                        Some(init) => try!(self.expr(&init)),
+                        None => "::core::mem::zeroed()".into()
+                    };
+                    stmts.push(format!("let mut {}: {} = {};",
+                        escape_ident(var.spelling()), try!(self.ty(var.type_())), init));
+                    self.locals.insert(var.spelling());
+                }
+                Ok(stmts.join(" "))
+            },
+            CK::IfStmt => {
+                let cond = try!(self.cond(&children[0]));
+                let then_stmt = try!(self.block(&children[1]));
+                match children.get(2) {
+                    Some(else_stmt) => Ok(format!("if {} {} else {}", cond, then_stmt, try!(self.block(else_stmt)))),
+                    None => Ok(format!("if {} {}", cond, then_stmt))
+                }
+            },
+            CK::WhileStmt => {
+                let cond = try!(self.cond(&children[0]));
+                Ok(format!("while {} {}", cond, try!(self.block(&children[1]))))
+            },
+            CK::BreakStmt => Ok("break;".into()),
+            CK::ContinueStmt => Ok("continue;".into()),
+            CK::NullStmt => Ok(String::new()),
+            kind if kind.is_expression() => Ok(format!("{};", try!(self.discarded_expr(cur)))),
+            kind => Err(unsupported(cur, &format!("{:?}", kind)))
+        }
+    }
+
+    /**
+    Translates an expression whose value is thrown away, such as an expression statement.
+
+    This is the only place assignments are allowed.
+    */
+    fn discarded_expr(&mut self, cur: &Cursor) -> Result<String, String> {
+        use clang::CursorKind as CK;
+        use clang::TypeKind as TK;
+
+        let op = match cur.kind() {
+            CK::BinaryOperator | CK::CompoundAssignOperator => cur.operator_spelling().unwrap_or(String::new()),
+            _ => return self.expr(cur)
+        };
+
+        let children = cur.children();
+        let (lhs, rhs) = (&children[0], &children[1]);
+        let lhs_kind = lhs.type_().canonical().kind();
+        match &*op {
+            "=" => Ok(format!("{} = {}", try!(self.expr(lhs)), try!(self.expr(rhs)))),
+            "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^=" | "<<=" | ">>=" => {
+                if lhs_kind == TK::Pointer {
+                    return Err(unsupported(cur, "pointer compound assignment"));
+                }
+                let rhs_expr = try!(self.expr(rhs));
+                let rhs_expr = match (int_prim(lhs_kind), int_prim(rhs.type_().canonical().kind())) {
+                    (Some(l), Some(r)) if l != r && op != "<<=" && op != ">>=" => {
+                        format!("({} as {})", rhs_expr, try!(self.ty(lhs.type_())))
+                    },
+                    _ => rhs_expr
+                };
+                Ok(format!("{} {} {}", try!(self.expr(lhs)), op, rhs_expr))
+            },
+            _ => self.expr(cur)
+        }
+    }
+
+    /**
+    Translates an expression used as a condition into a `bool` expression.
+    */
+    fn cond(&mut self, cur: &Cursor) -> Result<String, String> {
+        let inner = strip_expr(cur);
+        match inner.kind() {
+            CursorKind::BinaryOperator => match &*inner.operator_spelling().unwrap_or(String::new()) {
+                "==" | "!=" | "<" | "<=" | ">" | ">=" | "&&" | "||" => return self.bool_expr(&inner),
+                _ => ()
+            },
+            CursorKind::UnaryOperator if inner.operator_spelling().map(|op| op == "!").unwrap_or(false) => {
+                return self.bool_expr(&inner);
+            },
+            _ => ()
+        }
+
+        let expr = try!(self.expr(cur));
+        match cur.type_().canonical().kind() {
+            TypeKind::Pointer => Ok(format!("!({}).is_null()", expr)),
+            _ => Ok(format!("({} != 0)", expr))
+        }
+    }
+
+    /**
+    Translates a comparison or logical operator into a `bool` expression.
+    */
+    fn bool_expr(&mut self, cur: &Cursor) -> Result<String, String> {
+        let children = cur.children();
+        let op = cur.operator_spelling().unwrap_or(String::new());
+        match &*op {
+            "!" => Ok(format!("!{}", try!(self.cond(&children[0])))),
+            "&&" | "||" => Ok(format!("({} {} {})", try!(self.cond(&children[0])), op, try!(self.cond(&children[1])))),
+            _ => Ok(format!("({} {} {})", try!(self.expr(&children[0])), op, try!(self.expr(&children[1])))),
+        }
+    }
+
+    fn expr(&mut self, cur: &Cursor) -> Result<String, String> {
+        use clang::CursorKind as CK;
+        use clang::TypeKind as TK;
+
+        let children = cur.children();
+        match cur.kind() {
+            CK::ParenExpr => Ok(format!("({})", try!(self.expr(&children[0])))),
+
+            CK::IntegerLiteral => {
+                let prim = match int_prim(cur.type_().canonical().kind()) {
+                    Some(prim) => prim,
+                    None => return Err(unsupported(cur, "integer literal type"))
+                };
+
+                match cur.evaluate() {
+                    Some(EvalResult::Int(v)) => return Ok(format!("{}{}", v, prim)),
+                    Some(EvalResult::UInt(v)) => return Ok(format!("{}{}", v, prim)),
+                    _ => ()
+                }
+
+                // The literal might have come from a macro (*e.g.* `TRUE`), in which case the tokens are no use.
+                let tok = match cur.tokenize().get(0) {
+                    Some(tok) => tok.spelling(),
+                    None => return Err(unsupported(cur, "integer literal without tokens"))
+                };
+                if !tok.starts_with(|c: char| c.is_digit(10)) {
+                    return Err(unsupported(cur, &format!("integer literal spelled {:?}", tok)));
+                }
+                let digits = strip_int_suffix(&tok);
+                let digits = if digits.len() > 1 && digits.starts_with("0") && !digits.starts_with("0x") && !digits.starts_with("0X") {
+                    format!("0o{}", &digits[1..])
+                } else {
+                    digits.into()
+                };
+                Ok(format!("{}{}", digits, prim))
+            },
+
+            CK::CharacterLiteral => {
+                let tok = match cur.tokenize().get(0) {
+                    Some(tok) => tok.spelling(),
+                    None => return Err(unsupported(cur, "character literal without tokens"))
+                };
+                let prim = match int_prim(cur.type_().canonical().kind()) {
+                    Some(prim) => prim,
+                    None => return Err(unsupported(cur, "character literal type"))
+                };
+                match &*tok {
+                    "'\\0'" => Ok(format!("0{}", prim)),
+                    tok if tok.len() == 3 && tok.is_char_boundary(2) && tok[1..2].chars().all(|c| c != '\\' && (c as u32) < 0x80) => {
+                        Ok(format!("(b{} as {})", tok, prim))
+                    },
+                    _ => Err(unsupported(cur, "character literal"))
+                }
+            },
+
+            CK::DeclRefExpr => {
+                let decl = match cur.referenced() {
+                    Some(decl) => decl,
+                    None => return Err(unsupported(cur, "unresolved reference"))
+                };
+                let name = decl.spelling();
+                match decl.kind() {
+                    CK::ParmDecl | CK::VarDecl if self.locals.contains(&name) => Ok(escape_ident(name)),
+                    CK::EnumConstantDecl if decl.lexical_parent().map(|p| self.name_map.values().any(|c| *c == p)).unwrap_or(false) => {
+                        Ok(format!("({}{} as {})", mod_qual(&decl), escape_ident(name), try!(self.ty(cur.type_()))))
+                    },
+                    CK::FunctionDecl if self.name_map.contains_key(&name) => {
+                        Ok(format!("{}{}", mod_qual(&decl), escape_ident(name)))
+                    },
+                    CK::FunctionDecl => Err(unsupported(cur, &format!("call to untranslated function {:?}", name))),
+                    kind => Err(unsupported(cur, &format!("reference to {:?} {:?}", kind, name)))
+                }
+            },
+
+            CK::UnexposedExpr if children.len() == 1 => {
+                // Almost always an implicit conversion; make the integer and pointer ones explicit.
+                let inner = &children[0];
+                let expr = try!(self.expr(inner));
+                let (to, from) = (cur.type_().canonical(), inner.type_().canonical());
+                match (to.kind(), from.kind()) {
+                    (TK::Pointer, TK::ConstantArray) | (TK::Pointer, TK::IncompleteArray) => {
+                        Err(unsupported(cur, "array decay"))
+                    },
+                    (TK::Pointer, TK::FunctionProto) | (TK::Pointer, TK::FunctionNoProto) => Ok(expr),
+                    (to_kind, from_kind) if to_kind == from_kind && to_kind != TK::Pointer => Ok(expr),
+                    (TK::Pointer, TK::Pointer) if to.spelling() == from.spelling() => Ok(expr),
+                    (TK::Pointer, _) | (_, TK::Pointer) => Ok(format!("({} as {})", expr, try!(self.ty(cur.type_())))),
+                    (to_kind, from_kind) if int_prim(to_kind).is_some() && int_prim(from_kind).is_some() => {
+                        Ok(format!("({} as {})", expr, try!(self.ty(cur.type_()))))
+                    },
+                    _ => Ok(expr)
+                }
+            },
+
+            CK::CStyleCastExpr => {
+                let inner = match children.last() {
+                    Some(inner) => inner,
+                    None => return Err(unsupported(cur, "empty cast"))
+                };
+                if cur.type_().kind() == TK::Void {
+                    return self.expr(inner);
+                }
+                Ok(format!("({} as {})", try!(self.expr(inner)), try!(self.ty(cur.type_()))))
+            },
+
+            CK::UnaryOperator => {
+                let op = cur.operator_spelling().unwrap_or(String::new());
+                let operand = &children[0];
+                match &*op {
+                    "-" => Ok(format!("{}.wrapping_neg()", try!(self.expr(operand)))),
+                    "+" => self.expr(operand),
+                    "~" => Ok(format!("!{}", try!(self.expr(operand)))),
+                    "!" => Ok(format!("({} as {})", try!(self.bool_expr(cur)), try!(self.ty(cur.type_())))),
+                    "*" => Ok(format!("(*{})", try!(self.expr(operand)))),
+                    "&" => Ok(format!("(&mut {} as {})", try!(self.expr(operand)), try!(self.ty(cur.type_())))),
+                    op => Err(unsupported(cur, &format!("unary operator {:?}", op)))
+                }
+            },
+
+            CK::BinaryOperator | CK::CompoundAssignOperator => {
+                let op = cur.operator_spelling().unwrap_or(String::new());
+                let (lhs, rhs) = (&children[0], &children[1]);
+                let lhs_kind = lhs.type_().canonical().kind();
+                match &*op {
+                    "==" | "!=" | "<" | "<=" | ">" | ">=" | "&&" | "||" => {
+                        Ok(format!("({} as {})", try!(self.bool_expr(cur)), try!(self.ty(cur.type_()))))
+                    },
+                    "+" | "-" if lhs_kind == TK::Pointer => {
+                        if rhs.type_().canonical().kind() == TK::Pointer {
+                            return Err(unsupported(cur, "pointer difference"));
+                        }
+                        let neg = if op == "-" { "-" } else { "" };
+                        Ok(format!("{}.offset({}({} as isize))", try!(self.expr(lhs)), neg, try!(self.expr(rhs))))
+                    },
+                    "+" | "-" | "*" => {
+                        let method = match &*op { "+" => "wrapping_add", "-" => "wrapping_sub", _ => "wrapping_mul" };
+                        Ok(format!("{}.{}({})", try!(self.expr(lhs)), method, try!(self.expr(rhs))))
+                    },
+                    "/" | "%" | "&" | "|" | "^" | "<<" | ">>" => {
+                        Ok(format!("({} {} {})", try!(self.expr(lhs)), op, try!(self.expr(rhs))))
+                    },
+                    // In Rust, assignments have type `()`, so they can only be used where their value is thrown away.
+                    "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^=" | "<<=" | ">>=" => {
+                        Err(unsupported(cur, "assignment used as a value"))
+                    },
+                    op => Err(unsupported(cur, &format!("binary operator {:?}", op)))
+                }
+            },
+
+            CK::ConditionalOperator => {
+                Ok(format!("(if {} {{ {} }} else {{ {} }})",
+                    try!(self.cond(&children[0])),
+                    try!(self.expr(&children[1])),
+                    try!(self.expr(&children[2]))))
+            },
+
+            CK::MemberRefExpr => {
+                let field = match cur.referenced() {
+                    Some(field) => field,
+                    None => return Err(unsupported(cur, "unresolved member"))
+                };
+                if field.lexical_parent().map(|p| p.kind() == CK::UnionDecl).unwrap_or(false) {
+                    return Err(unsupported(cur, "union member access"));
+                }
+                let base = match children.first() {
+                    Some(base) => base,
+                    None => return Err(unsupported(cur, "implicit member base"))
+                };
+                let base_expr = try!(self.expr(base));
+                let field_name = escape_ident(field.spelling());
+                match base.type_().canonical().kind() {
+                    TK::Pointer => Ok(format!("(*{}).{}", base_expr, field_name)),
+                    _ => Ok(format!("{}.{}", base_expr, field_name))
+                }
+            },
+
+            CK::ArraySubscriptExpr => {
+                let (base, index) = (&children[0], &children[1]);
+                if base.type_().canonical().kind() != TK::Pointer {
+                    return Err(unsupported(cur, "subscript of non-pointer"));
+                }
+                Ok(format!("(*{}.offset({} as isize))", try!(self.expr(base)), try!(self.expr(index))))
+            },
+
+            CK::CallExpr => {
+                let mut args = vec![];
+                for arg in &children[1..] {
+                    args.push(try!(self.expr(arg)));
+                }
+                let callee = strip_expr(&children[0]);
+                if callee.kind() != CK::DeclRefExpr {
+                    return Err(unsupported(cur, "indirect call"));
+                }
+                Ok(format!("{}({})", try!(self.expr(&callee)), args.join(", ")))
+            },
+
+            kind => Err(unsupported(cur, &format!("{:?}", kind)))
+        }
+    }
+}
@@ -1,9 +1,12 @@
+use std::collections::HashMap;
 use itertools::Itertools;
+use {CharSet, ExpConfig, GenConfig};
 use clang::Cursor;
 use features::Features;
 use util::ResultOptionExt;
 
 use super::{EMIT_STUBS, NameMap, add_to_name_map_checked, escape_ident, file_stem, mod_qual};
+use super::const_groups::{GroupMap, add_const_to_groups};
 use super::output::OutputItems;
 
 /**
@@ -13,7 +16,11 @@ pub fn process_macro_defn(
     defn_cur: Cursor,
     output: &mut OutputItems,
     feat: Features,
+    gen_config: &GenConfig,
+    exp_config: &ExpConfig,
     name_map: &mut NameMap,
+    const_map: &mut ConstMap,
+    groups: &mut GroupMap,
 ) -> Result<(), String> {
     use ::ppmac::parse;
     use ::ppmac::parse::Result as PResult;
@@ -22,8 +29,6 @@ pub fn process_macro_defn(
 
     // Note: we skip the last token because it's just a newline.
     let toks = defn_cur.tokenize();
-    let first_tok = toks.at(0);
-    let next_tok = toks.get(1);
     let toks: Vec<_> = toks.into_iter().dropping(1).dropping_back(1).map(|t| t.spelling()).collect();
 
     // If it has no tokens... well, there's not much point.
@@ -34,11 +39,7 @@ pub fn process_macro_defn(
     let annot = defn_cur.location().display_short().to_string();
 
     // Work out whether this is a functionish macro or not.
-    let is_fn_macro = {
-        let first_col = first_tok.extent().expect("extent for macro first tok").end().column();
-        let next_col = next_tok.map(|t| t.extent().expect("extent for macro next tok").end().column()).unwrap_or(!0);
-        first_col + 1 == next_col
-    };
+    let is_fn_macro = defn_cur.is_macro_function_like();
 
     let (args, exp_toks) = if is_fn_macro {
         let args_end = toks.iter().take_while(|tok| *tok != ")").count();
@@ -80,6 +81,10 @@ pub fn process_macro_defn(
 
                 // We want to alias to the *original* thing, so that if someone aliases *us*, they know how to make it work.  It saves us from having to preserve this information in the name map itself.
                 try!(add_to_name_map_checked(name_map, name.clone(), decl_cur));
+                if let Some(value) = const_map.get(s).cloned() {
+                    add_const_to_groups(&name, &value, &header, defn_cur.location().line(), &feat, &annot, gen_config, groups, output);
+                    const_map.insert(name.clone(), value);
+                }
                 output.add_header_item(name, header, feat, decl, annot);
                 return Ok(())
             },
@@ -98,8 +103,25 @@ pub fn process_macro_defn(
         }
     }
 
+    // Check for an integer constant expression.
+    if let Some(value) = try!(eval_int(&exp_ast, name_map, const_map)) {
+        let value = match gen_config.int_macro_type(&name) {
+            Some(ty_name) => match try!(resolve_int_ty(ty_name, name_map)) {
+                Some(ty) => value.convert(ty),
+                None => return Err(format!("configured type {:?} is not an integer type", ty_name))
+            },
+            None => value
+        };
+        let decl = format!("pub const {}: {} = {}; /* {:?} */", escape_ident(name.clone()), value.ty.name, value.render(), exp_ast);
+        try!(add_to_name_map_checked(name_map, name.clone(), defn_cur.clone()));
+        add_const_to_groups(&name, &value, &header, defn_cur.location().line(), &feat, &annot, gen_config, groups, output);
+        const_map.insert(name.clone(), value);
+        output.add_header_item(name, header, feat, decl, annot);
+        return Ok(());
+    }
+
     // Check for an "inty" macro expression.
-    if let Some((v, t)) = try!(try_trans_inty_macro(&exp_ast, name_map)) {
+    if let Some((v, t)) = try!(try_trans_inty_macro(&exp_ast, gen_config, exp_config, name_map)) {
         let decl = format!("pub const {}: {} = {}; /* {:?} */", escape_ident(name.clone()), t, v, exp_ast);
         try!(add_to_name_map_checked(name_map, name.clone(), defn_cur.clone()));
         output.add_header_item(name, header, feat, decl, annot);
@@ -114,8 +136,8 @@ pub fn process_macro_defn(
     Err("unsupported-macro".into())
 }
 
-fn try_trans_inty_macro(node: &::ppmac::Node, name_map: &NameMap) -> Result<Option<(String, String)>, String> {
-    use ::ppmac::{Node, Signed, Size, UnaryOp};
+fn try_trans_inty_macro(node: &::ppmac::Node, gen_config: &GenConfig, exp_config: &ExpConfig, name_map: &NameMap) -> Result<Option<(String, String)>, String> {
+    use ::ppmac::{CharWidth, Node, UnaryOp};
     use self::try_trans_inty_macro as ttim;
 
     debug!("try_trans_inty_macro({:?})", node);
@@ -123,7 +145,16 @@ fn try_trans_inty_macro(node: &::ppmac::Node, name_map: &NameMap) -> Result<Opti
     match *node {
         Node::Call { ref subject, ref args } => match **subject {
             Node::Ident(ref s) => match (&**s, args.len()) {
-                ("TEXT", 1) => ttim(&args[0], name_map),
+                ("TEXT", 1) | ("__TEXT", 1) => match args[0] {
+                    Node::String(ref units, CharWidth::Narrow) => {
+                        let units = match gen_config.char_set {
+                            CharSet::Ansi => units.clone(),
+                            CharSet::Unicode => try!(widen_str(units)),
+                        };
+                        trans_str(&units, text_width(gen_config.char_set)).map(Some)
+                    },
+                    ref node => ttim(node, gen_config, exp_config, name_map)
+                },
                 (name, args) => {
                     let _decl = match name_map.get(s) {
                         Some(decl) => decl,
@@ -145,7 +176,7 @@ fn try_trans_inty_macro(node: &::ppmac::Node, name_map: &NameMap) -> Result<Opti
                     };
                     let ptr = if ptr { "*mut " } else { "" };
                     let ty = format!("{}{}{}", ptr, mod_qual(&ty_cur), name);
-                    ttim(value, name_map)
+                    ttim(value, gen_config, exp_config, name_map)
                         .ro_map(|(value, _)| (format!("{} as {}", value, ty), ty))
                 },
                 _ => Ok(None)
@@ -160,19 +191,489 @@ fn try_trans_inty_macro(node: &::ppmac::Node, name_map: &NameMap) -> Result<Opti
             Ok(None)
         },
         Node::Integer(v, signed, size) => {
-            Ok(Some(match (signed, size) {
-                (Signed::No, Size::Unknown) => (format!("0x{:x}u32", v), "u32".into()),
-                (Signed::No, Size::Long) => (format!("0x{:x}u64", v), "u64".into()),
-                (Signed::Yes, Size::Unknown) => (format!("0x{:x}i32", v as i64), "i32".into()),
-                (Signed::Yes, Size::Long) => (format!("0x{:x}i64", v as i64), "i64".into()),
-            }))
+            let value = IntConst::literal(v, signed, size);
+            Ok(Some((format!("{}{}", value.render(), value.ty.name), value.ty.name)))
+        },
+        Node::OffsetOf { ref ty, ref field } => match **ty {
+            Node::Type(ref name, false) => trans_offset_of(name, field, name_map).map(Some),
+            _ => {debug!("ttim: unsupported offsetof subject"); Ok(None)}
         },
-        Node::String(ref s, _) => Ok(Some((format!("\"{}\"", s), "&'static str".into()))),
-        Node::Unary(UnaryOp::Com, ref expr) => ttim(expr, name_map).ro_map(|(expr, ty)| (format!("!{}", expr), ty)),
-        Node::Unary(UnaryOp::Neg, ref expr) => ttim(expr, name_map).ro_map(|(expr, ty)| (format!("-{}", expr), ty)),
+        Node::SizeOf(ref operand) => match **operand {
+            Node::Type(ref name, ptr) => trans_size_of(name, ptr, exp_config, name_map).map(Some),
+            _ => {debug!("ttim: unsupported sizeof operand"); Ok(None)}
+        },
+        Node::String(ref units, width) => trans_str(units, width).map(Some),
+        Node::Unary(UnaryOp::Com, ref expr) => ttim(expr, gen_config, exp_config, name_map).ro_map(|(expr, ty)| (format!("!{}", expr), ty)),
+        Node::Unary(UnaryOp::Neg, ref expr) => ttim(expr, gen_config, exp_config, name_map).ro_map(|(expr, ty)| (format!("-{}", expr), ty)),
         ref node => {
             debug!("ttim: unsupported node: {:?}", node);
             Ok(None)
         }
     }
 }
+
+/**
+Maps the names of translated integer macros to their values.
+*/
+pub type ConstMap = HashMap<String, IntConst>;
+
+/**
+Function-like macros which exist only to cast their argument to a particular type.
+*/
+const INT_CAST_MACROS: &'static [(&'static str, &'static str)] = &[
+    ("_HRESULT_TYPEDEF_", "HRESULT"),
+    ("_NDIS_ERROR_TYPEDEF_", "NDIS_STATUS"),
+];
+
+/**
+An integer type, as far as constant evaluation is concerned.
+*/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IntTy {
+    /// How the type is spelled in the output.
+    pub name: String,
+    pub bits: u32,
+    pub signed: bool,
+}
+
+impl IntTy {
+    fn prim(bits: u32, signed: bool) -> IntTy {
+        IntTy {
+            name: format!("{}{}", if signed { "i" } else { "u" }, bits),
+            bits: bits,
+            signed: signed,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        if self.bits >= 64 { !0 } else { (1 << self.bits) - 1 }
+    }
+
+    /// Truncates a bit pattern to the width of this type.
+    fn wrap(&self, v: u64) -> u64 {
+        v & self.mask()
+    }
+
+    /// Interprets a (wrapped) bit pattern as a value of this type, widened to 64 bits.
+    fn extend(&self, v: u64) -> u64 {
+        let sign_bit = 1 << (self.bits - 1);
+        if self.signed && self.bits < 64 && v & sign_bit != 0 {
+            v | !self.mask()
+        } else {
+            v
+        }
+    }
+
+    /**
+    The type both operands of a binary operator are converted to.
+
+    This follows the usual arithmetic conversions, except that two operands of the *same* named type keep that name.  That way, or-ing two `DWORD` flags together gives you a `DWORD`.
+    */
+    fn common(&self, other: &IntTy) -> IntTy {
+        if self == other && self.bits >= 32 {
+            return self.clone();
+        }
+        let lhs = self.promote();
+        let rhs = other.promote();
+        if lhs.bits != rhs.bits {
+            if lhs.bits > rhs.bits { lhs } else { rhs }
+        } else {
+            IntTy::prim(lhs.bits, lhs.signed && rhs.signed)
+        }
+    }
+
+    /// Applies the integer promotions.
+    fn promote(&self) -> IntTy {
+        if self.bits < 32 { IntTy::prim(32, true) } else { self.clone() }
+    }
+}
+
+/**
+An integer constant, stored as the bit pattern of its type.
+*/
+#[derive(Clone, Debug)]
+pub struct IntConst {
+    pub value: u64,
+    pub ty: IntTy,
+}
+
+impl IntConst {
+    fn new(value: u64, ty: IntTy) -> IntConst {
+        IntConst {
+            value: ty.wrap(value),
+            ty: ty,
+        }
+    }
+
+    /**
+    Types a literal.
+
+    This follows MSVC: `long` is 32 bits, and an unsuffixed literal that doesn't fit in `int` becomes `unsigned long` before it becomes `long long`.
+    */
+    fn literal(v: u64, signed: ::ppmac::Signed, size: ::ppmac::Size) -> IntConst {
+        use ::ppmac::{Signed, Size};
+        let unsigned = signed == Signed::No;
+        let ty = match size {
+            Size::Unknown | Size::Long if unsigned && v <= 0xffff_ffff => IntTy::prim(32, false),
+            Size::Unknown | Size::Long if v <= 0x7fff_ffff => IntTy::prim(32, true),
+            Size::Unknown | Size::Long if v <= 0xffff_ffff => IntTy::prim(32, false),
+            _ if !unsigned && v <= 0x7fff_ffff_ffff_ffff => IntTy::prim(64, true),
+            _ => IntTy::prim(64, false),
+        };
+        IntConst::new(v, ty)
+    }
+
+    /// Converts this value to another integer type, as a C cast would.
+    pub fn convert(self, ty: IntTy) -> IntConst {
+        IntConst::new(self.ty.extend(self.value), ty)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.value == 0
+    }
+
+    fn is_negative(&self) -> bool {
+        self.ty.signed && (self.ty.extend(self.value) as i64) < 0
+    }
+
+    /**
+    Renders the value as a Rust literal, without a suffix.
+
+    Negative values are written as negated magnitudes, since Rust won't accept an out-of-range hex literal for a signed type.
+    */
+    pub fn render(&self) -> String {
+        if self.is_negative() {
+            format!("-0x{:x}", self.ty.extend(self.value).wrapping_neg())
+        } else {
+            format!("0x{:x}", self.value)
+        }
+    }
+}
+
+/**
+Works out the integer type named by a C type name, if it *is* an integer type.
+*/
+fn resolve_int_ty(name: &str, name_map: &NameMap) -> Result<Option<IntTy>, String> {
+    use clang::TypeKind as TK;
+
+    match name {
+        "char" => return Ok(Some(IntTy::prim(8, true))),
+        "short" => return Ok(Some(IntTy::prim(16, true))),
+        "int" | "long" => return Ok(Some(IntTy::prim(32, true))),
+        "unsigned" => return Ok(Some(IntTy::prim(32, false))),
+        "__int8" => return Ok(Some(IntTy::prim(8, true))),
+        "__int16" => return Ok(Some(IntTy::prim(16, true))),
+        "__int32" => return Ok(Some(IntTy::prim(32, true))),
+        "__int64" => return Ok(Some(IntTy::prim(64, true))),
+        _ => ()
+    }
+
+    let ty_cur = match name_map.get(name) {
+        Some(decl) => decl,
+        None => return Err(format!("forward-reference to name {:?}", name))
+    };
+
+    let (bits, signed) = match ty_cur.type_().canonical().kind() {
+        TK::Char_S | TK::SChar => (8, true),
+        TK::Char_U | TK::UChar => (8, false),
+        TK::Short => (16, true),
+        TK::UShort | TK::WChar | TK::Char16 => (16, false),
+        TK::Int | TK::Long => (32, true),
+        TK::UInt | TK::ULong | TK::Char32 => (32, false),
+        TK::LongLong => (64, true),
+        TK::ULongLong => (64, false),
+        _ => return Ok(None)
+    };
+
+    Ok(Some(IntTy {
+        name: format!("{}{}", mod_qual(ty_cur), escape_ident(name.into())),
+        bits: bits,
+        signed: signed,
+    }))
+}
+
+/**
+Tries to evaluate a macro expression as an integer constant.
+
+Returns `Ok(None)` if the expression isn't an integer constant expression we understand.
+*/
+fn eval_int(node: &::ppmac::Node, name_map: &NameMap, const_map: &ConstMap) -> Result<Option<IntConst>, String> {
+    use ::ppmac::{BinOp, Node, UnaryOp};
+
+    macro_rules! eval {
+        ($node:expr) => {
+            match try!(eval_int($node, name_map, const_map)) {
+                Some(v) => v,
+                None => return Ok(None)
+            }
+        };
+    }
+
+    match *node {
+        Node::Integer(v, signed, size) => Ok(Some(IntConst::literal(v, signed, size))),
+        Node::Ident(ref s) => Ok(const_map.get(s).cloned()),
+        Node::Cast { ref ty, ref value } => match **ty {
+            Node::Type(ref name, false) => match try!(resolve_int_ty(name, name_map)) {
+                Some(ty) => Ok(Some(eval!(value).convert(ty))),
+                None => Ok(None)
+            },
+            _ => Ok(None)
+        },
+        Node::Call { ref subject, ref args } => match (&**subject, args.len()) {
+            (&Node::Ident(ref s), 1) => match INT_CAST_MACROS.iter().find(|&&(m, _)| m == &s[..]) {
+                Some(&(_, ty_name)) => match try!(resolve_int_ty(ty_name, name_map)) {
+                    Some(ty) => Ok(Some(eval!(&args[0]).convert(ty))),
+                    None => Ok(None)
+                },
+                None => Ok(None)
+            },
+            _ => Ok(None)
+        },
+        Node::Unary(op, ref expr) => {
+            let v = eval!(expr);
+            let ty = v.ty.promote();
+            let v = v.convert(ty.clone());
+            let r = match op {
+                UnaryOp::Neg => v.value.wrapping_neg(),
+                UnaryOp::Com => !v.value,
+            };
+            Ok(Some(IntConst::new(r, ty)))
+        },
+        Node::Conditional { ref cond, ref then_expr, ref else_expr } => {
+            let cond = eval!(cond);
+            let then_v = eval!(then_expr);
+            let else_v = eval!(else_expr);
+            let ty = then_v.ty.common(&else_v.ty);
+            Ok(Some(if cond.is_zero() { else_v } else { then_v }.convert(ty)))
+        },
+        Node::Binary(op, ref lhs, ref rhs) => {
+            let lhs = eval!(lhs);
+            let rhs = eval!(rhs);
+
+            let bool_result = |b: bool| -> Result<Option<IntConst>, String> {
+                Ok(Some(IntConst::new(b as u64, IntTy::prim(32, true))))
+            };
+
+            match op {
+                BinOp::LogOr => return bool_result(!lhs.is_zero() || !rhs.is_zero()),
+                BinOp::LogAnd => return bool_result(!lhs.is_zero() && !rhs.is_zero()),
+                _ => ()
+            }
+
+            // Shifts take the type of their left operand; everything else uses the common type.
+            match op {
+                BinOp::Shl | BinOp::Shr => {
+                    let ty = lhs.ty.promote();
+                    let l = lhs.convert(ty.clone());
+                    let n = rhs.ty.extend(rhs.value);
+                    if n >= ty.bits as u64 {
+                        return Err(format!("shift by {} overflows {}", n as i64, ty.name));
+                    }
+                    let r = match op {
+                        BinOp::Shl => l.value << n,
+                        _ if ty.signed => ((ty.extend(l.value) as i64) >> n) as u64,
+                        _ => l.value >> n,
+                    };
+                    return Ok(Some(IntConst::new(r, ty)));
+                },
+                _ => ()
+            }
+
+            let ty = lhs.ty.common(&rhs.ty);
+            let (l, r) = (lhs.convert(ty.clone()), rhs.convert(ty.clone()));
+            let (lx, rx) = (ty.extend(l.value), ty.extend(r.value));
+
+            let v = match op {
+                BinOp::BitOr => lx | rx,
+                BinOp::BitXor => lx ^ rx,
+                BinOp::BitAnd => lx & rx,
+                BinOp::Add => lx.wrapping_add(rx),
+                BinOp::Sub => lx.wrapping_sub(rx),
+                BinOp::Mul => lx.wrapping_mul(rx),
+                BinOp::Div | BinOp::Rem if rx == 0 => return Err("division by zero".into()),
+                BinOp::Div if ty.signed => (lx as i64).wrapping_div(rx as i64) as u64,
+                BinOp::Div => lx / rx,
+                BinOp::Rem if ty.signed => (lx as i64).wrapping_rem(rx as i64) as u64,
+                BinOp::Rem => lx % rx,
+                BinOp::Eq => return bool_result(lx == rx),
+                BinOp::Ne => return bool_result(lx != rx),
+                BinOp::Lt => return bool_result(if ty.signed { (lx as i64) < (rx as i64) } else { lx < rx }),
+                BinOp::Le => return bool_result(if ty.signed { (lx as i64) <= (rx as i64) } else { lx <= rx }),
+                BinOp::Gt => return bool_result(if ty.signed { (lx as i64) > (rx as i64) } else { lx > rx }),
+                BinOp::Ge => return bool_result(if ty.signed { (lx as i64) >= (rx as i64) } else { lx >= rx }),
+                BinOp::LogOr | BinOp::LogAnd | BinOp::Shl | BinOp::Shr => unreachable!(),
+            };
+            Ok(Some(IntConst::new(v, ty)))
+        },
+        _ => Ok(None)
+    }
+}
+
+/**
+Works out what width of string `TEXT(_)` produces for a given character set.
+*/
+fn text_width(char_set: CharSet) -> ::ppmac::CharWidth {
+    use ::ppmac::CharWidth;
+    match char_set {
+        CharSet::Ansi => CharWidth::Narrow,
+        CharSet::Unicode => CharWidth::Wide,
+    }
+}
+
+/**
+Converts the code units of a narrow string into the code units of the equivalent wide string.
+
+This is what the pre-processor does when `TEXT("x")` becomes `L"x"`.
+*/
+fn widen_str(units: &[u32]) -> Result<Vec<u32>, String> {
+    let bytes: Vec<u8> = units.iter().map(|&cu| cu as u8).collect();
+    match String::from_utf8(bytes) {
+        Ok(s) => Ok(s.utf16_units().map(|cu| cu as u32).collect()),
+        Err(_) => Err("cannot widen non-UTF-8 narrow string".into())
+    }
+}
+
+/**
+Translates the code units of a string literal into a NUL-terminated Rust constant expression and its type.
+
+Narrow strings become byte strings; wide strings become `u16` slices, since that's what the `W` APIs actually want.
+*/
+fn trans_str(units: &[u32], width: ::ppmac::CharWidth) -> Result<(String, String), String> {
+    use std::fmt::Write;
+    use ::ppmac::CharWidth;
+
+    match width {
+        CharWidth::Narrow => {
+            let mut s = String::from("b\"");
+            for &cu in units.iter().chain(Some(&0)) {
+                match cu {
+                    // `$` is escaped so that it can't be mistaken for an output substitution.
+                    0x22 | 0x24 | 0x5c => write!(s, "\\x{:02x}", cu).unwrap(),
+                    0x20...0x7e => s.push(cu as u8 as char),
+                    0x00...0xff => write!(s, "\\x{:02x}", cu).unwrap(),
+                    cu => return Err(format!("narrow string code unit out of range: 0x{:x}", cu))
+                }
+            }
+            s.push('"');
+            Ok((s, "&'static [u8]".into()))
+        },
+        CharWidth::Wide => {
+            let units = units.iter().chain(Some(&0))
+                .map(|&cu| if cu <= 0xffff {
+                    Ok(format!("0x{:04x}", cu))
+                } else {
+                    Err(format!("wide string code unit out of range: 0x{:x}", cu))
+                })
+                .collect::<Result<Vec<_>, _>>();
+            Ok((format!("&[{}]", try!(units).join(", ")), "&'static [u16]".into()))
+        },
+    }
+}
+
+/**
+Works out the size of a named C type for the current expansion.
+
+Pointers and builtin types are sized directly; Windows is LLP64 on every architecture we care about.  Anything else is laid out by clang.  If clang *can't* lay it out (*e.g.* it's incomplete at this point), we fall back to asking Rust about the translated type.
+*/
+fn trans_size_of(name: &str, ptr: bool, exp_config: &ExpConfig, name_map: &NameMap) -> Result<(String, String), String> {
+    let size = if ptr {
+        Some(exp_config.arch.pointer_size())
+    } else {
+        builtin_size_of(name)
+    };
+
+    if let Some(size) = size {
+        return Ok((format!("{}usize", size), "usize".into()));
+    }
+
+    let ty_cur = match name_map.get(name) {
+        Some(decl) => decl,
+        None => return Err(format!("forward-reference to name {:?}", name))
+    };
+
+    match ty_cur.type_().try_size_of() {
+        Ok(size) => Ok((format!("{}usize", size), "usize".into())),
+        Err(err) => {
+            debug!("ttim: could not lay out {:?}: {:?}", name, err);
+            let ty = format!("{}{}", mod_qual(&ty_cur), escape_ident(name.into()));
+            Ok((format!("::core::mem::size_of::<{}>()", ty), "usize".into()))
+        }
+    }
+}
+
+/**
+Works out the byte offset of a (possibly nested) field within a named record type.
+
+Unlike `sizeof`, there's nothing sensible to fall back on here, so failing to lay out the record is an error.
+*/
+fn trans_offset_of(name: &str, field: &str, name_map: &NameMap) -> Result<(String, String), String> {
+    let ty_cur = match name_map.get(name) {
+        Some(decl) => decl,
+        None => return Err(format!("forward-reference to name {:?}", name))
+    };
+
+    let parts: Vec<&str> = field.split('.').collect();
+    let mut ty = ty_cur.type_();
+    let mut bits = 0;
+    for (i, &part) in parts.iter().enumerate() {
+        bits += try!(ty.offset_of(part)
+            .map_err(|err| format!("could not get offset of {:?} in {:?}: {:?}", part, name, err)));
+
+        // Only intermediate fields need their types; `offset_of` has already dealt with the last one.
+        if i + 1 == parts.len() {
+            break;
+        }
+
+        ty = match find_field(&ty.canonical().declaration(), part) {
+            Some(cur) => cur.type_(),
+            None => return Err(format!("could not find field {:?} in {:?}", part, name))
+        };
+    }
+
+    if bits % 8 != 0 {
+        return Err(format!("offset of {:?} in {:?} is not byte-aligned", field, name));
+    }
+    Ok((format!("{}usize", bits / 8), "usize".into()))
+}
+
+/**
+Finds a field of a record by name, including fields of anonymous struct and union members.
+*/
+fn find_field(record_cur: &Cursor, name: &str) -> Option<Cursor> {
+    use clang::CursorKind as CK;
+    use clang::TypeKind as TK;
+
+    let children = record_cur.children();
+    if let Some(cur) = children.iter().find(|cur| cur.kind() == CK::FieldDecl && cur.spelling() == name) {
+        return Some(cur.clone());
+    }
+
+    // An unnamed record which is the type of a named field isn't an anonymous member.
+    let field_records: Vec<Cursor> = children.iter()
+        .filter(|cur| cur.kind() == CK::FieldDecl && cur.spelling() != "")
+        .map(|cur| cur.type_().canonical())
+        .filter(|ty| ty.kind() == TK::Record)
+        .map(|ty| ty.declaration())
+        .collect();
+
+    children.iter()
+        .filter(|cur| match cur.kind() {
+            CK::StructDecl | CK::UnionDecl => cur.is_anonymous() && !field_records.contains(cur),
+            _ => false
+        })
+        .filter_map(|cur| find_field(cur, name))
+        .next()
+}
+
+/**
+Returns the size of a builtin C type, as laid out on Windows.
+*/
+fn builtin_size_of(name: &str) -> Option<usize> {
+    match name {
+        "char" | "__int8" => Some(1),
+        "short" | "__int16" | "wchar_t" => Some(2),
+        "int" | "long" | "float" | "__int32" => Some(4),
+        "double" | "__int64" => Some(8),
+        _ => None
+    }
+}